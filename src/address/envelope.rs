@@ -3,6 +3,8 @@ use super::Address;
 use crate::message::header::{self, Headers};
 #[cfg(feature = "builder")]
 use crate::message::{Mailbox, Mailboxes};
+#[cfg(feature = "smtp-transport")]
+use crate::transport::smtp::extension::{MailParameter, RcptParameter};
 use crate::Error;
 
 /// Simple email envelope representation
@@ -21,6 +23,20 @@ pub struct Envelope {
     forward_path: Vec<Address>,
     /// The envelope sender address
     reverse_path: Option<Address>,
+    /// Extra `MAIL FROM` parameters
+    #[cfg(feature = "smtp-transport")]
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    mail_parameters: Vec<MailParameter>,
+    /// Extra `RCPT TO` parameters, by recipient
+    #[cfg(feature = "smtp-transport")]
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    rcpt_parameters: Vec<(Address, RcptParameter)>,
 }
 
 /// just like the default implementation to deserialize `Vec<Address>` but it
@@ -106,6 +122,10 @@ impl Envelope {
         Ok(Envelope {
             forward_path: to,
             reverse_path: from,
+            #[cfg(feature = "smtp-transport")]
+            mail_parameters: Vec::new(),
+            #[cfg(feature = "smtp-transport")]
+            rcpt_parameters: Vec::new(),
         })
     }
 
@@ -152,6 +172,81 @@ impl Envelope {
         self.reverse_path.as_ref()
     }
 
+    /// Adds a parameter to the `MAIL FROM` command, like the DSN
+    /// `RET` and `ENVID` parameters.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use lettre::address::{Address, Envelope};
+    /// # use lettre::transport::smtp::extension::{MailParameter, MailRetParameter};
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let sender = "from@email.com".parse::<Address>()?;
+    /// let recipients = vec!["to@email.com".parse::<Address>()?];
+    ///
+    /// let mut envelope = Envelope::new(Some(sender), recipients)?;
+    /// envelope.add_mail_parameter(MailParameter::Ret(MailRetParameter::Headers));
+    /// envelope.add_mail_parameter(MailParameter::EnvId("QQ314159".to_owned()));
+    /// assert_eq!(envelope.mail_parameters().len(), 2);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "smtp-transport")]
+    pub fn add_mail_parameter(&mut self, parameter: MailParameter) {
+        self.mail_parameters.push(parameter);
+    }
+
+    /// Adds a parameter to the `RCPT TO` command of the `to` recipient,
+    /// like the DSN `NOTIFY` and `ORCPT` parameters.
+    ///
+    /// Parameters for addresses which aren't recipients of the envelope
+    /// are never sent.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use lettre::address::{Address, Envelope};
+    /// # use lettre::transport::smtp::extension::{RcptNotifyParameter, RcptParameter};
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let sender = "from@email.com".parse::<Address>()?;
+    /// let to = "to@email.com".parse::<Address>()?;
+    /// let cc = "cc@email.com".parse::<Address>()?;
+    ///
+    /// let mut envelope = Envelope::new(Some(sender), vec![to.clone(), cc.clone()])?;
+    /// envelope.add_rcpt_parameter(
+    ///     &to,
+    ///     RcptParameter::Notify(vec![RcptNotifyParameter::Failure]),
+    /// );
+    /// envelope.add_rcpt_parameter(&cc, RcptParameter::Notify(vec![]));
+    /// assert_eq!(envelope.rcpt_parameters(&to).count(), 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "smtp-transport")]
+    pub fn add_rcpt_parameter(&mut self, to: &Address, parameter: RcptParameter) {
+        self.rcpt_parameters.push((to.clone(), parameter));
+    }
+
+    /// Gets the extra parameters of the `MAIL FROM` command.
+    #[cfg(feature = "smtp-transport")]
+    pub fn mail_parameters(&self) -> &[MailParameter] {
+        &self.mail_parameters
+    }
+
+    /// Gets the extra parameters of the `RCPT TO` command of the `to` recipient.
+    #[cfg(feature = "smtp-transport")]
+    pub fn rcpt_parameters<'a>(
+        &'a self,
+        to: &'a Address,
+    ) -> impl Iterator<Item = &'a RcptParameter> + 'a {
+        self.rcpt_parameters
+            .iter()
+            .filter(move |(address, _)| address == to)
+            .map(|(_, parameter)| parameter)
+    }
+
     #[cfg(feature = "smtp-transport")]
     /// Check if any of the addresses in the envelope contains non-ascii chars
     pub(crate) fn has_non_ascii_addresses(&self) -> bool {
@@ -160,6 +255,25 @@ impl Envelope {
             .chain(self.forward_path.iter())
             .any(|a| !a.is_ascii())
     }
+
+    #[cfg(feature = "mx-transport")]
+    /// Copy of the envelope for a subset of its recipients, which must not be empty
+    pub(crate) fn with_recipients(&self, to: Vec<Address>) -> Envelope {
+        debug_assert!(!to.is_empty());
+        Envelope {
+            forward_path: to,
+            ..self.clone()
+        }
+    }
+
+    #[cfg(feature = "smtp-transport")]
+    /// Check if the envelope has any DSN parameter
+    pub(crate) fn has_dsn_parameters(&self) -> bool {
+        self.mail_parameters.iter().any(MailParameter::is_dsn)
+            || self.rcpt_parameters.iter().any(|(address, parameter)| {
+                self.forward_path.contains(address) && parameter.is_dsn()
+            })
+    }
 }
 
 #[cfg(feature = "builder")]
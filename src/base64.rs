@@ -3,6 +3,7 @@ use ::base64::{
     DecodeError,
 };
 
+#[cfg(any(feature = "smtp-transport", feature = "dkim"))]
 pub(crate) fn encode<T: AsRef<[u8]>>(input: T) -> String {
     STANDARD.encode(input)
 }
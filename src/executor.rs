@@ -1,15 +1,13 @@
-use std::fmt::Debug;
-#[cfg(feature = "smtp-transport")]
-use std::future::Future;
 #[cfg(feature = "file-transport")]
 use std::io::Result as IoResult;
 #[cfg(feature = "file-transport")]
 use std::path::Path;
+use std::{fmt::Debug, future::Future, time::Duration};
 #[cfg(feature = "smtp-transport")]
-use std::time::Duration;
+use std::{io, net::SocketAddr};
 
 use async_trait::async_trait;
-#[cfg(all(feature = "smtp-transport", feature = "async-std1"))]
+#[cfg(feature = "async-std1")]
 use futures_util::future::BoxFuture;
 
 #[cfg(all(
@@ -21,7 +19,7 @@ use crate::transport::smtp::client::AsyncSmtpConnection;
     feature = "smtp-transport",
     any(feature = "tokio1", feature = "async-std1")
 ))]
-use crate::transport::smtp::client::Tls;
+use crate::transport::smtp::client::{SocketOptions, Tls};
 #[cfg(all(
     feature = "smtp-transport",
     any(feature = "tokio1", feature = "async-std1")
@@ -46,7 +44,6 @@ use crate::transport::smtp::Error;
 pub trait Executor: Debug + Send + Sync + 'static + private::Sealed {
     #[cfg(feature = "smtp-transport")]
     type Handle: SpawnHandle;
-    #[cfg(feature = "smtp-transport")]
     type Sleep: Future<Output = ()> + Send + 'static;
 
     #[doc(hidden)]
@@ -57,17 +54,20 @@ pub trait Executor: Debug + Send + Sync + 'static + private::Sealed {
         F::Output: Send + 'static;
 
     #[doc(hidden)]
-    #[cfg(feature = "smtp-transport")]
     fn sleep(duration: Duration) -> Self::Sleep;
 
+    #[doc(hidden)]
+    #[cfg(feature = "smtp-transport")]
+    async fn resolve(host: &str, port: u16) -> io::Result<Vec<SocketAddr>>;
+
     #[doc(hidden)]
     #[cfg(feature = "smtp-transport")]
     async fn connect(
-        hostname: &str,
-        port: u16,
+        addrs: &[SocketAddr],
         timeout: Option<Duration>,
         hello_name: &ClientId,
         tls: &Tls,
+        socket_options: &SocketOptions,
     ) -> Result<AsyncSmtpConnection, Error>;
 
     #[doc(hidden)]
@@ -106,7 +106,6 @@ pub struct Tokio1Executor;
 impl Executor for Tokio1Executor {
     #[cfg(feature = "smtp-transport")]
     type Handle = tokio1_crate::task::JoinHandle<()>;
-    #[cfg(feature = "smtp-transport")]
     type Sleep = tokio1_crate::time::Sleep;
 
     #[cfg(feature = "smtp-transport")]
@@ -118,18 +117,24 @@ impl Executor for Tokio1Executor {
         tokio1_crate::spawn(fut)
     }
 
-    #[cfg(feature = "smtp-transport")]
     fn sleep(duration: Duration) -> Self::Sleep {
         tokio1_crate::time::sleep(duration)
     }
 
+    #[cfg(feature = "smtp-transport")]
+    async fn resolve(host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        Ok(tokio1_crate::net::lookup_host((host, port))
+            .await?
+            .collect())
+    }
+
     #[cfg(feature = "smtp-transport")]
     async fn connect(
-        hostname: &str,
-        port: u16,
+        addrs: &[SocketAddr],
         timeout: Option<Duration>,
         hello_name: &ClientId,
         tls: &Tls,
+        socket_options: &SocketOptions,
     ) -> Result<AsyncSmtpConnection, Error> {
         #[allow(clippy::match_single_binding)]
         let tls_parameters = match tls {
@@ -138,12 +143,12 @@ impl Executor for Tokio1Executor {
             _ => None,
         };
         #[allow(unused_mut)]
-        let mut conn = AsyncSmtpConnection::connect_tokio1(
-            (hostname, port),
+        let mut conn = AsyncSmtpConnection::connect_tokio1_with_options(
+            addrs,
             timeout,
             hello_name,
             tls_parameters,
-            None,
+            socket_options,
         )
         .await?;
 
@@ -202,7 +207,6 @@ pub struct AsyncStd1Executor;
 impl Executor for AsyncStd1Executor {
     #[cfg(feature = "smtp-transport")]
     type Handle = async_std::task::JoinHandle<()>;
-    #[cfg(feature = "smtp-transport")]
     type Sleep = BoxFuture<'static, ()>;
 
     #[cfg(feature = "smtp-transport")]
@@ -214,19 +218,24 @@ impl Executor for AsyncStd1Executor {
         async_std::task::spawn(fut)
     }
 
-    #[cfg(feature = "smtp-transport")]
     fn sleep(duration: Duration) -> Self::Sleep {
         let fut = async_std::task::sleep(duration);
         Box::pin(fut)
     }
 
+    #[cfg(feature = "smtp-transport")]
+    async fn resolve(host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        let addrs = async_std::net::ToSocketAddrs::to_socket_addrs(&(host, port)).await?;
+        Ok(addrs.collect())
+    }
+
     #[cfg(feature = "smtp-transport")]
     async fn connect(
-        hostname: &str,
-        port: u16,
+        addrs: &[SocketAddr],
         timeout: Option<Duration>,
         hello_name: &ClientId,
         tls: &Tls,
+        socket_options: &SocketOptions,
     ) -> Result<AsyncSmtpConnection, Error> {
         #[allow(clippy::match_single_binding)]
         let tls_parameters = match tls {
@@ -235,11 +244,12 @@ impl Executor for AsyncStd1Executor {
             _ => None,
         };
         #[allow(unused_mut)]
-        let mut conn = AsyncSmtpConnection::connect_asyncstd1(
-            (hostname, port),
+        let mut conn = AsyncSmtpConnection::connect_asyncstd1_with_options(
+            addrs,
             timeout,
             hello_name,
             tls_parameters,
+            socket_options,
         )
         .await?;
 
@@ -29,6 +29,7 @@
 //! * **smtp-transport** 📫: Enable the SMTP transport
 //! * **pool** 📫: Connection pool for SMTP transport
 //! * **hostname** 📫: Try to use the actual system hostname for the SMTP `CLIENTID`
+//! * **mx-transport**: Enable the transport delivering directly to the mail exchangers of the recipients
 //!
 //! #### SMTP over TLS via the native-tls crate
 //!
@@ -73,6 +74,12 @@
 //! * **file-transport**: Enable the file transport (saves emails into an `.eml` file)
 //! * **file-transport-envelope**: Allow writing the envelope into a JSON file (additionally saves envelopes into a `.json` file)
 //!
+//! ### Queued transport
+//!
+//! _Keep the emails which could not be sent yet in a [`queue`] directory, and retry them later_
+//!
+//! * **queue-transport**: Enable the queued transport
+//!
 //! ### Async execution runtimes
 //!
 //! _Use [tokio] or [async-std] as an async execution runtime for sending emails_
@@ -99,6 +106,7 @@
 //! [`SMTP`]: crate::transport::smtp
 //! [`sendmail`]: crate::transport::sendmail
 //! [`file`]: crate::transport::file
+//! [`queue`]: crate::transport::queue
 //! [`ContentType`]: crate::message::header::ContentType
 //! [tokio]: https://docs.rs/tokio/1
 //! [async-std]: https://docs.rs/async-std/1
@@ -191,7 +199,7 @@ Make sure to apply the same to any of your crate dependencies that use the `lett
 }
 
 pub mod address;
-#[cfg(any(feature = "smtp-transport", feature = "dkim"))]
+#[cfg(any(feature = "smtp-transport", feature = "dkim", feature = "builder"))]
 mod base64;
 pub mod error;
 #[cfg(any(feature = "tokio1", feature = "async-std1"))]
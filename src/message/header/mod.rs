@@ -123,6 +123,25 @@ impl Headers {
         self.find_header_index(name).map(|i| self.headers.remove(i))
     }
 
+    /// Appends a raw header to `Headers`, even if a header with the
+    /// same name is already present.
+    ///
+    /// Used when parsing messages, which may legitimately repeat headers
+    /// like `Received`.
+    pub(crate) fn append_raw(&mut self, value: HeaderValue) {
+        self.headers.push(value);
+    }
+
+    /// Moves the headers matching `f` into a new `Headers`, preserving their order
+    pub(crate) fn split_off_raw<F>(&mut self, mut f: F) -> Headers
+    where
+        F: FnMut(&HeaderName) -> bool,
+    {
+        let (matching, rest) = self.headers.drain(..).partition(|value| f(&value.name));
+        self.headers = rest;
+        Headers { headers: matching }
+    }
+
     pub(crate) fn find_header(&self, name: &str) -> Option<&HeaderValue> {
         self.headers.iter().find(|value| name == value.name)
     }
@@ -4,12 +4,12 @@ use mime::Mime;
 
 use crate::message::{
     header::{self, ContentTransferEncoding, ContentType, Header, Headers},
-    EmailFormat, IntoBody,
+    parse, EmailFormat, IntoBody, ParseMessageError,
 };
 
 /// MIME part variants
 #[derive(Debug, Clone)]
-pub(super) enum Part {
+pub enum Part {
     /// Single part with content
     Single(SinglePart),
 
@@ -18,6 +18,14 @@ pub(super) enum Part {
 }
 
 impl Part {
+    /// Get the headers from the part
+    pub fn headers(&self) -> &Headers {
+        match self {
+            Part::Single(part) => part.headers(),
+            Part::Multi(part) => part.headers(),
+        }
+    }
+
     #[cfg(feature = "dkim")]
     pub(super) fn format_body(&self, out: &mut Vec<u8>) {
         match self {
@@ -124,6 +132,22 @@ impl SinglePart {
             .body(body)
     }
 
+    /// Parse a raw singlepart, as produced by [`SinglePart::formatted`]
+    ///
+    /// The body is kept encoded, use [`SinglePart::decoded_body`] to decode it.
+    pub fn parse(raw: &[u8]) -> Result<Self, ParseMessageError> {
+        let (headers, body) = parse::split_headers(raw)?;
+        // `formatted` terminates the body with a line break
+        Ok(Self::from_parsed(
+            headers,
+            parse::strip_line_break(body).to_vec(),
+        ))
+    }
+
+    pub(super) fn from_parsed(headers: Headers, body: Vec<u8>) -> Self {
+        Self { headers, body }
+    }
+
     /// Get the headers from singlepart
     #[inline]
     pub fn headers(&self) -> &Headers {
@@ -136,6 +160,11 @@ impl SinglePart {
         &self.body
     }
 
+    /// Get the body, decoded according to its `Content-Transfer-Encoding`
+    pub fn decoded_body(&self) -> Result<Vec<u8>, ParseMessageError> {
+        parse::decode_body(&self.headers, &self.body)
+    }
+
     /// Get message content formatted for sending
     pub fn formatted(&self) -> Vec<u8> {
         let mut out = Vec::new();
@@ -349,6 +378,18 @@ impl MultiPart {
             .singlepart(SinglePart::html(html))
     }
 
+    /// Parse a raw multipart, as produced by [`MultiPart::formatted`]
+    ///
+    /// Fails if the `Content-Type` isn't `multipart/*` or is missing the boundary.
+    pub fn parse(raw: &[u8]) -> Result<Self, ParseMessageError> {
+        let (headers, body) = parse::split_headers(raw)?;
+        parse::parse_multipart(headers, body)
+    }
+
+    pub(super) fn from_parsed(headers: Headers, parts: Vec<Part>) -> Self {
+        Self { headers, parts }
+    }
+
     /// Add single part to multipart
     pub fn singlepart(mut self, part: SinglePart) -> Self {
         self.parts.push(Part::Single(part));
@@ -372,6 +413,11 @@ impl MultiPart {
             .into()
     }
 
+    /// Get the parts of the multipart
+    pub fn parts(&self) -> &[Part] {
+        &self.parts
+    }
+
     /// Get the headers from the multipart
     pub fn headers(&self) -> &Headers {
         &self.headers
@@ -206,6 +206,7 @@ pub use body::{Body, IntoBody, MaybeString};
 pub use dkim::*;
 pub use mailbox::*;
 pub use mimebody::*;
+pub use parse::ParseMessageError;
 
 mod attachment;
 mod body;
@@ -214,6 +215,7 @@ pub mod dkim;
 pub mod header;
 mod mailbox;
 mod mimebody;
+mod parse;
 
 use crate::{
     address::Envelope,
@@ -498,6 +500,75 @@ impl Message {
         MessageBuilder::new()
     }
 
+    /// Parse a raw [RFC5322](https://tools.ietf.org/html/rfc5322) message
+    ///
+    /// The envelope is derived from the headers. Formatting the parsed message with
+    /// [`Message::formatted`] gives back the original message, provided it uses
+    /// `CRLF` line endings, like the ones generated by lettre.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// use lettre::message::{header, Message};
+    ///
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let raw = concat!(
+    ///     "From: NoBody <nobody@domain.tld>\r\n",
+    ///     "To: Hei <hei@domain.tld>\r\n",
+    ///     "Subject: =?utf-8?b?SGFwcHkgbmV3IHllYXIg8J+OiQ==?=\r\n",
+    ///     "\r\n",
+    ///     "Be happy!",
+    /// );
+    ///
+    /// let m = Message::parse(raw.as_bytes())?;
+    /// assert_eq!(
+    ///     m.headers().get::<header::Subject>(),
+    ///     Some(header::Subject::from(String::from("Happy new year 🎉")))
+    /// );
+    /// assert_eq!(m.formatted(), raw.as_bytes());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn parse(raw: &[u8]) -> Result<Message, ParseMessageError> {
+        let (headers, body) = parse::split_headers(raw)?;
+        let envelope = Envelope::try_from(&headers).map_err(ParseMessageError::Envelope)?;
+        Self::from_parsed(headers, body, envelope)
+    }
+
+    /// Parse a raw message, using the supplied envelope
+    ///
+    /// Useful for messages stored without their `Bcc` header, for example
+    /// the ones read with `FileTransport::read`.
+    pub fn parse_with_envelope(
+        raw: &[u8],
+        envelope: Envelope,
+    ) -> Result<Message, ParseMessageError> {
+        let (headers, body) = parse::split_headers(raw)?;
+        Self::from_parsed(headers, body, envelope)
+    }
+
+    fn from_parsed(
+        mut headers: Headers,
+        body: &[u8],
+        envelope: Envelope,
+    ) -> Result<Message, ParseMessageError> {
+        let body = if parse::multipart_mime(&headers).is_some() {
+            // The `Content-*` headers belong to the top-level multipart
+            let part_headers = headers.split_off_raw(|name| {
+                name.len() > "Content-".len()
+                    && name[.."Content-".len()].eq_ignore_ascii_case("Content-")
+            });
+            MessageBody::Mime(Part::Multi(parse::parse_multipart(part_headers, body)?))
+        } else {
+            MessageBody::Raw(body.to_vec())
+        };
+
+        Ok(Message {
+            headers,
+            body,
+            envelope,
+        })
+    }
+
     /// Get the headers from the Message
     pub fn headers(&self) -> &Headers {
         &self.headers
@@ -513,6 +584,28 @@ impl Message {
         &self.envelope
     }
 
+    /// Get the MIME body of the message
+    ///
+    /// Returns `None` for messages with a plain body.
+    pub fn mime_body(&self) -> Option<&Part> {
+        match &self.body {
+            MessageBody::Mime(p) => Some(p),
+            MessageBody::Raw(_) => None,
+        }
+    }
+
+    /// Get the body, decoded according to its `Content-Transfer-Encoding`
+    ///
+    /// Fails with [`ParseMessageError::Multipart`] if the body is a [`MultiPart`],
+    /// whose parts need to be decoded one by one.
+    pub fn decoded_body(&self) -> Result<Vec<u8>, ParseMessageError> {
+        match &self.body {
+            MessageBody::Mime(Part::Single(p)) => p.decoded_body(),
+            MessageBody::Mime(Part::Multi(_)) => Err(ParseMessageError::Multipart),
+            MessageBody::Raw(r) => parse::decode_body(&self.headers, r),
+        }
+    }
+
     /// Get message content formatted for SMTP
     pub fn formatted(&self) -> Vec<u8> {
         let mut out = Vec::new();
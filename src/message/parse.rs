@@ -0,0 +1,507 @@
+//! Parsing of raw [RFC5322](https://tools.ietf.org/html/rfc5322) messages
+
+use std::{
+    error::Error as StdError,
+    fmt::{self, Display, Formatter},
+};
+
+use mime::Mime;
+
+use crate::message::{
+    header::{ContentTransferEncoding, ContentType, HeaderName, HeaderValue, Headers},
+    MultiPart, Part, SinglePart,
+};
+
+/// An error occurred while parsing a raw message
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ParseMessageError {
+    /// A header line isn't valid UTF-8 or isn't a `name: value` pair
+    InvalidHeader,
+    /// A header name isn't valid
+    InvalidHeaderName(String),
+    /// The `Content-Type` of the part isn't `multipart/*`
+    NotMultipart,
+    /// The multipart `Content-Type` doesn't have a `boundary` parameter
+    MissingBoundary,
+    /// The body is multipart, and must be decoded part by part
+    Multipart,
+    /// The body can't be decoded using its `Content-Transfer-Encoding`
+    InvalidBody,
+    /// The envelope couldn't be derived from the headers
+    Envelope(crate::Error),
+}
+
+impl Display for ParseMessageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidHeader => f.write_str("invalid header line"),
+            Self::InvalidHeaderName(name) => write!(f, "invalid header name: {name}"),
+            Self::NotMultipart => f.write_str("content type isn't multipart"),
+            Self::MissingBoundary => f.write_str("missing multipart boundary"),
+            Self::Multipart => f.write_str("body is multipart"),
+            Self::InvalidBody => f.write_str("body doesn't match its content transfer encoding"),
+            Self::Envelope(e) => e.fmt(f),
+        }
+    }
+}
+
+impl StdError for ParseMessageError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Self::Envelope(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Splits `raw` into its parsed headers and its body
+///
+/// The headers are separated from the body by the first empty line.
+/// If there is no empty line the whole input is treated as headers.
+pub(super) fn split_headers(raw: &[u8]) -> Result<(Headers, &[u8]), ParseMessageError> {
+    let mut pos = 0;
+    while pos < raw.len() {
+        let line_end = find_line_end(raw, pos);
+        let line = &raw[pos..line_end];
+        if line == b"\r" || line.is_empty() {
+            let headers = parse_headers(&raw[..pos])?;
+            let body = raw.get(line_end + 1..).unwrap_or_default();
+            return Ok((headers, body));
+        }
+
+        pos = line_end + 1;
+    }
+
+    Ok((parse_headers(raw)?, &[]))
+}
+
+/// Parses a header block, unfolding lines and decoding RFC2047 encoded words
+fn parse_headers(raw: &[u8]) -> Result<Headers, ParseMessageError> {
+    let raw = std::str::from_utf8(raw).map_err(|_| ParseMessageError::InvalidHeader)?;
+
+    let mut headers = Headers::new();
+    let mut field: Option<(&str, String)> = None;
+    for line in raw.lines() {
+        if line.starts_with([' ', '\t']) {
+            // folded continuation of the previous field
+            let (_, value) = field.as_mut().ok_or(ParseMessageError::InvalidHeader)?;
+            value.push_str("\r\n");
+            value.push_str(line);
+            continue;
+        }
+
+        if let Some((name, value)) = field.take() {
+            headers.append_raw(header_value(name, value)?);
+        }
+
+        let (name, value) = line
+            .split_once(':')
+            .ok_or(ParseMessageError::InvalidHeader)?;
+        field = Some((name, value.trim_start_matches([' ', '\t']).to_owned()));
+    }
+
+    if let Some((name, value)) = field {
+        headers.append_raw(header_value(name, value)?);
+    }
+
+    Ok(headers)
+}
+
+fn header_value(name: &str, encoded_value: String) -> Result<HeaderValue, ParseMessageError> {
+    let name = HeaderName::new_from_ascii(name.to_owned())
+        .map_err(|_| ParseMessageError::InvalidHeaderName(name.to_owned()))?;
+    let raw_value = decode_encoded_words(&encoded_value.replace("\r\n", ""));
+
+    // The value is kept exactly as it was folded and encoded in the input,
+    // so that formatting the parsed message gives back the original headers
+    Ok(HeaderValue::dangerous_new_pre_encoded(
+        name,
+        raw_value,
+        encoded_value,
+    ))
+}
+
+/// Parses a MIME part, recursing into nested multiparts
+pub(super) fn parse_part(raw: &[u8]) -> Result<Part, ParseMessageError> {
+    let (headers, body) = split_headers(raw)?;
+    if multipart_mime(&headers).is_some() {
+        parse_multipart(headers, body).map(Part::Multi)
+    } else {
+        Ok(Part::Single(SinglePart::from_parsed(
+            headers,
+            body.to_vec(),
+        )))
+    }
+}
+
+/// Parses the body of a multipart, given its headers
+pub(super) fn parse_multipart(
+    headers: Headers,
+    body: &[u8],
+) -> Result<MultiPart, ParseMessageError> {
+    let mime = multipart_mime(&headers).ok_or(ParseMessageError::NotMultipart)?;
+    let boundary = mime
+        .get_param("boundary")
+        .ok_or(ParseMessageError::MissingBoundary)?;
+
+    let parts = split_multipart(body, boundary.as_str())
+        .into_iter()
+        .map(parse_part)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(MultiPart::from_parsed(headers, parts))
+}
+
+/// Returns the `Content-Type` if it is `multipart/*`
+pub(super) fn multipart_mime(headers: &Headers) -> Option<Mime> {
+    headers
+        .get::<ContentType>()
+        .map(|content_type| content_type.as_ref().clone())
+        .filter(|mime| mime.type_() == mime::MULTIPART)
+}
+
+/// Splits a multipart body into its parts, ignoring the preamble and epilogue
+///
+/// As per [RFC2046](https://tools.ietf.org/html/rfc2046#section-5.1.1)
+/// the line break preceding a delimiter belongs to the delimiter.
+fn split_multipart<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{boundary}");
+
+    let mut parts = Vec::new();
+    let mut part_start = None;
+    let mut pos = 0;
+    while pos < body.len() {
+        let line_end = find_line_end(body, pos);
+        let line = body[pos..line_end]
+            .strip_suffix(b"\r")
+            .unwrap_or(&body[pos..line_end]);
+
+        if let Some(rest) = line.strip_prefix(delimiter.as_bytes()) {
+            let closing = rest.starts_with(b"--");
+            if closing || rest.iter().all(|&b| b == b' ' || b == b'\t') {
+                if let Some(start) = part_start {
+                    parts.push(strip_line_break(&body[start..pos]));
+                }
+                if closing {
+                    return parts;
+                }
+
+                part_start = Some((line_end + 1).min(body.len()));
+            }
+        }
+
+        pos = line_end + 1;
+    }
+
+    // Be lenient with a missing closing delimiter
+    if let Some(start) = part_start {
+        parts.push(&body[start..]);
+    }
+    parts
+}
+
+/// Decodes `body` according to the `Content-Transfer-Encoding` in `headers`
+pub(super) fn decode_body(headers: &Headers, body: &[u8]) -> Result<Vec<u8>, ParseMessageError> {
+    let encoding = headers
+        .get::<ContentTransferEncoding>()
+        .unwrap_or(ContentTransferEncoding::SevenBit);
+    match encoding {
+        ContentTransferEncoding::SevenBit
+        | ContentTransferEncoding::EightBit
+        | ContentTransferEncoding::Binary => Ok(body.to_vec()),
+        ContentTransferEncoding::QuotedPrintable => {
+            quoted_printable::decode(body, quoted_printable::ParseMode::Robust)
+                .map_err(|_| ParseMessageError::InvalidBody)
+        }
+        ContentTransferEncoding::Base64 => {
+            let stripped = body
+                .iter()
+                .copied()
+                .filter(|b| !b.is_ascii_whitespace())
+                .collect::<Vec<u8>>();
+            crate::base64::decode(stripped).map_err(|_| ParseMessageError::InvalidBody)
+        }
+    }
+}
+
+/// Removes a single trailing line break
+pub(super) fn strip_line_break(s: &[u8]) -> &[u8] {
+    s.strip_suffix(b"\n")
+        .map(|s| s.strip_suffix(b"\r").unwrap_or(s))
+        .unwrap_or(s)
+}
+
+fn find_line_end(s: &[u8], from: usize) -> usize {
+    s[from..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map_or(s.len(), |i| from + i)
+}
+
+/// Decodes the [RFC2047](https://tools.ietf.org/html/rfc2047) encoded words in `value`
+///
+/// Whitespace between two adjacent encoded words is removed. Encoded words
+/// which can't be decoded are left untouched.
+fn decode_encoded_words(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    let mut previous_encoded = false;
+    while let Some(start) = rest.find("=?") {
+        let (before, candidate) = rest.split_at(start);
+        match decode_encoded_word(candidate) {
+            Some((decoded, consumed)) => {
+                if !(previous_encoded && before.chars().all(|c| c == ' ' || c == '\t')) {
+                    out.push_str(before);
+                }
+                out.push_str(&decoded);
+                rest = &candidate[consumed..];
+                previous_encoded = true;
+            }
+            None => {
+                out.push_str(before);
+                out.push_str("=?");
+                rest = &candidate[2..];
+                previous_encoded = false;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Decodes a single `=?charset?encoding?text?=` word at the start of `s`,
+/// returning the decoded text and the length of the encoded word
+fn decode_encoded_word(s: &str) -> Option<(String, usize)> {
+    let inner = s.strip_prefix("=?")?;
+    let (charset, inner) = inner.split_once('?')?;
+    let (encoding, inner) = inner.split_once('?')?;
+    let end = inner.find("?=")?;
+    let text = &inner[..end];
+    if text.contains([' ', '\t']) {
+        return None;
+    }
+
+    let bytes = match encoding {
+        "b" | "B" => crate::base64::decode(text).ok()?,
+        "q" | "Q" => decode_q(text)?,
+        _ => return None,
+    };
+
+    // Ignore the RFC2231 language suffix
+    let charset = charset.split('*').next().unwrap_or_default();
+    let decoded =
+        if charset.eq_ignore_ascii_case("utf-8") || charset.eq_ignore_ascii_case("us-ascii") {
+            String::from_utf8(bytes).ok()?
+        } else if charset.eq_ignore_ascii_case("iso-8859-1") {
+            bytes.into_iter().map(char::from).collect()
+        } else {
+            return None;
+        };
+
+    let consumed = s.len() - inner.len() + end + "?=".len();
+    Some((decoded, consumed))
+}
+
+/// Decodes the `Q` encoding, a variant of quoted-printable
+fn decode_q(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len());
+    let mut bytes = text.bytes();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'_' => out.push(b' '),
+            b'=' => {
+                let hex = [bytes.next()?, bytes.next()?];
+                let hex = std::str::from_utf8(&hex).ok()?;
+                out.push(u8::from_str_radix(hex, 16).ok()?);
+            }
+            b => out.push(b),
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime};
+
+    use pretty_assertions::assert_eq;
+
+    use super::decode_encoded_words;
+    use crate::message::{header, Attachment, Message, MultiPart, Part, SinglePart};
+
+    fn date() -> SystemTime {
+        // Tue, 15 Nov 1994 08:12:31 GMT
+        SystemTime::UNIX_EPOCH + Duration::from_secs(784887151)
+    }
+
+    #[test]
+    fn decode_words() {
+        assert_eq!(decode_encoded_words("Hello world"), "Hello world");
+        assert_eq!(
+            decode_encoded_words("=?utf-8?b?0Y/So9CwINC10Lsg0LHQtdC705nQvSE=?="),
+            "яңа ел белән!"
+        );
+        assert_eq!(
+            decode_encoded_words("=?utf-8?b?0Y/So9Cw?= =?utf-8?b?INC10Ls=?= end"),
+            "яңа ел end"
+        );
+        assert_eq!(
+            decode_encoded_words("=?ISO-8859-1?Q?Andr=E9_Pirard?= <pirard@example.org>"),
+            "André Pirard <pirard@example.org>"
+        );
+        assert_eq!(decode_encoded_words("a =?b ?= c"), "a =?b ?= c");
+    }
+
+    #[test]
+    fn parse_raw_body_round_trip() {
+        let email = Message::builder()
+            .date(date())
+            .from("Каи <kayo@example.com>".parse().unwrap())
+            .to("\"Pony O.P.\" <pony@domain.tld>".parse().unwrap())
+            .subject("яңа ел белән!")
+            .body(String::from("Happy new year!"))
+            .unwrap();
+        let formatted = email.formatted();
+
+        let parsed = Message::parse(&formatted).unwrap();
+        assert_eq!(parsed.formatted(), formatted);
+        assert_eq!(parsed.envelope(), email.envelope());
+        assert_eq!(
+            parsed.headers().get::<header::Subject>(),
+            Some(header::Subject::from(String::from("яңа ел белән!")))
+        );
+        assert_eq!(parsed.headers().get::<header::Date>(), Some(date().into()));
+        assert_eq!(parsed.decoded_body().unwrap(), b"Happy new year!");
+    }
+
+    #[test]
+    fn parse_multipart_round_trip() {
+        let email = Message::builder()
+            .date(date())
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .subject("Happy new year")
+            .multipart(
+                MultiPart::mixed()
+                    .boundary("0oVZ2r6AoLAhLlb0gPNSKy6BEqdS2IfwxrcbUuo1")
+                    .multipart(
+                        MultiPart::alternative()
+                            .boundary("EyXdAZIgZuyUjAounq4Aj44a6MpJfqCKhm6pE1zk")
+                            .singlepart(SinglePart::plain(String::from("Hello, world! :)")))
+                            .singlepart(SinglePart::html(String::from(
+                                "<p><b>Hello</b>, <i>world</i>!</p>",
+                            ))),
+                    )
+                    .singlepart(Attachment::new(String::from("example.bin")).body(
+                        vec![0, 159, 146, 150],
+                        "application/octet-stream".parse().unwrap(),
+                    )),
+            )
+            .unwrap();
+        let formatted = email.formatted();
+
+        let parsed = Message::parse(&formatted).unwrap();
+        assert_eq!(
+            String::from_utf8(parsed.formatted()).unwrap(),
+            String::from_utf8(formatted).unwrap()
+        );
+
+        let Some(Part::Multi(mixed)) = parsed.mime_body() else {
+            panic!("expected a multipart body");
+        };
+        assert_eq!(mixed.boundary(), "0oVZ2r6AoLAhLlb0gPNSKy6BEqdS2IfwxrcbUuo1");
+        assert_eq!(mixed.parts().len(), 2);
+
+        let Part::Multi(alternative) = &mixed.parts()[0] else {
+            panic!("expected a multipart/alternative part");
+        };
+        let Part::Single(plain) = &alternative.parts()[0] else {
+            panic!("expected a text/plain part");
+        };
+        assert_eq!(plain.decoded_body().unwrap(), b"Hello, world! :)");
+
+        let Part::Single(attachment) = &mixed.parts()[1] else {
+            panic!("expected an attachment part");
+        };
+        assert_eq!(
+            attachment.headers().get::<header::ContentDisposition>(),
+            Some(header::ContentDisposition::attachment("example.bin"))
+        );
+        assert_eq!(attachment.decoded_body().unwrap(), [0, 159, 146, 150]);
+    }
+
+    #[test]
+    fn parse_part_round_trip() {
+        let part = SinglePart::builder()
+            .header(header::ContentType::TEXT_PLAIN)
+            .header(header::ContentTransferEncoding::QuotedPrintable)
+            .body(String::from("Текст письма в уникоде"));
+
+        let parsed = SinglePart::parse(&part.formatted()).unwrap();
+        assert_eq!(parsed.formatted(), part.formatted());
+        assert_eq!(
+            parsed.decoded_body().unwrap(),
+            "Текст письма в уникоде".as_bytes()
+        );
+
+        let multipart = MultiPart::related()
+            .boundary("0oVZ2r6AoLAhLlb0gPNSKy6BEqdS2IfwxrcbUuo1")
+            .singlepart(part);
+        let parsed = MultiPart::parse(&multipart.formatted()).unwrap();
+        assert_eq!(parsed.formatted(), multipart.formatted());
+    }
+
+    #[test]
+    fn parse_lf_line_endings() {
+        let raw = concat!(
+            "From: NoBody <nobody@domain.tld>\n",
+            "To: Hei <hei@domain.tld>,\n",
+            "\tYuin <yuin@domain.tld>\n",
+            "Received: from a\n",
+            "Received: from b\n",
+            "Content-Type: multipart/mixed; boundary=b1\n",
+            "\n",
+            "preamble\n",
+            "--b1\n",
+            "\n",
+            "first\n",
+            "--b1\n",
+            "Content-Transfer-Encoding: base64\n",
+            "\n",
+            "c2Vjb25k\n",
+            "--b1--\n",
+            "epilogue\n",
+        );
+
+        let parsed = Message::parse(raw.as_bytes()).unwrap();
+        assert_eq!(parsed.envelope().to().len(), 2);
+        assert_eq!(
+            parsed.headers().get_raw("To"),
+            Some("Hei <hei@domain.tld>,\tYuin <yuin@domain.tld>")
+        );
+
+        let Some(Part::Multi(mixed)) = parsed.mime_body() else {
+            panic!("expected a multipart body");
+        };
+        let bodies = mixed
+            .parts()
+            .iter()
+            .map(|part| match part {
+                Part::Single(part) => part.decoded_body().unwrap(),
+                Part::Multi(_) => panic!("unexpected nested multipart"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(bodies, [b"first".to_vec(), b"second".to_vec()]);
+        assert!(String::from_utf8(parsed.formatted())
+            .unwrap()
+            .contains("Received: from a\r\nReceived: from b\r\n"));
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!(Message::parse(b"From nobody\r\n\r\nbody").is_err());
+        assert!(Message::parse(b"Subject: no recipients\r\n\r\nbody").is_err());
+        assert!(MultiPart::parse(b"Content-Type: text/plain\r\n\r\nbody").is_err());
+        assert!(MultiPart::parse(b"Content-Type: multipart/mixed\r\n\r\nbody").is_err());
+    }
+}
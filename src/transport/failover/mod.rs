@@ -0,0 +1,393 @@
+//! The failover transport spreads the emails over several backend transports,
+//! like multiple SMTP relays, and sends them through another backend when one
+//! is unavailable.
+//!
+//! Backends failing with an error meaning they are unavailable (for SMTP, failing to
+//! connect or losing the connection) are marked unhealthy, and the email is sent
+//! with the next backend. Other errors, like the server rejecting the email, are
+//! returned right away. Unhealthy backends are skipped, until a periodic probe with
+//! their `test_connection` method succeeds. When all the backends are unhealthy,
+//! they are all tried anyway.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! # #[cfg(all(feature = "smtp-transport", feature = "builder", any(feature = "native-tls", feature = "rustls-tls")))]
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use lettre::{
+//!     transport::failover::{FailoverTransport, Strategy},
+//!     Message, SmtpTransport, Transport,
+//! };
+//!
+//! // Send two thirds of the emails through the first relay
+//! let sender = FailoverTransport::builder()
+//!     .strategy(Strategy::RoundRobin)
+//!     .weighted_backend(SmtpTransport::relay("smtp1.example.com")?.build(), 2)
+//!     .backend(SmtpTransport::relay("smtp2.example.com")?.build())
+//!     .build();
+//!
+//! let email = Message::builder()
+//!     .from("NoBody <nobody@domain.tld>".parse()?)
+//!     .to("Hei <hei@domain.tld>".parse()?)
+//!     .subject("Happy new year")
+//!     .body(String::from("Be happy!"))?;
+//! sender.send(&email)?;
+//! # Ok(())
+//! # }
+//!
+//! # #[cfg(not(all(feature = "smtp-transport", feature = "builder", any(feature = "native-tls", feature = "rustls-tls"))))]
+//! # fn main() {}
+//! ```
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+#[cfg(any(feature = "tokio1", feature = "async-std1"))]
+use async_trait::async_trait;
+
+#[cfg(any(feature = "tokio1", feature = "async-std1"))]
+use crate::AsyncTransport;
+use crate::{address::Envelope, Transport};
+
+/// Default delay between two probes of an unhealthy backend
+const DEFAULT_RECOVERY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Transport whose availability can be checked
+pub trait HealthCheck: Transport {
+    /// Returns true if `error` means the transport is unavailable, and other
+    /// backends should be tried
+    fn is_unavailable(error: &Self::Error) -> bool;
+
+    /// Returns true if the transport is available again
+    fn test_connection(&self) -> bool;
+}
+
+/// Async transport whose availability can be checked
+#[cfg(any(feature = "tokio1", feature = "async-std1"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "tokio1", feature = "async-std1"))))]
+#[async_trait]
+pub trait AsyncHealthCheck: AsyncTransport {
+    /// Returns true if `error` means the transport is unavailable, and other
+    /// backends should be tried
+    fn is_unavailable(error: &Self::Error) -> bool;
+
+    /// Returns true if the transport is available again
+    async fn test_connection(&self) -> bool;
+}
+
+/// How emails are spread over the backends
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Strategy {
+    /// Use the first healthy backend, in the order they were added
+    Priority,
+    /// Use the backends in turn, each one in proportion to its weight
+    #[default]
+    RoundRobin,
+}
+
+#[derive(Debug)]
+struct State {
+    /// Current weights of the smooth weighted round-robin
+    current_weights: Vec<i64>,
+    /// When each unhealthy backend was last checked
+    unhealthy: Vec<Option<Instant>>,
+}
+
+/// What to do with a backend
+enum Check {
+    Send,
+    Probe,
+    Skip,
+}
+
+/// Sends emails through one of several backends, failing over to the next one
+/// when a backend is unavailable
+#[derive(Debug, Clone)]
+pub struct FailoverTransport<T> {
+    backends: Vec<T>,
+    weights: Vec<u32>,
+    strategy: Strategy,
+    recovery_interval: Duration,
+    state: Arc<Mutex<State>>,
+}
+
+/// Contains options for configuring a [`FailoverTransport`]
+#[derive(Debug, Clone)]
+pub struct FailoverTransportBuilder<T> {
+    backends: Vec<T>,
+    weights: Vec<u32>,
+    strategy: Strategy,
+    recovery_interval: Duration,
+}
+
+impl<T> FailoverTransport<T> {
+    /// Creates a new builder, without any backend
+    pub fn builder() -> FailoverTransportBuilder<T> {
+        FailoverTransportBuilder {
+            backends: Vec::new(),
+            weights: Vec::new(),
+            strategy: Strategy::default(),
+            recovery_interval: DEFAULT_RECOVERY_INTERVAL,
+        }
+    }
+
+    /// Returns the backends
+    pub fn backends(&self) -> &[T] {
+        &self.backends
+    }
+
+    /// Returns whether the backend at `index` is considered available,
+    /// or `None` if there is no such backend
+    pub fn is_healthy(&self, index: usize) -> Option<bool> {
+        self.state
+            .lock()
+            .unwrap()
+            .unhealthy
+            .get(index)
+            .map(Option::is_none)
+    }
+
+    /// Order in which the backends are tried for the next email
+    fn order(&self) -> Vec<usize> {
+        let count = self.backends.len();
+        let first = match self.strategy {
+            Strategy::Priority => 0,
+            // Smooth weighted round-robin
+            Strategy::RoundRobin => {
+                let mut state = self.state.lock().unwrap();
+                let total: i64 = self.weights.iter().map(|&weight| i64::from(weight)).sum();
+                for (current, &weight) in state.current_weights.iter_mut().zip(&self.weights) {
+                    *current += i64::from(weight);
+                }
+                // The first of the backends with the highest current weight
+                let first = state
+                    .current_weights
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .max_by_key(|(_, &current)| current)
+                    .map_or(0, |(index, _)| index);
+                state.current_weights[first] -= total;
+                first
+            }
+        };
+        (0..count).map(|i| (first + i) % count).collect()
+    }
+
+    fn check(&self, index: usize) -> Check {
+        let mut state = self.state.lock().unwrap();
+        match &mut state.unhealthy[index] {
+            None => Check::Send,
+            Some(checked) if checked.elapsed() >= self.recovery_interval => {
+                *checked = Instant::now();
+                Check::Probe
+            }
+            Some(_) => Check::Skip,
+        }
+    }
+
+    fn set_healthy(&self, index: usize, healthy: bool) {
+        let mut state = self.state.lock().unwrap();
+        let unhealthy = &mut state.unhealthy[index];
+        if healthy {
+            *unhealthy = None;
+        } else if unhealthy.is_none() {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(backend = index, "backend unavailable");
+            *unhealthy = Some(Instant::now());
+        }
+    }
+}
+
+impl<T> FailoverTransportBuilder<T> {
+    /// Add a backend, with a weight of 1
+    pub fn backend(self, backend: T) -> Self {
+        self.weighted_backend(backend, 1)
+    }
+
+    /// Add a backend, receiving a share of the emails proportional to `weight`
+    /// with [`Strategy::RoundRobin`]
+    pub fn weighted_backend(mut self, backend: T, weight: u32) -> Self {
+        self.backends.push(backend);
+        self.weights.push(weight);
+        self
+    }
+
+    /// Set how emails are spread over the backends
+    ///
+    /// Defaults to [`Strategy::RoundRobin`].
+    pub fn strategy(mut self, strategy: Strategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Set the delay between two probes of an unhealthy backend
+    ///
+    /// Defaults to 30 seconds.
+    pub fn recovery_interval(mut self, interval: Duration) -> Self {
+        self.recovery_interval = interval;
+        self
+    }
+
+    /// Build the transport
+    ///
+    /// # Panics
+    ///
+    /// Panics if no backend was added.
+    pub fn build(self) -> FailoverTransport<T> {
+        assert!(
+            !self.backends.is_empty(),
+            "a failover transport needs at least one backend"
+        );
+
+        let count = self.backends.len();
+        FailoverTransport {
+            backends: self.backends,
+            weights: self.weights,
+            strategy: self.strategy,
+            recovery_interval: self.recovery_interval,
+            state: Arc::new(Mutex::new(State {
+                current_weights: vec![0; count],
+                unhealthy: vec![None; count],
+            })),
+        }
+    }
+}
+
+impl<T> Transport for FailoverTransport<T>
+where
+    T: HealthCheck,
+{
+    type Ok = T::Ok;
+    type Error = T::Error;
+
+    fn send_raw(&self, envelope: &Envelope, email: &[u8]) -> Result<Self::Ok, Self::Error> {
+        let order = self.order();
+        let mut skipped = Vec::new();
+        let mut last_error = None;
+
+        for index in order {
+            let backend = &self.backends[index];
+            match self.check(index) {
+                Check::Send => {}
+                Check::Probe if backend.test_connection() => self.set_healthy(index, true),
+                Check::Probe | Check::Skip => {
+                    skipped.push(index);
+                    continue;
+                }
+            }
+
+            match backend.send_raw(envelope, email) {
+                Ok(response) => return Ok(response),
+                Err(err) if T::is_unavailable(&err) => {
+                    self.set_healthy(index, false);
+                    last_error = Some(err);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        if let Some(err) = last_error {
+            return Err(err);
+        }
+
+        // All the backends are unhealthy, try them anyway
+        let mut result = None;
+        for index in skipped {
+            match self.backends[index].send_raw(envelope, email) {
+                Ok(response) => {
+                    self.set_healthy(index, true);
+                    return Ok(response);
+                }
+                Err(err) if T::is_unavailable(&err) => result = Some(Err(err)),
+                Err(err) => return Err(err),
+            }
+        }
+        result.expect("a failover transport has at least one backend")
+    }
+}
+
+#[cfg(any(feature = "tokio1", feature = "async-std1"))]
+#[async_trait]
+impl<T> AsyncTransport for FailoverTransport<T>
+where
+    T: AsyncHealthCheck + Send + Sync,
+    T::Ok: Send,
+    T::Error: Send,
+{
+    type Ok = T::Ok;
+    type Error = T::Error;
+
+    async fn send_raw(&self, envelope: &Envelope, email: &[u8]) -> Result<Self::Ok, Self::Error> {
+        let order = self.order();
+        let mut skipped = Vec::new();
+        let mut last_error = None;
+
+        for index in order {
+            let backend = &self.backends[index];
+            match self.check(index) {
+                Check::Send => {}
+                Check::Probe if backend.test_connection().await => self.set_healthy(index, true),
+                Check::Probe | Check::Skip => {
+                    skipped.push(index);
+                    continue;
+                }
+            }
+
+            match backend.send_raw(envelope, email).await {
+                Ok(response) => return Ok(response),
+                Err(err) if T::is_unavailable(&err) => {
+                    self.set_healthy(index, false);
+                    last_error = Some(err);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        if let Some(err) = last_error {
+            return Err(err);
+        }
+
+        // All the backends are unhealthy, try them anyway
+        let mut result = None;
+        for index in skipped {
+            match self.backends[index].send_raw(envelope, email).await {
+                Ok(response) => {
+                    self.set_healthy(index, true);
+                    return Ok(response);
+                }
+                Err(err) if T::is_unavailable(&err) => result = Some(Err(err)),
+                Err(err) => return Err(err),
+            }
+        }
+        result.expect("a failover transport has at least one backend")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transport::stub::StubTransport;
+
+    #[test]
+    fn test_weighted_order() {
+        let transport = FailoverTransport::builder()
+            .weighted_backend(StubTransport::new_ok(), 5)
+            .weighted_backend(StubTransport::new_ok(), 1)
+            .weighted_backend(StubTransport::new_ok(), 1)
+            .build();
+
+        let first = (0..7).map(|_| transport.order()[0]).collect::<Vec<_>>();
+        assert_eq!(first, [0, 0, 1, 0, 2, 0, 0]);
+
+        let transport = FailoverTransport::builder()
+            .backend(StubTransport::new_ok())
+            .backend(StubTransport::new_ok())
+            .strategy(Strategy::Priority)
+            .build();
+        assert_eq!(transport.order(), [0, 1]);
+        assert_eq!(transport.order(), [0, 1]);
+    }
+}
@@ -2,7 +2,7 @@
 
 use std::{error::Error as StdError, fmt};
 
-use crate::BoxError;
+use crate::{transport::RetryableError, BoxError};
 
 /// The Errors that may occur when sending an email over SMTP
 pub struct Error {
@@ -39,6 +39,13 @@ impl Error {
     }
 }
 
+impl RetryableError for Error {
+    /// File I/O errors are retryable
+    fn is_retryable(&self) -> bool {
+        self.is_io()
+    }
+}
+
 #[derive(Debug)]
 pub(crate) enum Kind {
     /// File I/O error
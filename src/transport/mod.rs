@@ -34,6 +34,13 @@
 //! | [`file`]     | File     | [`FileTransport`]     | [`AsyncFileTransport`]     | Saves the email as an `.eml` file                       |
 //! | [`stub`]     | Debug    | [`StubTransport`]     | [`AsyncStubTransport`]     | Drops the email - Useful for debugging                  |
 //!
+//! Other transports wrap one of them, changing how emails are sent:
+//!
+//! * [`retry`] sends the email again when the wrapped transport fails
+//! * [`failover`] spreads the emails over several transports, skipping the unavailable ones
+//! * `queue`, behind the `queue-transport` feature, keeps the emails which could not be
+//!   sent yet in a directory, and retries them later
+//!
 //! ## Building an email
 //!
 //! Emails can either be built though [`Message`], which is a typed API for constructing emails
@@ -94,6 +101,8 @@
 //! [`credentials`]: crate::transport::smtp::SmtpTransportBuilder::credentials
 //! [`Message`]: crate::Message
 //! [`file`]: self::file
+//! [`retry`]: self::retry
+//! [`failover`]: self::failover
 //! [`SmtpTransport`]: crate::SmtpTransport
 //! [`AsyncSmtpTransport`]: crate::AsyncSmtpTransport
 //! [`SendmailTransport`]: crate::SendmailTransport
@@ -110,9 +119,14 @@ use crate::Envelope;
 #[cfg(feature = "builder")]
 use crate::Message;
 
+pub mod failover;
 #[cfg(feature = "file-transport")]
 #[cfg_attr(docsrs, doc(cfg(feature = "file-transport")))]
 pub mod file;
+#[cfg(feature = "queue-transport")]
+#[cfg_attr(docsrs, doc(cfg(feature = "queue-transport")))]
+pub mod queue;
+pub mod retry;
 #[cfg(feature = "sendmail-transport")]
 #[cfg_attr(docsrs, doc(cfg(feature = "sendmail-transport")))]
 pub mod sendmail;
@@ -142,6 +156,15 @@ pub trait Transport {
     fn send_raw(&self, envelope: &Envelope, email: &[u8]) -> Result<Self::Ok, Self::Error>;
 }
 
+/// Errors telling if sending the email again later may succeed
+///
+/// Used by transports retrying failed emails, like [`Retry`](retry::Retry)
+/// and the `queue` transport.
+pub trait RetryableError {
+    /// Returns true if the failure is temporary
+    fn is_retryable(&self) -> bool;
+}
+
 /// Async Transport method for emails
 #[cfg(any(feature = "tokio1", feature = "async-std1"))]
 #[cfg_attr(docsrs, doc(cfg(any(feature = "tokio1", feature = "async-std1"))))]
@@ -0,0 +1,96 @@
+//! Error and result type for queued transport
+
+use std::{error::Error as StdError, fmt};
+
+use crate::BoxError;
+
+/// The Errors that may occur when queueing or sending an email
+pub struct Error {
+    inner: Box<Inner>,
+}
+
+struct Inner {
+    kind: Kind,
+    source: Option<BoxError>,
+}
+
+impl Error {
+    pub(crate) fn new<E>(kind: Kind, source: Option<E>) -> Error
+    where
+        E: Into<BoxError>,
+    {
+        Error {
+            inner: Box::new(Inner {
+                kind,
+                source: source.map(Into::into),
+            }),
+        }
+    }
+
+    /// Returns true if the error comes from reading or writing the queue directory
+    pub fn is_spool(&self) -> bool {
+        matches!(self.inner.kind, Kind::Spool)
+    }
+
+    /// Returns true if the wrapped transport failed with an error that
+    /// is not worth retrying
+    ///
+    /// The original error is available with [`StdError::source`].
+    pub fn is_transport(&self) -> bool {
+        matches!(self.inner.kind, Kind::Transport)
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum Kind {
+    /// Queue directory I/O or serialization error
+    Spool,
+    /// Permanent error of the wrapped transport
+    Transport,
+}
+
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut builder = f.debug_struct("lettre::transport::queue::Error");
+
+        builder.field("kind", &self.inner.kind);
+
+        if let Some(source) = &self.inner.source {
+            builder.field("source", source);
+        }
+
+        builder.finish()
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.inner.kind {
+            Kind::Spool => f.write_str("queue error")?,
+            Kind::Transport => f.write_str("transport error")?,
+        };
+
+        if let Some(e) = &self.inner.source {
+            write!(f, ": {e}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.inner.source.as_ref().map(|e| {
+            let r: &(dyn std::error::Error + 'static) = &**e;
+            r
+        })
+    }
+}
+
+pub(crate) fn spool<E: Into<BoxError>>(e: E) -> Error {
+    Error::new(Kind::Spool, Some(e))
+}
+
+pub(crate) fn transport<E: Into<BoxError>>(e: E) -> Error {
+    Error::new(Kind::Transport, Some(e))
+}
@@ -0,0 +1,545 @@
+//! The queued transport keeps the emails the wrapped transport could not send
+//! yet in a directory, and retries them later.
+//!
+//! Every email is first written to the queue directory: an `id.queue` file
+//! holding its delivery schedule, then the email itself with the same layout
+//! as [`FileTransport::with_envelope`] (`id.eml` for the content and `id.json`
+//! for the envelope). A first delivery attempt is then made right away:
+//!
+//! * on success, the email is removed from the queue
+//! * on a [retryable](RetryableError) error, it stays in the queue
+//!   and [`QueuedTransport::process_queue`] will retry it later, with
+//!   an exponential backoff
+//! * on any other error, it is removed from the queue and the error is returned
+//!
+//! Emails are given up after a configurable lifetime, or when an attempt
+//! fails with an error that isn't retryable. The bounce callback is then
+//! called, so the sender can be notified.
+//!
+//! Once an email was sent, failing to remove it from the queue is only
+//! logged, as reporting an error would have it sent again.
+//!
+//! Queued emails which can't be read are set aside, by renaming their
+//! schedule to `id.invalid`, so that they don't block the rest of the queue.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use std::error::Error;
+//! #
+//! # #[cfg(all(feature = "queue-transport", feature = "smtp-transport", feature = "builder", any(feature = "native-tls", feature = "rustls-tls")))]
+//! # fn main() -> Result<(), Box<dyn Error>> {
+//! use std::time::Duration;
+//!
+//! use lettre::{
+//!     transport::queue::{Delivery, QueuedTransport},
+//!     Message, SmtpTransport, Transport,
+//! };
+//!
+//! let relay = SmtpTransport::relay("smtp.example.com")?.build();
+//! let sender = QueuedTransport::builder(relay, "/var/spool/lettre")
+//!     .lifetime(Duration::from_secs(2 * 24 * 60 * 60))
+//!     .on_bounce(|bounce| eprintln!("giving up on {}: {}", bounce.id(), bounce.error()))
+//!     .build();
+//!
+//! let email = Message::builder()
+//!     .from("NoBody <nobody@domain.tld>".parse()?)
+//!     .to("Hei <hei@domain.tld>".parse()?)
+//!     .subject("Happy new year")
+//!     .body(String::from("Be happy!"))?;
+//!
+//! match sender.send(&email)? {
+//!     Delivery::Sent(_) => println!("Email sent"),
+//!     Delivery::Queued(id) => println!("Email queued as {id}"),
+//! }
+//!
+//! // Periodically
+//! sender.process_queue()?;
+//! # Ok(())
+//! # }
+//!
+//! # #[cfg(not(all(feature = "queue-transport", feature = "smtp-transport", feature = "builder", any(feature = "native-tls", feature = "rustls-tls"))))]
+//! # fn main() {}
+//! ```
+
+use std::{
+    collections::HashSet,
+    error::Error as StdError,
+    fmt::{self, Debug},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, SystemTime},
+};
+
+use uuid::Uuid;
+
+pub use self::error::Error;
+use crate::{address::Envelope, transport::RetryableError, FileTransport, Transport};
+
+mod error;
+
+type Id = String;
+
+/// Default lifetime of queued emails, five days as suggested by RFC 5321
+const DEFAULT_LIFETIME: Duration = Duration::from_secs(5 * 24 * 60 * 60);
+/// Default delay before the first retry
+const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_secs(60);
+/// Default maximum delay between two retries
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(60 * 60);
+
+type BounceCallback<E> = Arc<dyn Fn(&Bounce<'_, E>) + Send + Sync>;
+
+/// Delivery schedule of a queued email, stored in its `.queue` file
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+struct Schedule {
+    queued_at: SystemTime,
+    attempts: u32,
+    next_attempt: SystemTime,
+}
+
+/// Outcome of sending an email through a [`QueuedTransport`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Delivery<T> {
+    /// The wrapped transport sent the email, with this response
+    Sent(T),
+    /// The email could not be sent yet, and was queued with this id
+    Queued(Id),
+}
+
+/// An email given up by a [`QueuedTransport`]
+#[derive(Debug)]
+pub struct Bounce<'a, E> {
+    id: &'a str,
+    envelope: &'a Envelope,
+    email: &'a [u8],
+    error: &'a E,
+    expired: bool,
+}
+
+impl<'a, E> Bounce<'a, E> {
+    /// Queue id of the email
+    pub fn id(&self) -> &'a str {
+        self.id
+    }
+
+    /// Envelope of the email
+    pub fn envelope(&self) -> &'a Envelope {
+        self.envelope
+    }
+
+    /// Raw content of the email
+    pub fn email(&self) -> &'a [u8] {
+        self.email
+    }
+
+    /// Error of the last delivery attempt
+    pub fn error(&self) -> &'a E {
+        self.error
+    }
+
+    /// Returns true if the email was given up because it stayed
+    /// in the queue longer than its lifetime
+    pub fn is_expired(&self) -> bool {
+        self.expired
+    }
+}
+
+/// Sends emails with the wrapped transport, queueing them on disk
+/// until they can be sent
+#[cfg_attr(docsrs, doc(cfg(feature = "queue-transport")))]
+pub struct QueuedTransport<T: Transport> {
+    inner: T,
+    spool: FileTransport,
+    path: PathBuf,
+    lifetime: Duration,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    on_bounce: Option<BounceCallback<T::Error>>,
+    processing: Arc<Mutex<()>>,
+    /// Emails `send_raw` is still sending, which `process_queue` must skip
+    sending: Arc<Mutex<HashSet<Id>>>,
+}
+
+/// Contains options for configuring a [`QueuedTransport`]
+#[cfg_attr(docsrs, doc(cfg(feature = "queue-transport")))]
+pub struct QueuedTransportBuilder<T: Transport> {
+    transport: QueuedTransport<T>,
+}
+
+impl<T> QueuedTransport<T>
+where
+    T: Transport,
+    T::Error: RetryableError + StdError + Send + Sync + 'static,
+{
+    /// Creates a new builder for a transport wrapping `inner`, and keeping
+    /// the emails waiting to be sent in the `path` directory
+    pub fn builder<P: AsRef<Path>>(inner: T, path: P) -> QueuedTransportBuilder<T> {
+        QueuedTransportBuilder {
+            transport: QueuedTransport {
+                inner,
+                spool: FileTransport::with_envelope(&path),
+                path: PathBuf::from(path.as_ref()),
+                lifetime: DEFAULT_LIFETIME,
+                initial_backoff: DEFAULT_INITIAL_BACKOFF,
+                max_backoff: DEFAULT_MAX_BACKOFF,
+                on_bounce: None,
+                processing: Arc::new(Mutex::new(())),
+                sending: Arc::new(Mutex::new(HashSet::new())),
+            },
+        }
+    }
+
+    /// Returns the ids of the emails waiting in the queue
+    pub fn queued(&self) -> Result<Vec<Id>, Error> {
+        let mut ids = Vec::new();
+        for entry in fs::read_dir(&self.path).map_err(error::spool)? {
+            let path = entry.map_err(error::spool)?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "queue")
+            {
+                if let Some(id) = path.file_stem().and_then(|stem| stem.to_str()) {
+                    ids.push(id.to_owned());
+                }
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Retries sending the queued emails which are due
+    ///
+    /// Should be called periodically. Returns the number of emails still
+    /// waiting in the queue. Calls made concurrently from clones of this
+    /// transport wait for each other, and skip the emails still being sent
+    /// by [`Transport::send`], but the queue directory must not be shared
+    /// between processes.
+    pub fn process_queue(&self) -> Result<usize, Error> {
+        // Nothing to protect, a panicking bounce callback must not poison the queue
+        let _processing = self
+            .processing
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        let mut remaining = 0;
+        for id in self.queued()? {
+            // Possibly not fully written yet, and sent meanwhile anyway
+            if self.is_sending(&id) {
+                remaining += 1;
+                continue;
+            }
+
+            let mut schedule = match self.read_schedule(&id) {
+                Ok(schedule) => schedule,
+                Err(err) => {
+                    self.quarantine(&id, &err);
+                    continue;
+                }
+            };
+            let now = SystemTime::now();
+            if schedule.next_attempt > now {
+                remaining += 1;
+                continue;
+            }
+
+            let (envelope, email) = match self.spool.read(&id).map_err(error::spool) {
+                Ok(entry) => entry,
+                Err(err) => {
+                    self.quarantine(&id, &err);
+                    continue;
+                }
+            };
+            let err = match self.inner.send_raw(&envelope, &email) {
+                Ok(_) => {
+                    self.discard(&id);
+                    continue;
+                }
+                Err(err) => err,
+            };
+
+            let expired = now
+                .duration_since(schedule.queued_at)
+                .is_ok_and(|age| age >= self.lifetime);
+            if err.is_retryable() && !expired {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(id, attempts = schedule.attempts, "email still not sent");
+                // Retried on the next run with its previous schedule
+                if let Err(err) = self.reschedule(&id, &mut schedule, now) {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(id, "couldn't reschedule email: {}", err);
+                    #[cfg(not(feature = "tracing"))]
+                    let _ = err;
+                }
+                remaining += 1;
+            } else {
+                self.bounce(&id, &envelope, &email, &err, expired);
+            }
+        }
+        Ok(remaining)
+    }
+
+    fn is_sending(&self, id: &str) -> bool {
+        self.sending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .contains(id)
+    }
+
+    /// Gives up on an email, calling the bounce callback
+    fn bounce(&self, id: &str, envelope: &Envelope, email: &[u8], err: &T::Error, expired: bool) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(id, expired, "giving up on email");
+        if let Some(on_bounce) = &self.on_bounce {
+            on_bounce(&Bounce {
+                id,
+                envelope,
+                email,
+                error: err,
+                expired,
+            });
+        }
+        self.discard(id);
+    }
+
+    /// Delay before the next attempt, after `attempts` failed ones
+    fn backoff(&self, attempts: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempts.saturating_sub(1));
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+
+    fn reschedule(&self, id: &str, schedule: &mut Schedule, now: SystemTime) -> Result<(), Error> {
+        schedule.attempts += 1;
+        schedule.next_attempt = now + self.backoff(schedule.attempts);
+        self.write_schedule(id, schedule)
+    }
+
+    fn schedule_path(&self, id: &str) -> PathBuf {
+        self.path.join(format!("{id}.queue"))
+    }
+
+    fn read_schedule(&self, id: &str) -> Result<Schedule, Error> {
+        let json = fs::read(self.schedule_path(id)).map_err(error::spool)?;
+        serde_json::from_slice(&json).map_err(error::spool)
+    }
+
+    fn write_schedule(&self, id: &str, schedule: &Schedule) -> Result<(), Error> {
+        let json = serde_json::to_vec(schedule).map_err(error::spool)?;
+        // Replaced atomically, so that a crash never leaves a truncated schedule
+        let tmp_path = self.path.join(format!("{id}.queue.tmp"));
+        fs::write(&tmp_path, json).map_err(error::spool)?;
+        fs::rename(tmp_path, self.schedule_path(id)).map_err(error::spool)
+    }
+
+    /// Writes the content and the envelope of an email
+    fn write_email(&self, id: &str, envelope: &Envelope, email: &[u8]) -> Result<(), Error> {
+        let json = serde_json::to_vec(envelope).map_err(error::spool)?;
+        fs::write(self.path.join(format!("{id}.eml")), email).map_err(error::spool)?;
+        fs::write(self.path.join(format!("{id}.json")), json).map_err(error::spool)
+    }
+
+    /// Sets aside an email which can't be read, so that it isn't retried
+    #[allow(unused_variables)]
+    fn quarantine(&self, id: &str, err: &Error) {
+        #[cfg(feature = "tracing")]
+        tracing::warn!(id, "setting aside unreadable email: {}", err);
+        let invalid_path = self.path.join(format!("{id}.invalid"));
+        if let Err(err) = fs::rename(self.schedule_path(id), invalid_path) {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(id, "couldn't set aside unreadable email: {}", err);
+        }
+    }
+
+    /// Removes an email from the queue
+    fn remove(&self, id: &str) -> Result<(), Error> {
+        // Removing the schedule first prevents retrying a half removed email
+        for extension in ["queue", "queue.tmp", "eml", "json"] {
+            match fs::remove_file(self.path.join(format!("{id}.{extension}"))) {
+                Ok(()) => {}
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(error::spool(err)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes an email which was sent or given up, only logging failures
+    #[allow(unused_variables)]
+    fn discard(&self, id: &str) {
+        if let Err(err) = self.remove(id) {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(id, "couldn't remove email from the queue: {}", err);
+        }
+    }
+}
+
+impl<T> QueuedTransportBuilder<T>
+where
+    T: Transport,
+    T::Error: RetryableError + StdError + Send + Sync + 'static,
+{
+    /// Set how long an email is retried before being given up
+    ///
+    /// Defaults to 5 days.
+    pub fn lifetime(mut self, lifetime: Duration) -> Self {
+        self.transport.lifetime = lifetime;
+        self
+    }
+
+    /// Set the delay before the first retry, doubled after each failed retry
+    ///
+    /// Defaults to 1 minute.
+    pub fn initial_backoff(mut self, backoff: Duration) -> Self {
+        self.transport.initial_backoff = backoff;
+        self
+    }
+
+    /// Set the maximum delay between two retries
+    ///
+    /// Defaults to 1 hour.
+    pub fn max_backoff(mut self, backoff: Duration) -> Self {
+        self.transport.max_backoff = backoff;
+        self
+    }
+
+    /// Set a callback called with the emails given up, because they failed
+    /// with an error that isn't retryable or expired
+    pub fn on_bounce<F>(mut self, on_bounce: F) -> Self
+    where
+        F: Fn(&Bounce<'_, T::Error>) + Send + Sync + 'static,
+    {
+        self.transport.on_bounce = Some(Arc::new(on_bounce));
+        self
+    }
+
+    /// Build the transport
+    pub fn build(self) -> QueuedTransport<T> {
+        self.transport
+    }
+}
+
+impl<T> Transport for QueuedTransport<T>
+where
+    T: Transport,
+    T::Error: RetryableError + StdError + Send + Sync + 'static,
+{
+    type Ok = Delivery<T::Ok>;
+    type Error = Error;
+
+    fn send_raw(&self, envelope: &Envelope, email: &[u8]) -> Result<Self::Ok, Self::Error> {
+        let id = Uuid::new_v4().to_string();
+        let _sending = Sending::new(&self.sending, &id);
+        let now = SystemTime::now();
+        // Not due before the end of the first attempt, if the process stops meanwhile
+        let mut schedule = Schedule {
+            queued_at: now,
+            attempts: 0,
+            next_attempt: now + self.backoff(1),
+        };
+        // Written first, so that a crash never leaves an email the queue doesn't know about
+        self.write_schedule(&id, &schedule)?;
+        if let Err(err) = self.write_email(&id, envelope, email) {
+            self.discard(&id);
+            return Err(err);
+        }
+
+        match self.inner.send_raw(envelope, email) {
+            Ok(response) => {
+                self.discard(&id);
+                Ok(Delivery::Sent(response))
+            }
+            Err(err) if err.is_retryable() => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(id, "queueing email");
+                self.reschedule(&id, &mut schedule, now)?;
+                Ok(Delivery::Queued(id))
+            }
+            Err(err) => {
+                self.bounce(&id, envelope, email, &err, false);
+                Err(error::transport(err))
+            }
+        }
+    }
+}
+
+/// Marks an email as being sent by `send_raw`, until dropped
+struct Sending<'a> {
+    sending: &'a Mutex<HashSet<Id>>,
+    id: Id,
+}
+
+impl<'a> Sending<'a> {
+    fn new(sending: &'a Mutex<HashSet<Id>>, id: &str) -> Self {
+        sending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(id.to_owned());
+        Self {
+            sending,
+            id: id.to_owned(),
+        }
+    }
+}
+
+impl Drop for Sending<'_> {
+    fn drop(&mut self) {
+        self.sending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&self.id);
+    }
+}
+
+impl<T> Debug for QueuedTransport<T>
+where
+    T: Transport + Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("QueuedTransport")
+            .field("inner", &self.inner)
+            .field("path", &self.path)
+            .field("lifetime", &self.lifetime)
+            .field("initial_backoff", &self.initial_backoff)
+            .field("max_backoff", &self.max_backoff)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> Clone for QueuedTransport<T>
+where
+    T: Transport + Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            spool: self.spool.clone(),
+            path: self.path.clone(),
+            lifetime: self.lifetime,
+            initial_backoff: self.initial_backoff,
+            max_backoff: self.max_backoff,
+            on_bounce: self.on_bounce.clone(),
+            processing: Arc::clone(&self.processing),
+            sending: Arc::clone(&self.sending),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let transport = QueuedTransport::builder(FileTransport::new("."), ".")
+            .initial_backoff(Duration::from_secs(10))
+            .max_backoff(Duration::from_secs(60))
+            .build();
+
+        assert_eq!(transport.backoff(1), Duration::from_secs(10));
+        assert_eq!(transport.backoff(2), Duration::from_secs(20));
+        assert_eq!(transport.backoff(3), Duration::from_secs(40));
+        assert_eq!(transport.backoff(4), Duration::from_secs(60));
+        assert_eq!(transport.backoff(100), Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,284 @@
+//! The retry transport wraps another transport, sending again the emails
+//! it failed to send.
+//!
+//! When to retry, and after which delay, is decided by a [`RetryPolicy`]
+//! looking at the error. The default [`ExponentialBackoff`] policy retries
+//! [retryable](RetryableError) errors a few times, waiting longer between
+//! each attempt. Closures can be used as custom policies.
+//!
+//! Unlike the `queue` transport, the attempts are all made
+//! during the call to `send`, and nothing is persisted.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! # #[cfg(all(feature = "smtp-transport", feature = "builder", any(feature = "native-tls", feature = "rustls-tls")))]
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use std::time::Duration;
+//!
+//! use lettre::{
+//!     transport::{retry::Retry, smtp},
+//!     Message, SmtpTransport, Transport,
+//! };
+//!
+//! let relay = SmtpTransport::relay("smtp.example.com")?.build();
+//! // Retry up to 3 times, after 1 second, 2 seconds and 4 seconds
+//! let sender = Retry::new(relay);
+//!
+//! // Only retry timeouts, once
+//! let relay = SmtpTransport::relay("smtp.example.com")?.build();
+//! let sender = Retry::with_policy(relay, |attempt: u32, error: &smtp::Error| {
+//!     (attempt == 1 && error.is_timeout()).then_some(Duration::from_secs(5))
+//! });
+//!
+//! let email = Message::builder()
+//!     .from("NoBody <nobody@domain.tld>".parse()?)
+//!     .to("Hei <hei@domain.tld>".parse()?)
+//!     .subject("Happy new year")
+//!     .body(String::from("Be happy!"))?;
+//! sender.send(&email)?;
+//! # Ok(())
+//! # }
+//!
+//! # #[cfg(not(all(feature = "smtp-transport", feature = "builder", any(feature = "native-tls", feature = "rustls-tls"))))]
+//! # fn main() {}
+//! ```
+
+#[cfg(any(feature = "tokio1", feature = "async-std1"))]
+use std::marker::PhantomData;
+use std::{thread, time::Duration};
+
+#[cfg(any(feature = "tokio1", feature = "async-std1"))]
+use async_trait::async_trait;
+
+use crate::{address::Envelope, transport::RetryableError, Transport};
+#[cfg(any(feature = "tokio1", feature = "async-std1"))]
+use crate::{AsyncTransport, Executor};
+
+/// Decides if a failed email should be sent again
+pub trait RetryPolicy<E> {
+    /// Returns the delay to wait before sending the email again after the
+    /// `attempt`-th attempt failed with `error`, starting from 1, or `None`
+    /// to give up and return the error
+    fn retry(&self, attempt: u32, error: &E) -> Option<Duration>;
+}
+
+impl<E, F> RetryPolicy<E> for F
+where
+    F: Fn(u32, &E) -> Option<Duration>,
+{
+    fn retry(&self, attempt: u32, error: &E) -> Option<Duration> {
+        self(attempt, error)
+    }
+}
+
+/// Retries [retryable](RetryableError) errors, doubling the delay between
+/// two attempts each time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExponentialBackoff {
+    max_retries: u32,
+    initial_delay: Duration,
+    max_delay: Duration,
+}
+
+impl ExponentialBackoff {
+    /// Creates a policy retrying at most `max_retries` times, waiting
+    /// `initial_delay` before the first retry
+    pub fn new(max_retries: u32, initial_delay: Duration) -> Self {
+        Self {
+            max_retries,
+            initial_delay,
+            max_delay: Duration::MAX,
+        }
+    }
+
+    /// Set the maximum delay between two attempts
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+}
+
+impl Default for ExponentialBackoff {
+    /// Retries 3 times, after 1 second, 2 seconds and 4 seconds
+    fn default() -> Self {
+        Self::new(3, Duration::from_secs(1))
+    }
+}
+
+impl<E: RetryableError> RetryPolicy<E> for ExponentialBackoff {
+    fn retry(&self, attempt: u32, error: &E) -> Option<Duration> {
+        if attempt > self.max_retries || !error.is_retryable() {
+            return None;
+        }
+
+        let factor = 2u32.saturating_pow(attempt - 1);
+        Some(
+            self.initial_delay
+                .saturating_mul(factor)
+                .min(self.max_delay),
+        )
+    }
+}
+
+/// Sends emails with the wrapped transport, retrying according to a [`RetryPolicy`]
+#[derive(Debug, Clone)]
+pub struct Retry<T, P = ExponentialBackoff> {
+    inner: T,
+    policy: P,
+}
+
+/// Asynchronously sends emails with the wrapped transport, retrying
+/// according to a [`RetryPolicy`]
+///
+/// Waits between attempts with [`Executor::sleep`].
+#[derive(Debug, Clone)]
+#[cfg(any(feature = "tokio1", feature = "async-std1"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "tokio1", feature = "async-std1"))))]
+pub struct AsyncRetry<T, E: Executor, P = ExponentialBackoff> {
+    inner: T,
+    policy: P,
+    marker_: PhantomData<E>,
+}
+
+impl<T> Retry<T> {
+    /// Creates a transport retrying with the default [`ExponentialBackoff`] policy
+    pub fn new(inner: T) -> Self {
+        Self::with_policy(inner, ExponentialBackoff::default())
+    }
+}
+
+impl<T, P> Retry<T, P> {
+    /// Creates a transport retrying with the given policy
+    pub fn with_policy(inner: T, policy: P) -> Self {
+        Self { inner, policy }
+    }
+
+    /// Returns the wrapped transport
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+#[cfg(any(feature = "tokio1", feature = "async-std1"))]
+impl<T, E> AsyncRetry<T, E>
+where
+    E: Executor,
+{
+    /// Creates a transport retrying with the default [`ExponentialBackoff`] policy
+    pub fn new(inner: T) -> Self {
+        Self::with_policy(inner, ExponentialBackoff::default())
+    }
+}
+
+#[cfg(any(feature = "tokio1", feature = "async-std1"))]
+impl<T, E, P> AsyncRetry<T, E, P>
+where
+    E: Executor,
+{
+    /// Creates a transport retrying with the given policy
+    pub fn with_policy(inner: T, policy: P) -> Self {
+        Self {
+            inner,
+            policy,
+            marker_: PhantomData,
+        }
+    }
+
+    /// Returns the wrapped transport
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T, P> Transport for Retry<T, P>
+where
+    T: Transport,
+    P: RetryPolicy<T::Error>,
+{
+    type Ok = T::Ok;
+    type Error = T::Error;
+
+    fn send_raw(&self, envelope: &Envelope, email: &[u8]) -> Result<Self::Ok, Self::Error> {
+        let mut attempt = 1;
+        loop {
+            let err = match self.inner.send_raw(envelope, email) {
+                Ok(response) => return Ok(response),
+                Err(err) => err,
+            };
+
+            let delay = self.policy.retry(attempt, &err).ok_or(err)?;
+            #[cfg(feature = "tracing")]
+            tracing::debug!(attempt, ?delay, "retrying to send email");
+            thread::sleep(delay);
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(any(feature = "tokio1", feature = "async-std1"))]
+#[async_trait]
+impl<T, E, P> AsyncTransport for AsyncRetry<T, E, P>
+where
+    T: AsyncTransport + Send + Sync,
+    T::Error: Send,
+    E: Executor,
+    P: RetryPolicy<T::Error> + Send + Sync,
+{
+    type Ok = T::Ok;
+    type Error = T::Error;
+
+    async fn send_raw(&self, envelope: &Envelope, email: &[u8]) -> Result<Self::Ok, Self::Error> {
+        let mut attempt = 1;
+        loop {
+            let err = match self.inner.send_raw(envelope, email).await {
+                Ok(response) => return Ok(response),
+                Err(err) => err,
+            };
+
+            let delay = self.policy.retry(attempt, &err).ok_or(err)?;
+            #[cfg(feature = "tracing")]
+            tracing::debug!(attempt, ?delay, "retrying to send email");
+            E::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Retryable(bool);
+
+    impl RetryableError for Retryable {
+        fn is_retryable(&self) -> bool {
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_exponential_backoff() {
+        let policy =
+            ExponentialBackoff::new(4, Duration::from_secs(1)).max_delay(Duration::from_secs(5));
+
+        assert_eq!(
+            policy.retry(1, &Retryable(true)),
+            Some(Duration::from_secs(1))
+        );
+        assert_eq!(
+            policy.retry(2, &Retryable(true)),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(
+            policy.retry(3, &Retryable(true)),
+            Some(Duration::from_secs(4))
+        );
+        assert_eq!(
+            policy.retry(4, &Retryable(true)),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(policy.retry(5, &Retryable(true)), None);
+        assert_eq!(policy.retry(1, &Retryable(false)), None);
+    }
+}
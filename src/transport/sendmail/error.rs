@@ -2,7 +2,7 @@
 
 use std::{error::Error as StdError, fmt};
 
-use crate::BoxError;
+use crate::{transport::RetryableError, BoxError};
 
 /// The Errors that may occur when sending an email over sendmail
 pub struct Error {
@@ -36,6 +36,18 @@ impl Error {
     pub fn is_response(&self) -> bool {
         matches!(self.inner.kind, Kind::Response)
     }
+
+    /// Returns true if the sendmail command reported a temporary failure
+    pub fn is_temporary(&self) -> bool {
+        matches!(self.inner.kind, Kind::Temporary)
+    }
+}
+
+impl RetryableError for Error {
+    /// Temporary failures of the sendmail command are retryable
+    fn is_retryable(&self) -> bool {
+        self.is_temporary()
+    }
 }
 
 #[derive(Debug)]
@@ -44,6 +56,8 @@ pub(crate) enum Kind {
     Response,
     /// Internal client error
     Client,
+    /// The sendmail command exited with `EX_TEMPFAIL`
+    Temporary,
 }
 
 impl fmt::Debug for Error {
@@ -65,6 +79,7 @@ impl fmt::Display for Error {
         match self.inner.kind {
             Kind::Response => f.write_str("response error")?,
             Kind::Client => f.write_str("internal client error")?,
+            Kind::Temporary => f.write_str("temporary failure")?,
         };
 
         if let Some(e) = &self.inner.source {
@@ -91,3 +106,7 @@ pub(crate) fn response<E: Into<BoxError>>(e: E) -> Error {
 pub(crate) fn client<E: Into<BoxError>>(e: E) -> Error {
     Error::new(Kind::Client, Some(e))
 }
+
+pub(crate) fn temporary<E: Into<BoxError>>(e: E) -> Error {
+    Error::new(Kind::Temporary, Some(e))
+}
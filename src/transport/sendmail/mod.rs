@@ -82,7 +82,7 @@ use std::marker::PhantomData;
 use std::{
     ffi::OsString,
     io::Write,
-    process::{Command, Stdio},
+    process::{Command, ExitStatus, Stdio},
 };
 
 #[cfg(any(feature = "async-std1", feature = "tokio1"))]
@@ -101,6 +101,9 @@ mod error;
 
 const DEFAULT_SENDMAIL: &str = "sendmail";
 
+/// Exit code of sendmail for temporary failures, from `sysexits.h`
+const EX_TEMPFAIL: i32 = 75;
+
 /// Sends emails using the `sendmail` command
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
@@ -230,6 +233,15 @@ where
     }
 }
 
+/// Builds the error of a sendmail command which exited with `status`
+fn exit_error(status: ExitStatus, stderr: String) -> Error {
+    if status.code() == Some(EX_TEMPFAIL) {
+        error::temporary(stderr)
+    } else {
+        error::client(stderr)
+    }
+}
+
 impl Transport for SendmailTransport {
     type Ok = ();
     type Error = Error;
@@ -253,7 +265,7 @@ impl Transport for SendmailTransport {
             Ok(())
         } else {
             let stderr = String::from_utf8(output.stderr).map_err(error::response)?;
-            Err(error::client(stderr))
+            Err(exit_error(output.status, stderr))
         }
     }
 }
@@ -288,7 +300,7 @@ impl AsyncTransport for AsyncSendmailTransport<AsyncStd1Executor> {
             Ok(())
         } else {
             let stderr = String::from_utf8(output.stderr).map_err(error::response)?;
-            Err(error::client(stderr))
+            Err(exit_error(output.status, stderr))
         }
     }
 }
@@ -323,7 +335,7 @@ impl AsyncTransport for AsyncSendmailTransport<Tokio1Executor> {
             Ok(())
         } else {
             let stderr = String::from_utf8(output.stderr).map_err(error::response)?;
-            Err(error::client(stderr))
+            Err(exit_error(output.status, stderr))
         }
     }
 }
@@ -1,8 +1,7 @@
-#[cfg(feature = "pool")]
-use std::sync::Arc;
 use std::{
     fmt::{self, Debug},
     marker::PhantomData,
+    sync::Arc,
     time::Duration,
 };
 
@@ -10,8 +9,6 @@ use async_trait::async_trait;
 
 #[cfg(feature = "pool")]
 use super::pool::async_impl::Pool;
-#[cfg(feature = "pool")]
-use super::PoolConfig;
 #[cfg(any(
     feature = "tokio1-native-tls",
     feature = "tokio1-rustls-tls",
@@ -19,14 +16,21 @@ use super::PoolConfig;
 ))]
 use super::Tls;
 use super::{
-    client::AsyncSmtpConnection, ClientId, Credentials, Error, Mechanism, Response, SmtpInfo,
+    authentication::AsyncCredentialsProvider,
+    client::{AsyncSmtpConnection, SocketOptions},
+    error,
+    rate_limit::RateLimiter,
+    semaphore::Permit,
+    AsyncResolver, ClientId, Credentials, Error, Mechanism, RateLimit, Response, SmtpInfo,
 };
+#[cfg(feature = "pool")]
+use super::{PoolConfig, PoolStats};
 #[cfg(feature = "async-std1")]
 use crate::AsyncStd1Executor;
-#[cfg(any(feature = "tokio1", feature = "async-std1"))]
-use crate::AsyncTransport;
 #[cfg(feature = "tokio1")]
 use crate::Tokio1Executor;
+#[cfg(any(feature = "tokio1", feature = "async-std1"))]
+use crate::{transport::failover::AsyncHealthCheck, AsyncTransport};
 use crate::{Envelope, Executor};
 
 /// Asynchronously sends emails using the SMTP protocol
@@ -47,7 +51,8 @@ use crate::{Envelope, Executor};
 ///
 /// The connection pool can grow to hold multiple SMTP connections if multiple
 /// emails are sent concurrently, as SMTP does not support multiplexing within a
-/// single connection.
+/// single connection. It opens at most [`PoolConfig::max_size`] connections,
+/// additional senders wait for one of them to be released.
 ///
 /// However, **connection reuse is not possible** if the `SyncSmtpTransport` instance
 /// is dropped after every email send operation. You must reuse the instance
@@ -60,6 +65,8 @@ pub struct AsyncSmtpTransport<E: Executor> {
     inner: Arc<Pool<E>>,
     #[cfg(not(feature = "pool"))]
     inner: AsyncSmtpClient<E>,
+    partial_delivery: bool,
+    rate_limiter: Option<Arc<RateLimiter>>,
 }
 
 #[cfg(feature = "tokio1")]
@@ -70,9 +77,21 @@ impl AsyncTransport for AsyncSmtpTransport<Tokio1Executor> {
 
     /// Sends an email
     async fn send_raw(&self, envelope: &Envelope, email: &[u8]) -> Result<Self::Ok, Self::Error> {
+        // Declared first, the permit is released after the connection
+        let _permit = self.wait_rate_limit(envelope).await;
         let mut conn = self.inner.connection().await?;
 
-        let result = conn.send(envelope, email).await?;
+        let result = match self.send_with(&mut conn, envelope, email).await {
+            #[cfg(feature = "pool")]
+            Err(err) if conn.is_stale(&err) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("pooled connection was closed, retrying with a new one");
+
+                conn.reconnect().await?;
+                self.send_with(&mut conn, envelope, email).await?
+            }
+            result => result?,
+        };
 
         #[cfg(not(feature = "pool"))]
         conn.abort().await;
@@ -89,9 +108,21 @@ impl AsyncTransport for AsyncSmtpTransport<AsyncStd1Executor> {
 
     /// Sends an email
     async fn send_raw(&self, envelope: &Envelope, email: &[u8]) -> Result<Self::Ok, Self::Error> {
+        // Declared first, the permit is released after the connection
+        let _permit = self.wait_rate_limit(envelope).await;
         let mut conn = self.inner.connection().await?;
 
-        let result = conn.send(envelope, email).await?;
+        let result = match self.send_with(&mut conn, envelope, email).await {
+            #[cfg(feature = "pool")]
+            Err(err) if conn.is_stale(&err) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("pooled connection was closed, retrying with a new one");
+
+                conn.reconnect().await?;
+                self.send_with(&mut conn, envelope, email).await?
+            }
+            result => result?,
+        };
 
         conn.quit().await?;
 
@@ -99,6 +130,34 @@ impl AsyncTransport for AsyncSmtpTransport<AsyncStd1Executor> {
     }
 }
 
+#[cfg(feature = "tokio1")]
+#[async_trait]
+impl AsyncHealthCheck for AsyncSmtpTransport<Tokio1Executor> {
+    /// Connection and network errors make the relay unhealthy, unless the
+    /// message content was sent and the relay may have accepted it
+    fn is_unavailable(error: &Error) -> bool {
+        error.is_network() && !error.has_sent_content()
+    }
+
+    async fn test_connection(&self) -> bool {
+        self.test_connection().await.unwrap_or(false)
+    }
+}
+
+#[cfg(feature = "async-std1")]
+#[async_trait]
+impl AsyncHealthCheck for AsyncSmtpTransport<AsyncStd1Executor> {
+    /// Connection and network errors make the relay unhealthy, unless the
+    /// message content was sent and the relay may have accepted it
+    fn is_unavailable(error: &Error) -> bool {
+        error.is_network() && !error.has_sent_content()
+    }
+
+    async fn test_connection(&self) -> bool {
+        self.test_connection().await.unwrap_or(false)
+    }
+}
+
 impl<E> AsyncSmtpTransport<E>
 where
     E: Executor,
@@ -288,6 +347,10 @@ where
     /// `test_connection()` tests the connection by using the SMTP NOOP command.
     /// The connection is closed afterward if a connection pool is not used.
     pub async fn test_connection(&self) -> Result<bool, Error> {
+        let _permit = match &self.rate_limiter {
+            Some(rate_limiter) => rate_limiter.connection_async().await,
+            None => None,
+        };
         let mut conn = self.inner.connection().await?;
 
         let is_connected = conn.test_connected().await;
@@ -297,12 +360,69 @@ where
 
         Ok(is_connected)
     }
+
+    /// Returns statistics about the connection pool
+    ///
+    /// To receive them periodically, see [`PoolConfig::on_stats`].
+    #[cfg(feature = "pool")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "pool")))]
+    pub async fn pool_stats(&self) -> PoolStats {
+        self.inner.stats().await
+    }
+
+    /// Opens the [`min_idle`](PoolConfig::min_idle) connections of the
+    /// pool, before sending the first emails
+    ///
+    /// Returns the first error, if a connection can't be opened.
+    #[cfg(feature = "pool")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "pool")))]
+    pub async fn warm_up(&self) -> Result<(), Error> {
+        self.inner.warm_up().await
+    }
+
+    /// Closes the connections of the pool, sending `QUIT` to the server
+    ///
+    /// Idle connections are closed right away, the ones currently used to
+    /// send an email when they are released. Sending emails with this
+    /// transport, or its clones, fails afterward.
+    ///
+    /// Returns the first error, after trying to close all the idle connections.
+    #[cfg(feature = "pool")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "pool")))]
+    pub async fn close(&self) -> Result<(), Error> {
+        self.inner.shutdown().await
+    }
+
+    async fn send_with(
+        &self,
+        conn: &mut AsyncSmtpConnection,
+        envelope: &Envelope,
+        email: &[u8],
+    ) -> Result<Response, Error> {
+        if self.partial_delivery {
+            conn.send_partial(envelope, email).await
+        } else {
+            conn.send(envelope, email).await
+        }
+    }
+
+    /// Waits until the rate limit allows sending the email
+    async fn wait_rate_limit(&self, envelope: &Envelope) -> Option<Permit> {
+        let rate_limiter = self.rate_limiter.as_ref()?;
+        let delay = rate_limiter.delay(envelope.to().len());
+        if !delay.is_zero() {
+            E::sleep(delay).await;
+        }
+        rate_limiter.connection_async().await
+    }
 }
 
 impl<E: Executor> Debug for AsyncSmtpTransport<E> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut builder = f.debug_struct("AsyncSmtpTransport");
         builder.field("inner", &self.inner);
+        builder.field("partial_delivery", &self.partial_delivery);
+        builder.field("rate_limiter", &self.rate_limiter);
         builder.finish()
     }
 }
@@ -317,6 +437,8 @@ where
             inner: Arc::clone(&self.inner),
             #[cfg(not(feature = "pool"))]
             inner: self.inner.clone(),
+            partial_delivery: self.partial_delivery,
+            rate_limiter: self.rate_limiter.clone(),
         }
     }
 }
@@ -329,6 +451,7 @@ pub struct AsyncSmtpTransportBuilder {
     info: SmtpInfo,
     #[cfg(feature = "pool")]
     pool_config: PoolConfig,
+    rate_limit: Option<RateLimit>,
 }
 
 /// Builder for the SMTP `AsyncSmtpTransport`
@@ -344,6 +467,7 @@ impl AsyncSmtpTransportBuilder {
             info,
             #[cfg(feature = "pool")]
             pool_config: PoolConfig::default(),
+            rate_limit: None,
         }
     }
 
@@ -359,6 +483,32 @@ impl AsyncSmtpTransportBuilder {
         self
     }
 
+    /// Set a provider for the authentication credentials
+    ///
+    /// The provider is consulted each time a new connection is opened,
+    /// instead of the [`credentials`](Self::credentials). If the server
+    /// rejects them with a 535 reply, [`AsyncCredentialsProvider::refresh`] is
+    /// called and the connection is retried once.
+    pub fn credentials_provider<P>(mut self, provider: P) -> Self
+    where
+        P: AsyncCredentialsProvider + 'static,
+    {
+        self.info.async_credentials_provider = Some(Arc::new(provider));
+        self
+    }
+
+    /// Set the resolver of the server host name
+    ///
+    /// The resolver is consulted each time a new connection is opened.
+    /// By default, the host name is resolved by the async runtime.
+    pub fn resolver<R>(mut self, resolver: R) -> Self
+    where
+        R: AsyncResolver + 'static,
+    {
+        self.info.async_resolver = Some(Arc::new(resolver));
+        self
+    }
+
     /// Set the authentication mechanism to use
     pub fn authentication(mut self, mechanisms: Vec<Mechanism>) -> Self {
         self.info.authentication = mechanisms;
@@ -388,6 +538,14 @@ impl AsyncSmtpTransportBuilder {
         self
     }
 
+    /// Set the options of the TCP sockets, like the source address
+    ///
+    /// See [`SocketOptions`] for the defaults, and the limitations with async-std.
+    pub fn socket_options(mut self, socket_options: SocketOptions) -> Self {
+        self.info.socket_options = socket_options;
+        self
+    }
+
     /// Set the TLS settings to use
     ///
     /// # ⚠️⚠️⚠️ You probably don't need to call this method ⚠️⚠️⚠️
@@ -418,6 +576,19 @@ impl AsyncSmtpTransportBuilder {
         self
     }
 
+    /// Deliver the message to the accepted recipients when some are rejected
+    ///
+    /// By default the whole transaction is aborted as soon as the server
+    /// rejects one `RCPT TO`. When enabled, the rejected recipients are skipped
+    /// and the returned [`Response`] lists the accepted and rejected recipients
+    /// with their responses, see [`Response::accepted_recipients`] and
+    /// [`Response::rejected_recipients`]. Sending still fails if every
+    /// recipient is rejected.
+    pub fn partial_delivery(mut self, enabled: bool) -> Self {
+        self.info.partial_delivery = enabled;
+        self
+    }
+
     /// Use a custom configuration for the connection pool
     ///
     /// Defaults can be found at [`PoolConfig`]
@@ -428,20 +599,39 @@ impl AsyncSmtpTransportBuilder {
         self
     }
 
+    /// Limit how fast emails are sent
+    ///
+    /// By default, there is no limit. Senders exceeding the limit await
+    /// until they can proceed.
+    pub fn rate_limit(mut self, rate_limit: RateLimit) -> Self {
+        self.rate_limit = Some(rate_limit);
+        self
+    }
+
     /// Build the transport
     pub fn build<E>(self) -> AsyncSmtpTransport<E>
     where
         E: Executor,
     {
+        let partial_delivery = self.info.partial_delivery;
         let client = AsyncSmtpClient {
             info: self.info,
             marker_: PhantomData,
         };
 
         #[cfg(feature = "pool")]
-        let client = Pool::new(self.pool_config, client);
+        let pool_config = match &self.rate_limit {
+            Some(rate_limit) => rate_limit.limit_pool(self.pool_config),
+            None => self.pool_config,
+        };
+        #[cfg(feature = "pool")]
+        let client = Pool::new(pool_config, client);
 
-        AsyncSmtpTransport { inner: client }
+        AsyncSmtpTransport {
+            inner: client,
+            partial_delivery,
+            rate_limiter: self.rate_limit.map(RateLimiter::new),
+        }
     }
 }
 
@@ -459,16 +649,48 @@ where
     ///
     /// Handles encryption and authentication
     pub async fn connection(&self) -> Result<AsyncSmtpConnection, Error> {
+        let Some(provider) = &self.info.async_credentials_provider else {
+            return self.connect(self.info.credentials.as_ref()).await;
+        };
+
+        let credentials = provider.credentials().await.map_err(error::client)?;
+        match self.connect(Some(&credentials)).await {
+            Err(err) if err.status().map(u16::from) == Some(535) => {
+                let credentials = provider
+                    .refresh(&credentials)
+                    .await
+                    .map_err(error::client)?;
+                self.connect(Some(&credentials)).await
+            }
+            result => result,
+        }
+    }
+
+    async fn connect(
+        &self,
+        credentials: Option<&Credentials>,
+    ) -> Result<AsyncSmtpConnection, Error> {
+        let addrs = match &self.info.async_resolver {
+            Some(resolver) => resolver
+                .resolve(&self.info.server, self.info.port)
+                .await
+                .map_err(error::connection)?,
+            None => E::resolve(&self.info.server, self.info.port)
+                .await
+                .map_err(error::connection)?,
+        };
+
         let mut conn = E::connect(
-            &self.info.server,
-            self.info.port,
+            &addrs,
             self.info.timeout,
             &self.info.hello_name,
             &self.info.tls,
+            &self.info.socket_options,
         )
         .await?;
+        conn.set_server_address(&self.info.server, self.info.port);
 
-        if let Some(credentials) = &self.info.credentials {
+        if let Some(credentials) = credentials {
             conn.auth(&self.info.authentication, credentials).await?;
         }
         Ok(conn)
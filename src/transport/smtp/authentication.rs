@@ -1,8 +1,24 @@
 //! Provides limited SASL authentication mechanisms
 
-use std::fmt::{self, Debug, Display, Formatter};
+use std::{
+    error::Error as StdError,
+    fmt::{self, Debug, Display, Formatter, Write},
+    iter::Peekable,
+    str::Chars,
+    sync::Arc,
+};
 
-use crate::transport::smtp::error::{self, Error};
+#[cfg(any(feature = "tokio1", feature = "async-std1"))]
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use md5::Md5;
+use sha2::{Digest, Sha256};
+
+use crate::transport::smtp::{
+    commands::Auth,
+    error::{self, Error},
+    response::Response,
+};
 
 /// Accepted authentication mechanisms
 ///
@@ -43,6 +59,86 @@ impl Debug for Credentials {
     }
 }
 
+/// Provides the credentials used to authenticate new connections
+///
+/// Useful when the credentials change over time, like OAuth 2.0 access tokens
+/// used with [`Mechanism::Xoauth2`] or [`Mechanism::OAuthBearer`].
+/// See [`SmtpTransportBuilder::credentials_provider`].
+///
+/// [`SmtpTransportBuilder::credentials_provider`]: crate::transport::smtp::SmtpTransportBuilder::credentials_provider
+pub trait CredentialsProvider: Debug + Send + Sync {
+    /// Returns the credentials to authenticate a new connection with
+    fn credentials(&self) -> Result<Credentials, Box<dyn StdError + Send + Sync>>;
+
+    /// Returns fresh credentials after the server rejected `rejected`
+    ///
+    /// Called when authentication fails with a 535 reply, the connection
+    /// is then retried once with the returned credentials.
+    /// By default, [`Self::credentials`] is called again.
+    fn refresh(
+        &self,
+        rejected: &Credentials,
+    ) -> Result<Credentials, Box<dyn StdError + Send + Sync>> {
+        let _ = rejected;
+        self.credentials()
+    }
+}
+
+impl<P: CredentialsProvider + ?Sized> CredentialsProvider for Arc<P> {
+    fn credentials(&self) -> Result<Credentials, Box<dyn StdError + Send + Sync>> {
+        (**self).credentials()
+    }
+
+    fn refresh(
+        &self,
+        rejected: &Credentials,
+    ) -> Result<Credentials, Box<dyn StdError + Send + Sync>> {
+        (**self).refresh(rejected)
+    }
+}
+
+/// Asynchronously provides the credentials used to authenticate new connections
+///
+/// Async counterpart of [`CredentialsProvider`].
+/// See [`AsyncSmtpTransportBuilder::credentials_provider`].
+///
+/// [`AsyncSmtpTransportBuilder::credentials_provider`]: crate::transport::smtp::AsyncSmtpTransportBuilder::credentials_provider
+#[cfg(any(feature = "tokio1", feature = "async-std1"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "tokio1", feature = "async-std1"))))]
+#[async_trait]
+pub trait AsyncCredentialsProvider: Debug + Send + Sync {
+    /// Returns the credentials to authenticate a new connection with
+    async fn credentials(&self) -> Result<Credentials, Box<dyn StdError + Send + Sync>>;
+
+    /// Returns fresh credentials after the server rejected `rejected`
+    ///
+    /// Called when authentication fails with a 535 reply, the connection
+    /// is then retried once with the returned credentials.
+    /// By default, [`Self::credentials`] is called again.
+    async fn refresh(
+        &self,
+        rejected: &Credentials,
+    ) -> Result<Credentials, Box<dyn StdError + Send + Sync>> {
+        let _ = rejected;
+        self.credentials().await
+    }
+}
+
+#[cfg(any(feature = "tokio1", feature = "async-std1"))]
+#[async_trait]
+impl<P: AsyncCredentialsProvider + ?Sized> AsyncCredentialsProvider for Arc<P> {
+    async fn credentials(&self) -> Result<Credentials, Box<dyn StdError + Send + Sync>> {
+        (**self).credentials().await
+    }
+
+    async fn refresh(
+        &self,
+        rejected: &Credentials,
+    ) -> Result<Credentials, Box<dyn StdError + Send + Sync>> {
+        (**self).refresh(rejected).await
+    }
+}
+
 /// Represents authentication mechanisms
 #[derive(PartialEq, Eq, Copy, Clone, Hash, Debug)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
@@ -58,6 +154,25 @@ pub enum Mechanism {
     /// Non-standard XOAUTH2 mechanism, defined in
     /// [xoauth2-protocol](https://developers.google.com/gmail/imap/xoauth2-protocol)
     Xoauth2,
+    /// OAUTHBEARER mechanism, defined in
+    /// [RFC 7628](https://tools.ietf.org/html/rfc7628)
+    ///
+    /// The credentials hold the username and the OAuth 2.0 bearer token. When the
+    /// token is rejected, the details sent by the server are available through
+    /// [`Error::oauth_bearer_error`].
+    OAuthBearer,
+    /// CRAM-MD5 authentication mechanism
+    /// Obsolete but still offered by some servers
+    ///
+    /// Defined in [RFC 2195](https://tools.ietf.org/html/rfc2195).
+    CramMd5,
+    /// SCRAM-SHA-256 authentication mechanism, defined in
+    /// [RFC 7677](https://tools.ietf.org/html/rfc7677)
+    ///
+    /// The server is authenticated too: the exchange fails if it can't
+    /// prove it knows the password. Username and password are used as is,
+    /// without SASLprep normalization. Channel binding is not supported.
+    ScramSha256,
 }
 
 impl Display for Mechanism {
@@ -66,6 +181,9 @@ impl Display for Mechanism {
             Mechanism::Plain => "PLAIN",
             Mechanism::Login => "LOGIN",
             Mechanism::Xoauth2 => "XOAUTH2",
+            Mechanism::OAuthBearer => "OAUTHBEARER",
+            Mechanism::CramMd5 => "CRAM-MD5",
+            Mechanism::ScramSha256 => "SCRAM-SHA-256",
         })
     }
 }
@@ -74,13 +192,19 @@ impl Mechanism {
     /// Does the mechanism support initial response?
     pub fn supports_initial_response(self) -> bool {
         match self {
-            Mechanism::Plain | Mechanism::Xoauth2 => true,
-            Mechanism::Login => false,
+            Mechanism::Plain
+            | Mechanism::Xoauth2
+            | Mechanism::OAuthBearer
+            | Mechanism::ScramSha256 => true,
+            Mechanism::Login | Mechanism::CramMd5 => false,
         }
     }
 
     /// Returns the string to send to the server, using the provided username, password and
     /// challenge in some cases
+    ///
+    /// SCRAM-SHA-256 needs to keep state between its steps, it is only available
+    /// through [`SmtpConnection::auth`](super::client::SmtpConnection::auth).
     pub fn response(
         self,
         credentials: &Credentials,
@@ -121,10 +245,441 @@ impl Mechanism {
                     credentials.authentication_identity, credentials.secret
                 )),
             },
+            Mechanism::OAuthBearer => match challenge {
+                // The server sent an error, a dummy response ends the exchange
+                Some(_) => Ok("\x01".to_owned()),
+                None => Ok(oauth_bearer_response(credentials, None)),
+            },
+            Mechanism::CramMd5 => {
+                let challenge = challenge
+                    .ok_or_else(|| error::client("This mechanism does expect a challenge"))?;
+
+                let mut mac = Hmac::<Md5>::new_from_slice(credentials.secret.as_bytes())
+                    .map_err(error::client)?;
+                mac.update(challenge.as_bytes());
+
+                let mut response = credentials.authentication_identity.clone();
+                response.push(' ');
+                for byte in mac.finalize().into_bytes() {
+                    write!(response, "{byte:02x}").unwrap();
+                }
+                Ok(response)
+            }
+            Mechanism::ScramSha256 => {
+                Err(error::client("This mechanism needs a stateful exchange"))
+            }
+        }
+    }
+}
+
+/// Client side of an authentication exchange
+///
+/// Keeps the state multi-step mechanisms need between two challenges.
+pub(crate) struct Exchange {
+    mechanism: Mechanism,
+    credentials: Credentials,
+    /// Host name and port of the server, when known
+    server: Option<(String, u16)>,
+    scram: Option<Scram>,
+    oauth_bearer_error: Option<OAuthBearerError>,
+}
+
+impl Exchange {
+    pub(crate) fn new(
+        mechanism: Mechanism,
+        credentials: &Credentials,
+        server: Option<(&str, u16)>,
+    ) -> Self {
+        Self {
+            mechanism,
+            credentials: credentials.clone(),
+            server: server.map(|(host, port)| (host.to_owned(), port)),
+            scram: None,
+            oauth_bearer_error: None,
+        }
+    }
+
+    /// Returns the `AUTH` command starting the exchange
+    pub(crate) fn start(&mut self) -> Result<Auth, Error> {
+        match self.mechanism {
+            Mechanism::ScramSha256 => {
+                let scram = Scram::new(&self.credentials.authentication_identity, nonce()?);
+                let response = scram.client_first();
+                self.scram = Some(scram);
+                Ok(Auth::with_response(
+                    self.mechanism,
+                    self.credentials.clone(),
+                    None,
+                    response,
+                ))
+            }
+            Mechanism::OAuthBearer => Ok(Auth::with_response(
+                self.mechanism,
+                self.credentials.clone(),
+                None,
+                oauth_bearer_response(
+                    &self.credentials,
+                    self.server
+                        .as_ref()
+                        .map(|(host, port)| (host.as_str(), *port)),
+                ),
+            )),
+            _ => Auth::new(self.mechanism, self.credentials.clone(), None),
+        }
+    }
+
+    /// Returns the answer to a challenge (with 334 response code)
+    pub(crate) fn respond(&mut self, response: &Response) -> Result<Auth, Error> {
+        if self.mechanism == Mechanism::OAuthBearer {
+            let challenge = Auth::decode_challenge(response)?;
+            self.oauth_bearer_error = OAuthBearerError::from_json(&challenge);
+        }
+
+        let Some(scram) = &mut self.scram else {
+            return Auth::new_from_response(self.mechanism, self.credentials.clone(), response);
+        };
+
+        let challenge = Auth::decode_challenge(response)?;
+        let answer = scram.respond(&self.credentials.secret, &challenge)?;
+        Ok(Auth::with_response(
+            self.mechanism,
+            self.credentials.clone(),
+            Some(challenge),
+            answer,
+        ))
+    }
+
+    /// Checks the exchange is complete once the server accepted it
+    pub(crate) fn finish(&self) -> Result<(), Error> {
+        match &self.scram {
+            Some(scram) if !scram.server_verified() => Err(error::response(
+                "The server did not prove it knows the password",
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Attaches the details the server sent before rejecting the exchange
+    pub(crate) fn error(&mut self, err: Error) -> Error {
+        match (self.oauth_bearer_error.take(), err.status()) {
+            (Some(oauth_bearer_error), Some(code)) => error::code(code, Some(oauth_bearer_error)),
+            _ => err,
+        }
+    }
+}
+
+/// Error sent by the server when it rejects an OAUTHBEARER token
+///
+/// Defined in [RFC 7628, section 3.2.2](https://tools.ietf.org/html/rfc7628#section-3.2.2).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OAuthBearerError {
+    status: String,
+    scope: Option<String>,
+    openid_configuration: Option<String>,
+}
+
+impl OAuthBearerError {
+    /// Status of the authentication, like `invalid_token`
+    pub fn status(&self) -> &str {
+        &self.status
+    }
+
+    /// Scope the token needs to be granted
+    pub fn scope(&self) -> Option<&str> {
+        self.scope.as_deref()
+    }
+
+    /// URL of the OpenID Connect discovery document of the authorization server
+    pub fn openid_configuration(&self) -> Option<&str> {
+        self.openid_configuration.as_deref()
+    }
+
+    fn from_json(json: &str) -> Option<Self> {
+        let mut status = None;
+        let mut scope = None;
+        let mut openid_configuration = None;
+
+        for (key, value) in parse_json_object(json)? {
+            match key.as_str() {
+                "status" => status = Some(value),
+                "scope" => scope = Some(value),
+                "openid-configuration" => openid_configuration = Some(value),
+                _ => {}
+            }
+        }
+
+        Some(Self {
+            status: status?,
+            scope,
+            openid_configuration,
+        })
+    }
+}
+
+impl Display for OAuthBearerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "OAUTHBEARER authentication failed with status {}",
+            self.status
+        )?;
+        if let Some(scope) = &self.scope {
+            write!(f, " (scope: {scope})")?;
         }
+        Ok(())
     }
 }
 
+impl std::error::Error for OAuthBearerError {}
+
+/// Builds the OAUTHBEARER initial client response
+///
+/// [RFC 7628, section 3.1](https://tools.ietf.org/html/rfc7628#section-3.1)
+fn oauth_bearer_response(credentials: &Credentials, server: Option<(&str, u16)>) -> String {
+    let mut response = String::from("n,");
+    if !credentials.authentication_identity.is_empty() {
+        write!(
+            response,
+            "a={}",
+            saslname(&credentials.authentication_identity)
+        )
+        .unwrap();
+    }
+    response.push_str(",\x01");
+    if let Some((host, port)) = server {
+        write!(response, "host={host}\x01port={port}\x01").unwrap();
+    }
+    write!(response, "auth=Bearer {}\x01\x01", credentials.secret).unwrap();
+    response
+}
+
+/// Parses a flat JSON object, keeping string values only
+fn parse_json_object(json: &str) -> Option<Vec<(String, String)>> {
+    fn skip_whitespace(chars: &mut Peekable<Chars<'_>>) {
+        while chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+    }
+
+    fn parse_string(chars: &mut Peekable<Chars<'_>>) -> Option<String> {
+        if chars.next()? != '"' {
+            return None;
+        }
+
+        let mut string = String::new();
+        loop {
+            match chars.next()? {
+                '"' => return Some(string),
+                '\\' => match chars.next()? {
+                    'b' => string.push('\u{8}'),
+                    'f' => string.push('\u{c}'),
+                    'n' => string.push('\n'),
+                    'r' => string.push('\r'),
+                    't' => string.push('\t'),
+                    'u' => {
+                        let mut code = parse_hex4(chars)?;
+                        if (0xD800..0xDC00).contains(&code) {
+                            // Surrogate pair
+                            if chars.next()? != '\\' || chars.next()? != 'u' {
+                                return None;
+                            }
+                            let low = parse_hex4(chars)?;
+                            code = 0x10000 + ((code - 0xD800) << 10) + low.checked_sub(0xDC00)?;
+                        }
+                        string.push(char::from_u32(code)?);
+                    }
+                    c @ ('"' | '\\' | '/') => string.push(c),
+                    _ => return None,
+                },
+                c => string.push(c),
+            }
+        }
+    }
+
+    fn parse_hex4(chars: &mut Peekable<Chars<'_>>) -> Option<u32> {
+        (0..4).try_fold(0, |code, _| Some(code * 16 + chars.next()?.to_digit(16)?))
+    }
+
+    let mut chars = json.chars().peekable();
+    let mut object = Vec::new();
+
+    skip_whitespace(&mut chars);
+    if chars.next()? != '{' {
+        return None;
+    }
+    skip_whitespace(&mut chars);
+    if chars.next_if_eq(&'}').is_none() {
+        loop {
+            skip_whitespace(&mut chars);
+            let key = parse_string(&mut chars)?;
+            skip_whitespace(&mut chars);
+            if chars.next()? != ':' {
+                return None;
+            }
+            skip_whitespace(&mut chars);
+            if chars.peek() == Some(&'"') {
+                object.push((key, parse_string(&mut chars)?));
+            } else {
+                // Numbers, booleans and null are not used by RFC 7628
+                while chars.next_if(|c| !matches!(c, ',' | '}')).is_some() {}
+            }
+            skip_whitespace(&mut chars);
+            match chars.next()? {
+                ',' => continue,
+                '}' => break,
+                _ => return None,
+            }
+        }
+    }
+    skip_whitespace(&mut chars);
+
+    chars.next().is_none().then_some(object)
+}
+
+/// Escapes a username for SCRAM and GS2 headers
+fn saslname(name: &str) -> String {
+    name.replace('=', "=3D").replace(',', "=2C")
+}
+
+/// State of a SCRAM-SHA-256 exchange
+///
+/// [RFC 5802, section 3](https://tools.ietf.org/html/rfc5802#section-3)
+struct Scram {
+    /// `client-first-message-bare`
+    client_first_bare: String,
+    client_nonce: String,
+    step: ScramStep,
+}
+
+enum ScramStep {
+    /// Waiting for the `server-first-message`
+    ServerFirst,
+    /// Waiting for the `server-final-message`, holding the expected `ServerSignature`
+    ServerFinal(Vec<u8>),
+    /// The server signature was verified
+    Done,
+}
+
+/// GS2 header without channel binding nor authorization identity
+const SCRAM_GS2_HEADER: &str = "n,,";
+/// Highest iteration count accepted from the server, as each iteration costs
+/// the client an HMAC computation
+const SCRAM_MAX_ITERATIONS: u32 = 1_000_000;
+
+impl Scram {
+    fn new(username: &str, client_nonce: String) -> Self {
+        Self {
+            client_first_bare: format!("n={},r={client_nonce}", saslname(username)),
+            client_nonce,
+            step: ScramStep::ServerFirst,
+        }
+    }
+
+    fn client_first(&self) -> String {
+        format!("{SCRAM_GS2_HEADER}{}", self.client_first_bare)
+    }
+
+    fn respond(&mut self, password: &str, challenge: &str) -> Result<String, Error> {
+        match self.step {
+            ScramStep::ServerFirst => {
+                let (response, server_signature) = self.client_final(password, challenge)?;
+                self.step = ScramStep::ServerFinal(server_signature);
+                Ok(response)
+            }
+            ScramStep::ServerFinal(ref server_signature) => {
+                if let Some(e) = scram_attribute(challenge, 'e') {
+                    return Err(error::response(format!("SCRAM authentication failed: {e}")));
+                }
+                let verifier = scram_attribute(challenge, 'v')
+                    .ok_or_else(|| error::response("Missing SCRAM server signature"))?;
+                let verifier = crate::base64::decode(verifier).map_err(error::response)?;
+                if &verifier != server_signature {
+                    return Err(error::response("Invalid SCRAM server signature"));
+                }
+
+                self.step = ScramStep::Done;
+                Ok(String::new())
+            }
+            ScramStep::Done => Err(error::response("Unexpected SCRAM challenge")),
+        }
+    }
+
+    /// Computes the `client-final-message` and the expected `ServerSignature`
+    fn client_final(&self, password: &str, server_first: &str) -> Result<(String, Vec<u8>), Error> {
+        if scram_attribute(server_first, 'm').is_some() {
+            return Err(error::response("Unsupported SCRAM extension"));
+        }
+        let nonce = scram_attribute(server_first, 'r')
+            .filter(|nonce| nonce.starts_with(&self.client_nonce))
+            .ok_or_else(|| error::response("Invalid SCRAM nonce"))?;
+        let salt = scram_attribute(server_first, 's')
+            .ok_or_else(|| error::response("Missing SCRAM salt"))?;
+        let salt = crate::base64::decode(salt).map_err(error::response)?;
+        let iterations = scram_attribute(server_first, 'i')
+            .and_then(|i| i.parse::<u32>().ok())
+            .filter(|i| *i > 0)
+            .ok_or_else(|| error::response("Invalid SCRAM iteration count"))?;
+        if iterations > SCRAM_MAX_ITERATIONS {
+            return Err(error::response(format!(
+                "SCRAM iteration count {iterations} is higher than {SCRAM_MAX_ITERATIONS}"
+            )));
+        }
+
+        let mut salted_password = [0; 32];
+        pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), &salt, iterations, &mut salted_password);
+        let client_key = hmac_sha256(&salted_password, b"Client Key");
+        let stored_key = Sha256::digest(&client_key);
+        let server_key = hmac_sha256(&salted_password, b"Server Key");
+
+        let client_final_without_proof =
+            format!("c={},r={nonce}", crate::base64::encode(SCRAM_GS2_HEADER));
+        let auth_message = format!(
+            "{},{server_first},{client_final_without_proof}",
+            self.client_first_bare
+        );
+
+        let client_signature = hmac_sha256(&stored_key, auth_message.as_bytes());
+        let client_proof = client_key
+            .iter()
+            .zip(client_signature)
+            .map(|(key, signature)| key ^ signature)
+            .collect::<Vec<_>>();
+        let server_signature = hmac_sha256(&server_key, auth_message.as_bytes());
+
+        Ok((
+            format!(
+                "{client_final_without_proof},p={}",
+                crate::base64::encode(client_proof)
+            ),
+            server_signature,
+        ))
+    }
+
+    fn server_verified(&self) -> bool {
+        matches!(self.step, ScramStep::Done)
+    }
+}
+
+/// Finds the value of an attribute in a SCRAM message
+fn scram_attribute(message: &str, name: char) -> Option<&str> {
+    message.split(',').find_map(|attribute| {
+        attribute
+            .strip_prefix(name)
+            .and_then(|value| value.strip_prefix('='))
+    })
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Generates a random printable nonce
+fn nonce() -> Result<String, Error> {
+    let mut bytes = [0; 18];
+    getrandom::getrandom(&mut bytes).map_err(error::client)?;
+    Ok(crate::base64::encode(bytes))
+}
+
 fn contains_ignore_ascii_case<'a>(
     haystack: &str,
     needles: impl IntoIterator<Item = &'a str>,
@@ -136,7 +691,11 @@ fn contains_ignore_ascii_case<'a>(
 
 #[cfg(test)]
 mod test {
-    use super::{Credentials, Mechanism};
+    use super::{
+        oauth_bearer_response, parse_json_object, Credentials, Exchange, Mechanism,
+        OAuthBearerError, Scram,
+    };
+    use crate::transport::smtp::response::Response;
 
     #[test]
     fn test_plain() {
@@ -201,6 +760,167 @@ mod test {
         assert!(mechanism.response(&credentials, Some("test")).is_err());
     }
 
+    #[test]
+    fn test_oauth_bearer() {
+        let mechanism = Mechanism::OAuthBearer;
+
+        let credentials = Credentials::new(
+            "user@example.com".to_owned(),
+            "vF9dft4qmTc2Nvb3RlckBhdHRhdmlzdGEuY29tCg==".to_owned(),
+        );
+
+        // Example from RFC 7628
+        assert_eq!(
+            crate::base64::encode(oauth_bearer_response(
+                &credentials,
+                Some(("server.example.com", 143))
+            )),
+            "bixhPXVzZXJAZXhhbXBsZS5jb20sAWhvc3Q9c2VydmVyLmV4YW1wbGUuY29tAXBvcnQ9MTQzAWF1dGg9QmVhcmVyIHZGOWRmdDRxbVRjMk52YjNSbGNrQmhkSFJoZG1semRHRXVZMjl0Q2c9PQEB"
+        );
+        assert_eq!(
+            mechanism.response(&credentials, None).unwrap(),
+            "n,a=user@example.com,\x01auth=Bearer vF9dft4qmTc2Nvb3RlckBhdHRhdmlzdGEuY29tCg==\x01\x01"
+        );
+        assert_eq!(
+            mechanism.response(&credentials, Some("{}")).unwrap(),
+            "\x01"
+        );
+    }
+
+    #[test]
+    fn test_oauth_bearer_error() {
+        let credentials = Credentials::new("user@example.com".to_owned(), "token".to_owned());
+        let mut exchange = Exchange::new(
+            Mechanism::OAuthBearer,
+            &credentials,
+            Some(("server.example.com", 587)),
+        );
+        assert_eq!(
+            exchange.start().unwrap().to_string(),
+            format!(
+                "AUTH OAUTHBEARER {}\r\n",
+                crate::base64::encode(
+                    "n,a=user@example.com,\x01host=server.example.com\x01port=587\x01auth=Bearer token\x01\x01"
+                )
+            )
+        );
+
+        // Example from RFC 7628
+        let challenge = "334 eyJzdGF0dXMiOiJpbnZhbGlkX3Rva2VuIiwic2NvcGUiOiJleGFtcGxlX3Njb3BlIiwib3BlbmlkLWNvbmZpZ3VyYXRpb24iOiJodHRwczovL2V4YW1wbGUuY29tLy53ZWxsLWtub3duL29wZW5pZC1jb25maWd1cmF0aW9uIn0=\r\n"
+            .parse::<Response>()
+            .unwrap();
+        assert_eq!(
+            exchange.respond(&challenge).unwrap().to_string(),
+            "AQ==\r\n"
+        );
+
+        let rejected = "535 5.7.8 Authentication credentials invalid\r\n"
+            .parse::<Response>()
+            .unwrap();
+        let error = exchange.error(rejected.into_result().unwrap_err());
+        assert!(error.is_permanent());
+        let oauth_bearer_error = error.oauth_bearer_error().unwrap();
+        assert_eq!(oauth_bearer_error.status(), "invalid_token");
+        assert_eq!(oauth_bearer_error.scope(), Some("example_scope"));
+        assert_eq!(
+            oauth_bearer_error.openid_configuration(),
+            Some("https://example.com/.well-known/openid-configuration")
+        );
+    }
+
+    #[test]
+    fn test_parse_json_object() {
+        assert_eq!(
+            parse_json_object(r#" { "a" : "b\"\u00e9\ud83d\ude00", "n": 42, "c":"d" } "#).unwrap(),
+            [
+                ("a".to_owned(), "b\"é😀".to_owned()),
+                ("c".to_owned(), "d".to_owned())
+            ]
+        );
+        assert_eq!(parse_json_object("{}").unwrap(), []);
+        assert!(parse_json_object("{\"a\":\"b\"").is_none());
+        assert!(parse_json_object("{\"a\":\"b\"} x").is_none());
+        assert!(OAuthBearerError::from_json("{\"scope\":\"a\"}").is_none());
+    }
+
+    #[test]
+    fn test_cram_md5() {
+        let mechanism = Mechanism::CramMd5;
+
+        let credentials = Credentials::new("tim".to_owned(), "tanstaaftanstaaf".to_owned());
+
+        assert_eq!(
+            mechanism
+                .response(
+                    &credentials,
+                    Some("<1896.697170952@postoffice.reston.mci.net>")
+                )
+                .unwrap(),
+            "tim b913a602c7eda7a495b4e6e7334d3890"
+        );
+        assert!(mechanism.response(&credentials, None).is_err());
+    }
+
+    #[test]
+    fn test_scram_sha256() {
+        // Example from RFC 7677
+        let mut scram = Scram::new("user", "rOprNGfwEbeRWgbNEkqO".to_owned());
+
+        assert_eq!(scram.client_first(), "n,,n=user,r=rOprNGfwEbeRWgbNEkqO");
+        assert_eq!(
+            scram
+                .respond(
+                    "pencil",
+                    "r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096"
+                )
+                .unwrap(),
+            "c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ="
+        );
+        assert!(!scram.server_verified());
+        assert_eq!(
+            scram
+                .respond("pencil", "v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=")
+                .unwrap(),
+            ""
+        );
+        assert!(scram.server_verified());
+        assert!(Mechanism::ScramSha256
+            .response(
+                &Credentials::new("user".to_owned(), "pencil".to_owned()),
+                None
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_scram_sha256_invalid_server() {
+        let mut scram = Scram::new("user", "rOprNGfwEbeRWgbNEkqO".to_owned());
+        assert!(scram
+            .respond("pencil", "r=other,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096")
+            .is_err());
+
+        // Not computed, it would take hours
+        let mut scram = Scram::new("user", "rOprNGfwEbeRWgbNEkqO".to_owned());
+        assert!(scram
+            .respond(
+                "pencil",
+                "r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4294967295",
+            )
+            .is_err());
+
+        let mut scram = Scram::new("user", "rOprNGfwEbeRWgbNEkqO".to_owned());
+        scram
+            .respond(
+                "pencil",
+                "r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096",
+            )
+            .unwrap();
+        assert!(scram
+            .respond("pencil", "v=AAAATRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=")
+            .is_err());
+        assert!(!scram.server_verified());
+    }
+
     #[test]
     fn test_from_user_pass_for_credentials() {
         assert_eq!(
@@ -6,11 +6,14 @@ use futures_util::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use super::async_net::AsyncTokioStream;
 #[cfg(feature = "tracing")]
 use super::escape_crlf;
-use super::{AsyncNetworkStream, ClientCodec, TlsParameters};
+use super::{
+    is_binary, AsyncNetworkStream, ClientCodec, Recipients, SocketOptions, TlsParameters,
+    BDAT_CHUNK_SIZE,
+};
 use crate::{
     transport::smtp::{
-        authentication::{Credentials, Mechanism},
-        commands::{Auth, Data, Ehlo, Mail, Noop, Quit, Rcpt, Starttls},
+        authentication::{Credentials, Exchange, Mechanism},
+        commands::{Bdat, Data, Ehlo, Mail, Noop, Quit, Rcpt, Rset, Starttls},
         error,
         error::Error,
         extension::{ClientId, Extension, MailBodyParameter, MailParameter, ServerInfo},
@@ -38,8 +41,12 @@ pub struct AsyncSmtpConnection {
     stream: BufReader<AsyncNetworkStream>,
     /// Panic state
     panic: bool,
+    /// Whether the content of the last email was sent
+    content_sent: bool,
     /// Information about the server
     server_info: ServerInfo,
+    /// Host name and port of the server, when known
+    server_address: Option<(String, u16)>,
 }
 
 impl AsyncSmtpConnection {
@@ -48,6 +55,13 @@ impl AsyncSmtpConnection {
         &self.server_info
     }
 
+    /// Sets the host name and port the connection was opened to
+    ///
+    /// Some authentication mechanisms include them in their messages.
+    pub(crate) fn set_server_address(&mut self, host: &str, port: u16) {
+        self.server_address = Some((host.to_owned(), port));
+    }
+
     /// Connects with existing async stream
     ///
     /// Sends EHLO and parses server information
@@ -105,6 +119,25 @@ impl AsyncSmtpConnection {
         Self::connect_impl(stream, hello_name).await
     }
 
+    /// Connects to the configured server, with sockets configured by `socket_options`
+    #[cfg(feature = "tokio1")]
+    pub(crate) async fn connect_tokio1_with_options<T: tokio1_crate::net::ToSocketAddrs>(
+        server: T,
+        timeout: Option<Duration>,
+        hello_name: &ClientId,
+        tls_parameters: Option<TlsParameters>,
+        socket_options: &SocketOptions,
+    ) -> Result<AsyncSmtpConnection, Error> {
+        let stream = AsyncNetworkStream::connect_tokio1_with_options(
+            server,
+            timeout,
+            tls_parameters,
+            socket_options,
+        )
+        .await?;
+        Self::connect_impl(stream, hello_name).await
+    }
+
     /// Connects to the configured server
     ///
     /// Sends EHLO and parses server information
@@ -119,6 +152,25 @@ impl AsyncSmtpConnection {
         Self::connect_impl(stream, hello_name).await
     }
 
+    /// Connects to the configured server, with sockets configured by `socket_options`
+    #[cfg(feature = "async-std1")]
+    pub(crate) async fn connect_asyncstd1_with_options<T: async_std::net::ToSocketAddrs>(
+        server: T,
+        timeout: Option<Duration>,
+        hello_name: &ClientId,
+        tls_parameters: Option<TlsParameters>,
+        socket_options: &SocketOptions,
+    ) -> Result<AsyncSmtpConnection, Error> {
+        let stream = AsyncNetworkStream::connect_asyncstd1_with_options(
+            server,
+            timeout,
+            tls_parameters,
+            socket_options,
+        )
+        .await?;
+        Self::connect_impl(stream, hello_name).await
+    }
+
     async fn connect_impl(
         stream: AsyncNetworkStream,
         hello_name: &ClientId,
@@ -127,7 +179,9 @@ impl AsyncSmtpConnection {
         let mut conn = AsyncSmtpConnection {
             stream,
             panic: false,
+            content_sent: false,
             server_info: ServerInfo::default(),
+            server_address: None,
         };
         // TODO log
         let _response = conn.read_response().await?;
@@ -141,6 +195,31 @@ impl AsyncSmtpConnection {
     }
 
     pub async fn send(&mut self, envelope: &Envelope, email: &[u8]) -> Result<Response, Error> {
+        self.send_inner(envelope, email, false).await
+    }
+
+    /// Sends an email, even if the server rejects some of its recipients
+    ///
+    /// The message is delivered to the accepted recipients, and the returned
+    /// [`Response`] holds the `RCPT TO` response of each recipient, see
+    /// [`Response::accepted_recipients`] and [`Response::rejected_recipients`].
+    /// An error is only returned if every recipient is rejected.
+    pub async fn send_partial(
+        &mut self,
+        envelope: &Envelope,
+        email: &[u8],
+    ) -> Result<Response, Error> {
+        self.send_inner(envelope, email, true).await
+    }
+
+    async fn send_inner(
+        &mut self,
+        envelope: &Envelope,
+        email: &[u8],
+        partial: bool,
+    ) -> Result<Response, Error> {
+        self.content_sent = false;
+
         // Mail
         let mut mail_options = vec![];
 
@@ -160,8 +239,19 @@ impl AsyncSmtpConnection {
             mail_options.push(MailParameter::SmtpUtfEight);
         }
 
-        // Check for non-ascii content in the message
-        if !email.is_ascii() {
+        // Binary content handling
+        //
+        // * CHUNKING and BINARYMIME: https://tools.ietf.org/html/rfc3030
+        //
+        // BDAT is used whenever available, as it doesn't need dot-stuffing.
+        let chunking = self.server_info().supports_feature(Extension::Chunking);
+        if chunking
+            && self.server_info().supports_feature(Extension::BinaryMime)
+            && is_binary(email)
+        {
+            mail_options.push(MailParameter::Body(MailBodyParameter::BinaryMime));
+        } else if !email.is_ascii() {
+            // Check for non-ascii content in the message
             if !self.server_info().supports_feature(Extension::EightBitMime) {
                 return Err(error::client(
                     "Message contains non-ascii chars but server does not support 8BITMIME",
@@ -170,32 +260,192 @@ impl AsyncSmtpConnection {
             mail_options.push(MailParameter::Body(MailBodyParameter::EightBitMime));
         }
 
-        try_smtp!(
-            self.command(Mail::new(envelope.from().cloned(), mail_options))
-                .await,
-            self
-        );
+        // Message size declaration: https://tools.ietf.org/html/rfc1870
+        if self.server_info().supports_feature(Extension::Size) {
+            if let Some(max_size) = self.server_info().max_size() {
+                if email.len() > max_size {
+                    return Err(error::message_too_large(email.len(), max_size));
+                }
+            }
+            if !envelope
+                .mail_parameters()
+                .iter()
+                .any(|parameter| matches!(parameter, MailParameter::Size(_)))
+            {
+                mail_options.push(MailParameter::Size(email.len()));
+            }
+        }
 
-        // Recipient
-        for to_address in envelope.to() {
-            try_smtp!(
-                self.command(Rcpt::new(to_address.clone(), vec![])).await,
-                self
-            );
+        // Delivery status notifications: https://tools.ietf.org/html/rfc3461
+        if envelope.has_dsn_parameters() && !self.server_info().supports_feature(Extension::Dsn) {
+            return Err(error::client(
+                "Envelope contains DSN parameters but server does not support DSN",
+            ));
         }
 
-        // Data
-        try_smtp!(self.command(Data).await, self);
+        // Message transfer priorities: https://tools.ietf.org/html/rfc6710
+        for parameter in envelope.mail_parameters() {
+            if let MailParameter::MtPriority(priority) = parameter {
+                if !self.server_info().supports_feature(Extension::MtPriority) {
+                    return Err(error::client(
+                        "Envelope has a priority but server does not support MT-PRIORITY",
+                    ));
+                }
+                if !(-9..=9).contains(priority) {
+                    return Err(error::client("MT-PRIORITY must be between -9 and 9"));
+                }
+            }
+        }
+
+        // Requiring TLS: https://tools.ietf.org/html/rfc8689
+        //
+        // Never send such messages in cleartext, nor to a server which
+        // could relay them in cleartext.
+        if envelope
+            .mail_parameters()
+            .contains(&MailParameter::RequireTls)
+        {
+            if !self.is_encrypted() {
+                return Err(error::client(
+                    "Envelope requires TLS but the connection is not encrypted",
+                ));
+            }
+            if !self.server_info().supports_feature(Extension::RequireTls) {
+                return Err(error::client(
+                    "Envelope requires TLS but server does not support REQUIRETLS",
+                ));
+            }
+        }
+
+        mail_options.extend(envelope.mail_parameters().iter().cloned());
+
+        let mail = Mail::new(envelope.from().cloned(), mail_options);
+        let rcpts = envelope
+            .to()
+            .iter()
+            .map(|to_address| {
+                Rcpt::new(
+                    to_address.clone(),
+                    envelope.rcpt_parameters(to_address).cloned().collect(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let mut recipients = Recipients::new(partial);
+
+        if self.server_info.supports_feature(Extension::Pipelining) {
+            // PIPELINING: https://tools.ietf.org/html/rfc2920
+            //
+            // Send MAIL, RCPT and DATA (unless using BDAT) as a single group, then read
+            // all the responses so that the first failure is reported
+            let mut commands = mail.to_string();
+            for rcpt in &rcpts {
+                commands.push_str(&rcpt.to_string());
+            }
+            if !chunking {
+                commands.push_str(&Data.to_string());
+            }
+            try_smtp!(self.write(commands.as_bytes()).await, self);
+
+            let mut first_error = None;
+            let mut broken = false;
+            let mut rcpt_accepted = false;
+            let mut data_accepted = false;
+            for i in 0..rcpts.len() + if chunking { 1 } else { 2 } {
+                let reply = match self.read_reply().await {
+                    Ok(reply) => reply,
+                    Err(err) => {
+                        // The connection is unusable, stop reading
+                        first_error.get_or_insert(err);
+                        broken = true;
+                        break;
+                    }
+                };
+
+                if i == rcpts.len() + 1 {
+                    data_accepted = reply.is_positive();
+                }
+                let result = if (1..=rcpts.len()).contains(&i) {
+                    rcpt_accepted |= reply.is_positive();
+                    recipients.push(&envelope.to()[i - 1], reply)
+                } else {
+                    reply.into_result().map(|_| ())
+                };
+                if let Err(err) = result {
+                    first_error.get_or_insert(err);
+                }
+
+                if i == rcpts.len() {
+                    // Report the rejections rather than the failed DATA
+                    if let Err(err) = recipients.check() {
+                        first_error.get_or_insert(err);
+                    }
+                }
+            }
+            if let Some(err) = first_error {
+                if data_accepted && rcpt_accepted {
+                    // The server now expects the message content for the accepted
+                    // recipients, which can only be cancelled by closing the connection
+                    self.panic = true;
+                    self.abort().await;
+                } else if broken || self.close_transaction(data_accepted).await.is_err() {
+                    self.abort().await;
+                }
+                return Err(err);
+            }
+        } else {
+            try_smtp!(self.command(mail).await, self);
+
+            // Recipient
+            for (rcpt, to) in rcpts.into_iter().zip(envelope.to()) {
+                let reply = try_smtp!(self.command_reply(rcpt).await, self);
+                try_smtp!(recipients.push(to, reply), self);
+            }
+            try_smtp!(recipients.check(), self);
+
+            // Data
+            if !chunking {
+                try_smtp!(self.command(Data).await, self);
+            }
+        }
 
         // Message content
-        let result = try_smtp!(self.message(email).await, self);
-        Ok(result)
+        self.content_sent = true;
+        let result = if chunking {
+            self.message_chunks(email).await
+        } else {
+            self.message(email).await
+        };
+        let result = try_smtp!(result.map_err(Error::content_sent), self);
+        Ok(recipients.into_response(result))
+    }
+
+    /// Ends a transaction the server refused, keeping the connection usable
+    ///
+    /// When the server accepted DATA although it rejected every recipient, an
+    /// empty message is sent to close the transaction, which the server rejects.
+    /// See [RFC 2920, section 3.1](https://tools.ietf.org/html/rfc2920#section-3.1).
+    async fn close_transaction(&mut self, data_accepted: bool) -> Result<(), Error> {
+        if data_accepted {
+            self.write(b".\r\n").await?;
+            self.read_reply().await?;
+        } else {
+            self.command(Rset).await?;
+        }
+        Ok(())
     }
 
     pub fn has_broken(&self) -> bool {
         self.panic
     }
 
+    /// Returns true if sending the last email went as far as sending its content
+    ///
+    /// When it didn't, the server can't have accepted the email.
+    pub fn has_sent_content(&self) -> bool {
+        self.content_sent
+    }
+
     pub fn can_starttls(&self) -> bool {
         !self.is_encrypted() && self.server_info.supports_feature(Extension::StartTls)
     }
@@ -273,19 +523,19 @@ impl AsyncSmtpConnection {
 
         // Limit challenges to avoid blocking
         let mut challenges: u8 = 10;
-        let mut response = self
-            .command(Auth::new(mechanism, credentials.clone(), None)?)
-            .await?;
+        let server_address = self
+            .server_address
+            .as_ref()
+            .map(|(host, port)| (host.as_str(), *port));
+        let mut exchange = Exchange::new(mechanism, credentials, server_address);
+        let mut response = self.command(exchange.start()?).await?;
 
         while challenges > 0 && response.has_code(334) {
             challenges -= 1;
             response = try_smtp!(
-                self.command(Auth::new_from_response(
-                    mechanism,
-                    credentials.clone(),
-                    &response,
-                )?)
-                .await,
+                self.command(exchange.respond(&response)?)
+                    .await
+                    .map_err(|err| exchange.error(err)),
                 self
             );
         }
@@ -293,6 +543,7 @@ impl AsyncSmtpConnection {
         if challenges == 0 {
             Err(error::response("Unexpected number of challenges"))
         } else {
+            try_smtp!(exchange.finish(), self);
             Ok(response)
         }
     }
@@ -307,10 +558,31 @@ impl AsyncSmtpConnection {
         self.read_response().await
     }
 
+    /// Sends the message content in `BDAT` chunks, without transparency
+    pub async fn message_chunks(&mut self, message: &[u8]) -> Result<Response, Error> {
+        let mut chunks = message.chunks(BDAT_CHUNK_SIZE).peekable();
+        loop {
+            let chunk = chunks.next().unwrap_or_default();
+            let last = chunks.peek().is_none();
+            self.write(Bdat::new(chunk.len(), last).to_string().as_bytes())
+                .await?;
+            self.write(chunk).await?;
+            let response = self.read_response().await?;
+            if last {
+                return Ok(response);
+            }
+        }
+    }
+
     /// Sends an SMTP command
     pub async fn command<C: Display>(&mut self, command: C) -> Result<Response, Error> {
+        self.command_reply(command).await?.into_result()
+    }
+
+    /// Sends an SMTP command and returns the reply, even if it is negative
+    async fn command_reply<C: Display>(&mut self, command: C) -> Result<Response, Error> {
         self.write(command.to_string().as_bytes()).await?;
-        self.read_response().await
+        self.read_reply().await
     }
 
     /// Writes a string to the server
@@ -333,6 +605,11 @@ impl AsyncSmtpConnection {
 
     /// Gets the SMTP response
     pub async fn read_response(&mut self) -> Result<Response, Error> {
+        self.read_reply().await?.into_result()
+    }
+
+    /// Gets the SMTP response, even if it is negative
+    async fn read_reply(&mut self) -> Result<Response, Error> {
         let mut buffer = String::with_capacity(100);
 
         while self
@@ -345,16 +622,7 @@ impl AsyncSmtpConnection {
             #[cfg(feature = "tracing")]
             tracing::debug!("<< {}", escape_crlf(&buffer));
             match parse_response(&buffer) {
-                Ok((_remaining, response)) => {
-                    return if response.is_positive() {
-                        Ok(response)
-                    } else {
-                        Err(error::code(
-                            response.code(),
-                            Some(response.message().collect()),
-                        ))
-                    }
-                }
+                Ok((_remaining, response)) => return Ok(response),
                 Err(nom::Err::Failure(e)) => {
                     return Err(error::response(e.to_string()));
                 }
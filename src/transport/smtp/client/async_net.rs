@@ -1,7 +1,9 @@
 use std::{
-    fmt, io, mem,
+    fmt,
+    future::Future,
+    io, mem,
     net::{IpAddr, SocketAddr},
-    pin::Pin,
+    pin::{pin, Pin},
     task::{Context, Poll},
     time::Duration,
 };
@@ -14,8 +16,13 @@ use futures_io::{
 };
 #[cfg(feature = "async-std1-rustls-tls")]
 use futures_rustls::client::TlsStream as AsyncStd1RustlsTlsStream;
+use futures_util::{
+    future::{self, Either},
+    stream::{FuturesUnordered, StreamExt},
+};
 #[cfg(any(feature = "tokio1-rustls-tls", feature = "async-std1-rustls-tls"))]
 use rustls::pki_types::ServerName;
+use socket2::SockRef;
 #[cfg(feature = "tokio1-boring-tls")]
 use tokio1_boring::SslStream as Tokio1SslStream;
 #[cfg(feature = "tokio1")]
@@ -37,7 +44,8 @@ use tokio1_rustls::client::TlsStream as Tokio1RustlsTlsStream;
     feature = "async-std1-rustls-tls"
 ))]
 use super::InnerTlsParameters;
-use super::TlsParameters;
+use super::{SocketOptions, TlsParameters};
+use crate::transport::smtp::client::net::interleave_addresses;
 #[cfg(feature = "tokio1")]
 use crate::transport::smtp::client::net::resolved_address_filter;
 use crate::transport::smtp::{error, Error};
@@ -136,58 +144,79 @@ impl AsyncNetworkStream {
         timeout: Option<Duration>,
         tls_parameters: Option<TlsParameters>,
         local_addr: Option<IpAddr>,
+    ) -> Result<AsyncNetworkStream, Error> {
+        Self::connect_tokio1_with_options(
+            server,
+            timeout,
+            tls_parameters,
+            &SocketOptions::with_source_address(local_addr),
+        )
+        .await
+    }
+
+    #[cfg(feature = "tokio1")]
+    pub(crate) async fn connect_tokio1_with_options<T: Tokio1ToSocketAddrs>(
+        server: T,
+        timeout: Option<Duration>,
+        tls_parameters: Option<TlsParameters>,
+        socket_options: &SocketOptions,
     ) -> Result<AsyncNetworkStream, Error> {
         async fn try_connect<T: Tokio1ToSocketAddrs>(
             server: T,
             timeout: Option<Duration>,
-            local_addr: Option<IpAddr>,
+            socket_options: &SocketOptions,
         ) -> Result<Tokio1TcpStream, Error> {
+            let local_addr = socket_options.get_source_address();
             let addrs = tokio1_crate::net::lookup_host(server)
                 .await
                 .map_err(error::connection)?
-                .filter(|resolved_addr| resolved_address_filter(resolved_addr, local_addr));
-
-            let mut last_err = None;
+                .filter(|resolved_addr| resolved_address_filter(resolved_addr, local_addr))
+                .collect();
+
+            race(
+                interleave_addresses(addrs),
+                socket_options.get_happy_eyeballs(),
+                |addr| connect_address(addr, timeout, socket_options),
+                tokio1_crate::time::sleep,
+            )
+            .await
+        }
 
-            for addr in addrs {
-                let socket = match addr.ip() {
-                    IpAddr::V4(_) => Tokio1TcpSocket::new_v4(),
-                    IpAddr::V6(_) => Tokio1TcpSocket::new_v6(),
-                }
+        async fn connect_address(
+            addr: SocketAddr,
+            timeout: Option<Duration>,
+            socket_options: &SocketOptions,
+        ) -> Result<Tokio1TcpStream, Error> {
+            let socket = match addr.ip() {
+                IpAddr::V4(_) => Tokio1TcpSocket::new_v4(),
+                IpAddr::V6(_) => Tokio1TcpSocket::new_v6(),
+            }
+            .map_err(error::connection)?;
+            socket_options
+                .apply(&SockRef::from(&socket))
                 .map_err(error::connection)?;
-                if let Some(local_addr) = local_addr {
-                    socket
-                        .bind(SocketAddr::new(local_addr, 0))
-                        .map_err(error::connection)?;
-                }
-
-                let connect_future = socket.connect(addr);
-                if let Some(timeout) = timeout {
-                    match tokio1_crate::time::timeout(timeout, connect_future).await {
-                        Ok(Ok(stream)) => return Ok(stream),
-                        Ok(Err(err)) => last_err = Some(err),
-                        Err(_) => {
-                            last_err = Some(io::Error::new(
-                                io::ErrorKind::TimedOut,
-                                "connection timed out",
-                            ))
-                        }
-                    }
-                } else {
-                    match connect_future.await {
-                        Ok(stream) => return Ok(stream),
-                        Err(err) => last_err = Some(err),
-                    }
-                }
+            if let Some(local_addr) = socket_options.get_source_address() {
+                socket
+                    .bind(SocketAddr::new(local_addr, 0))
+                    .map_err(error::connection)?;
             }
 
-            Err(match last_err {
-                Some(last_err) => error::connection(last_err),
-                None => error::connection("could not resolve to any supported address"),
-            })
+            let connect_future = socket.connect(addr);
+            match timeout {
+                Some(timeout) => tokio1_crate::time::timeout(timeout, connect_future)
+                    .await
+                    .unwrap_or_else(|_| {
+                        Err(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            "connection timed out",
+                        ))
+                    }),
+                None => connect_future.await,
+            }
+            .map_err(error::connection)
         }
 
-        let tcp_stream = try_connect(server, timeout, local_addr).await?;
+        let tcp_stream = try_connect(server, timeout, socket_options).await?;
         let mut stream =
             AsyncNetworkStream::new(InnerAsyncNetworkStream::Tokio1Tcp(Box::new(tcp_stream)));
         if let Some(tls_parameters) = tls_parameters {
@@ -201,44 +230,70 @@ impl AsyncNetworkStream {
         server: T,
         timeout: Option<Duration>,
         tls_parameters: Option<TlsParameters>,
+    ) -> Result<AsyncNetworkStream, Error> {
+        Self::connect_asyncstd1_with_options(server, timeout, tls_parameters, &SocketOptions::new())
+            .await
+    }
+
+    #[cfg(feature = "async-std1")]
+    pub(crate) async fn connect_asyncstd1_with_options<T: AsyncStd1ToSocketAddrs>(
+        server: T,
+        timeout: Option<Duration>,
+        tls_parameters: Option<TlsParameters>,
+        socket_options: &SocketOptions,
     ) -> Result<AsyncNetworkStream, Error> {
         // Unfortunately, there doesn't currently seem to be a way to set the local address.
         // Whilst we can create a AsyncStd1TcpStream from an existing socket, it needs to first have
         // been connected, which is a blocking operation.
-        async fn try_connect_timeout<T: AsyncStd1ToSocketAddrs>(
+        if socket_options.get_source_address().is_some() {
+            return Err(error::client(
+                "binding a source address isn't supported with async-std",
+            ));
+        }
+
+        async fn try_connect<T: AsyncStd1ToSocketAddrs>(
             server: T,
-            timeout: Duration,
+            timeout: Option<Duration>,
+            socket_options: &SocketOptions,
         ) -> Result<AsyncStd1TcpStream, Error> {
-            let addrs = server.to_socket_addrs().await.map_err(error::connection)?;
-
-            let mut last_err = None;
+            let addrs = server
+                .to_socket_addrs()
+                .await
+                .map_err(error::connection)?
+                .collect();
+
+            race(
+                interleave_addresses(addrs),
+                socket_options.get_happy_eyeballs(),
+                |addr| connect_address(addr, timeout),
+                async_std::task::sleep,
+            )
+            .await
+        }
 
-            for addr in addrs {
-                let connect_future = AsyncStd1TcpStream::connect(&addr);
-                match async_std::future::timeout(timeout, connect_future).await {
-                    Ok(Ok(stream)) => return Ok(stream),
-                    Ok(Err(err)) => last_err = Some(err),
-                    Err(_) => {
-                        last_err = Some(io::Error::new(
+        async fn connect_address(
+            addr: SocketAddr,
+            timeout: Option<Duration>,
+        ) -> Result<AsyncStd1TcpStream, Error> {
+            let connect_future = AsyncStd1TcpStream::connect(addr);
+            match timeout {
+                Some(timeout) => async_std::future::timeout(timeout, connect_future)
+                    .await
+                    .unwrap_or_else(|_| {
+                        Err(io::Error::new(
                             io::ErrorKind::TimedOut,
                             "connection timed out",
                         ))
-                    }
-                }
+                    }),
+                None => connect_future.await,
             }
-
-            Err(match last_err {
-                Some(last_err) => error::connection(last_err),
-                None => error::connection("could not resolve to any address"),
-            })
+            .map_err(error::connection)
         }
 
-        let tcp_stream = match timeout {
-            Some(t) => try_connect_timeout(server, t).await?,
-            None => AsyncStd1TcpStream::connect(server)
-                .await
-                .map_err(error::connection)?,
-        };
+        let tcp_stream = try_connect(server, timeout, socket_options).await?;
+        socket_options
+            .apply(&SockRef::from(&tcp_stream))
+            .map_err(error::connection)?;
 
         let mut stream = AsyncNetworkStream::new(InnerAsyncNetworkStream::AsyncStd1Tcp(tcp_stream));
         if let Some(tls_parameters) = tls_parameters {
@@ -521,6 +576,48 @@ impl AsyncNetworkStream {
     }
 }
 
+/// Connects with the first of `addrs` accepting the connection, starting the
+/// next attempt after `delay`, or as soon as the previous one failed (RFC 8305)
+async fn race<S, C, F, W, D>(
+    addrs: Vec<SocketAddr>,
+    delay: Option<Duration>,
+    connect: C,
+    sleep: W,
+) -> Result<S, Error>
+where
+    C: Fn(SocketAddr) -> F,
+    F: Future<Output = Result<S, Error>>,
+    W: Fn(Duration) -> D,
+    D: Future<Output = ()>,
+{
+    let mut addrs = addrs.into_iter();
+    let mut attempts = FuturesUnordered::new();
+    let mut last_err = None;
+
+    loop {
+        if let Some(addr) = addrs.next() {
+            attempts.push(connect(addr));
+        }
+
+        let result = match delay {
+            Some(delay) if !addrs.as_slice().is_empty() => {
+                match future::select(attempts.next(), pin!(sleep(delay))).await {
+                    Either::Left((result, _)) => result,
+                    Either::Right(_) => continue,
+                }
+            }
+            _ => attempts.next().await,
+        };
+        match result {
+            Some(Ok(stream)) => return Ok(stream),
+            Some(Err(err)) => last_err = Some(err),
+            None => break,
+        }
+    }
+
+    Err(last_err.unwrap_or_else(|| error::connection("could not resolve to any address")))
+}
+
 impl FuturesAsyncRead for AsyncNetworkStream {
     fn poll_read(
         mut self: Pin<&mut Self>,
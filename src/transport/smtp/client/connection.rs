@@ -7,12 +7,15 @@ use std::{
 
 #[cfg(feature = "tracing")]
 use super::escape_crlf;
-use super::{ClientCodec, NetworkStream, TlsParameters};
+use super::{
+    is_binary, ClientCodec, NetworkStream, Recipients, SocketOptions, TlsParameters,
+    BDAT_CHUNK_SIZE,
+};
 use crate::{
     address::Envelope,
     transport::smtp::{
-        authentication::{Credentials, Mechanism},
-        commands::{Auth, Data, Ehlo, Mail, Noop, Quit, Rcpt, Starttls},
+        authentication::{Credentials, Exchange, Mechanism},
+        commands::{Bdat, Data, Ehlo, Mail, Noop, Quit, Rcpt, Rset, Starttls},
         error,
         error::Error,
         extension::{ClientId, Extension, MailBodyParameter, MailParameter, ServerInfo},
@@ -39,8 +42,12 @@ pub struct SmtpConnection {
     stream: BufReader<NetworkStream>,
     /// Panic state
     panic: bool,
+    /// Whether the content of the last email was sent
+    content_sent: bool,
     /// Information about the server
     server_info: ServerInfo,
+    /// Host name and port of the server, when known
+    server_address: Option<(String, u16)>,
 }
 
 impl SmtpConnection {
@@ -49,6 +56,13 @@ impl SmtpConnection {
         &self.server_info
     }
 
+    /// Sets the host name and port the connection was opened to
+    ///
+    /// Some authentication mechanisms include them in their messages.
+    pub(crate) fn set_server_address(&mut self, host: &str, port: u16) {
+        self.server_address = Some((host.to_owned(), port));
+    }
+
     // FIXME add simple connect and rename this one
 
     /// Connects to the configured server
@@ -61,12 +75,31 @@ impl SmtpConnection {
         tls_parameters: Option<&TlsParameters>,
         local_address: Option<IpAddr>,
     ) -> Result<SmtpConnection, Error> {
-        let stream = NetworkStream::connect(server, timeout, tls_parameters, local_address)?;
+        Self::connect_with_options(
+            server,
+            timeout,
+            hello_name,
+            tls_parameters,
+            &SocketOptions::with_source_address(local_address),
+        )
+    }
+
+    /// Connects to the configured server, with sockets configured by `socket_options`
+    pub(crate) fn connect_with_options<A: ToSocketAddrs>(
+        server: A,
+        timeout: Option<Duration>,
+        hello_name: &ClientId,
+        tls_parameters: Option<&TlsParameters>,
+        socket_options: &SocketOptions,
+    ) -> Result<SmtpConnection, Error> {
+        let stream = NetworkStream::connect(server, timeout, tls_parameters, socket_options)?;
         let stream = BufReader::new(stream);
         let mut conn = SmtpConnection {
             stream,
             panic: false,
+            content_sent: false,
             server_info: ServerInfo::default(),
+            server_address: None,
         };
         conn.set_timeout(timeout).map_err(error::network)?;
         // TODO log
@@ -81,6 +114,27 @@ impl SmtpConnection {
     }
 
     pub fn send(&mut self, envelope: &Envelope, email: &[u8]) -> Result<Response, Error> {
+        self.send_inner(envelope, email, false)
+    }
+
+    /// Sends an email, even if the server rejects some of its recipients
+    ///
+    /// The message is delivered to the accepted recipients, and the returned
+    /// [`Response`] holds the `RCPT TO` response of each recipient, see
+    /// [`Response::accepted_recipients`] and [`Response::rejected_recipients`].
+    /// An error is only returned if every recipient is rejected.
+    pub fn send_partial(&mut self, envelope: &Envelope, email: &[u8]) -> Result<Response, Error> {
+        self.send_inner(envelope, email, true)
+    }
+
+    fn send_inner(
+        &mut self,
+        envelope: &Envelope,
+        email: &[u8],
+        partial: bool,
+    ) -> Result<Response, Error> {
+        self.content_sent = false;
+
         // Mail
         let mut mail_options = vec![];
 
@@ -100,8 +154,19 @@ impl SmtpConnection {
             mail_options.push(MailParameter::SmtpUtfEight);
         }
 
-        // Check for non-ascii content in the message
-        if !email.is_ascii() {
+        // Binary content handling
+        //
+        // * CHUNKING and BINARYMIME: https://tools.ietf.org/html/rfc3030
+        //
+        // BDAT is used whenever available, as it doesn't need dot-stuffing.
+        let chunking = self.server_info().supports_feature(Extension::Chunking);
+        if chunking
+            && self.server_info().supports_feature(Extension::BinaryMime)
+            && is_binary(email)
+        {
+            mail_options.push(MailParameter::Body(MailBodyParameter::BinaryMime));
+        } else if !email.is_ascii() {
+            // Check for non-ascii content in the message
             if !self.server_info().supports_feature(Extension::EightBitMime) {
                 return Err(error::client(
                     "Message contains non-ascii chars but server does not support 8BITMIME",
@@ -110,28 +175,192 @@ impl SmtpConnection {
             mail_options.push(MailParameter::Body(MailBodyParameter::EightBitMime));
         }
 
-        try_smtp!(
-            self.command(Mail::new(envelope.from().cloned(), mail_options)),
-            self
-        );
+        // Message size declaration: https://tools.ietf.org/html/rfc1870
+        if self.server_info().supports_feature(Extension::Size) {
+            if let Some(max_size) = self.server_info().max_size() {
+                if email.len() > max_size {
+                    return Err(error::message_too_large(email.len(), max_size));
+                }
+            }
+            if !envelope
+                .mail_parameters()
+                .iter()
+                .any(|parameter| matches!(parameter, MailParameter::Size(_)))
+            {
+                mail_options.push(MailParameter::Size(email.len()));
+            }
+        }
 
-        // Recipient
-        for to_address in envelope.to() {
-            try_smtp!(self.command(Rcpt::new(to_address.clone(), vec![])), self);
+        // Delivery status notifications: https://tools.ietf.org/html/rfc3461
+        if envelope.has_dsn_parameters() && !self.server_info().supports_feature(Extension::Dsn) {
+            return Err(error::client(
+                "Envelope contains DSN parameters but server does not support DSN",
+            ));
         }
 
-        // Data
-        try_smtp!(self.command(Data), self);
+        // Message transfer priorities: https://tools.ietf.org/html/rfc6710
+        for parameter in envelope.mail_parameters() {
+            if let MailParameter::MtPriority(priority) = parameter {
+                if !self.server_info().supports_feature(Extension::MtPriority) {
+                    return Err(error::client(
+                        "Envelope has a priority but server does not support MT-PRIORITY",
+                    ));
+                }
+                if !(-9..=9).contains(priority) {
+                    return Err(error::client("MT-PRIORITY must be between -9 and 9"));
+                }
+            }
+        }
+
+        // Requiring TLS: https://tools.ietf.org/html/rfc8689
+        //
+        // Never send such messages in cleartext, nor to a server which
+        // could relay them in cleartext.
+        if envelope
+            .mail_parameters()
+            .contains(&MailParameter::RequireTls)
+        {
+            if !self.is_encrypted() {
+                return Err(error::client(
+                    "Envelope requires TLS but the connection is not encrypted",
+                ));
+            }
+            if !self.server_info().supports_feature(Extension::RequireTls) {
+                return Err(error::client(
+                    "Envelope requires TLS but server does not support REQUIRETLS",
+                ));
+            }
+        }
+
+        mail_options.extend(envelope.mail_parameters().iter().cloned());
+
+        let mail = Mail::new(envelope.from().cloned(), mail_options);
+        let rcpts = envelope
+            .to()
+            .iter()
+            .map(|to_address| {
+                Rcpt::new(
+                    to_address.clone(),
+                    envelope.rcpt_parameters(to_address).cloned().collect(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let mut recipients = Recipients::new(partial);
+
+        if self.server_info.supports_feature(Extension::Pipelining) {
+            // PIPELINING: https://tools.ietf.org/html/rfc2920
+            //
+            // Send MAIL, RCPT and DATA (unless using BDAT) as a single group, then read
+            // all the responses so that the first failure is reported
+            let mut commands = mail.to_string();
+            for rcpt in &rcpts {
+                commands.push_str(&rcpt.to_string());
+            }
+            if !chunking {
+                commands.push_str(&Data.to_string());
+            }
+            try_smtp!(self.write(commands.as_bytes()), self);
+
+            let mut first_error = None;
+            let mut broken = false;
+            let mut rcpt_accepted = false;
+            let mut data_accepted = false;
+            for i in 0..rcpts.len() + if chunking { 1 } else { 2 } {
+                let reply = match self.read_reply() {
+                    Ok(reply) => reply,
+                    Err(err) => {
+                        // The connection is unusable, stop reading
+                        first_error.get_or_insert(err);
+                        broken = true;
+                        break;
+                    }
+                };
+
+                if i == rcpts.len() + 1 {
+                    data_accepted = reply.is_positive();
+                }
+                let result = if (1..=rcpts.len()).contains(&i) {
+                    rcpt_accepted |= reply.is_positive();
+                    recipients.push(&envelope.to()[i - 1], reply)
+                } else {
+                    reply.into_result().map(|_| ())
+                };
+                if let Err(err) = result {
+                    first_error.get_or_insert(err);
+                }
+
+                if i == rcpts.len() {
+                    // Report the rejections rather than the failed DATA
+                    if let Err(err) = recipients.check() {
+                        first_error.get_or_insert(err);
+                    }
+                }
+            }
+            if let Some(err) = first_error {
+                if data_accepted && rcpt_accepted {
+                    // The server now expects the message content for the accepted
+                    // recipients, which can only be cancelled by closing the connection
+                    self.panic = true;
+                    self.abort();
+                } else if broken || self.close_transaction(data_accepted).is_err() {
+                    self.abort();
+                }
+                return Err(err);
+            }
+        } else {
+            try_smtp!(self.command(mail), self);
+
+            // Recipient
+            for (rcpt, to) in rcpts.into_iter().zip(envelope.to()) {
+                let reply = try_smtp!(self.command_reply(rcpt), self);
+                try_smtp!(recipients.push(to, reply), self);
+            }
+            try_smtp!(recipients.check(), self);
+
+            // Data
+            if !chunking {
+                try_smtp!(self.command(Data), self);
+            }
+        }
 
         // Message content
-        let result = try_smtp!(self.message(email), self);
-        Ok(result)
+        self.content_sent = true;
+        let result = if chunking {
+            self.message_chunks(email)
+        } else {
+            self.message(email)
+        };
+        let result = try_smtp!(result.map_err(Error::content_sent), self);
+        Ok(recipients.into_response(result))
+    }
+
+    /// Ends a transaction the server refused, keeping the connection usable
+    ///
+    /// When the server accepted DATA although it rejected every recipient, an
+    /// empty message is sent to close the transaction, which the server rejects.
+    /// See [RFC 2920, section 3.1](https://tools.ietf.org/html/rfc2920#section-3.1).
+    fn close_transaction(&mut self, data_accepted: bool) -> Result<(), Error> {
+        if data_accepted {
+            self.write(b".\r\n")?;
+            self.read_reply()?;
+        } else {
+            self.command(Rset)?;
+        }
+        Ok(())
     }
 
     pub fn has_broken(&self) -> bool {
         self.panic
     }
 
+    /// Returns true if sending the last email went as far as sending its content
+    ///
+    /// When it didn't, the server can't have accepted the email.
+    pub fn has_sent_content(&self) -> bool {
+        self.content_sent
+    }
+
     pub fn can_starttls(&self) -> bool {
         !self.is_encrypted() && self.server_info.supports_feature(Extension::StartTls)
     }
@@ -220,16 +449,18 @@ impl SmtpConnection {
 
         // Limit challenges to avoid blocking
         let mut challenges = 10;
-        let mut response = self.command(Auth::new(mechanism, credentials.clone(), None)?)?;
+        let server_address = self
+            .server_address
+            .as_ref()
+            .map(|(host, port)| (host.as_str(), *port));
+        let mut exchange = Exchange::new(mechanism, credentials, server_address);
+        let mut response = self.command(exchange.start()?)?;
 
         while challenges > 0 && response.has_code(334) {
             challenges -= 1;
             response = try_smtp!(
-                self.command(Auth::new_from_response(
-                    mechanism,
-                    credentials.clone(),
-                    &response,
-                )?),
+                self.command(exchange.respond(&response)?)
+                    .map_err(|err| exchange.error(err)),
                 self
             );
         }
@@ -237,6 +468,7 @@ impl SmtpConnection {
         if challenges == 0 {
             Err(error::response("Unexpected number of challenges"))
         } else {
+            try_smtp!(exchange.finish(), self);
             Ok(response)
         }
     }
@@ -252,10 +484,30 @@ impl SmtpConnection {
         self.read_response()
     }
 
+    /// Sends the message content in `BDAT` chunks, without transparency
+    pub fn message_chunks(&mut self, message: &[u8]) -> Result<Response, Error> {
+        let mut chunks = message.chunks(BDAT_CHUNK_SIZE).peekable();
+        loop {
+            let chunk = chunks.next().unwrap_or_default();
+            let last = chunks.peek().is_none();
+            self.write(Bdat::new(chunk.len(), last).to_string().as_bytes())?;
+            self.write(chunk)?;
+            let response = self.read_response()?;
+            if last {
+                return Ok(response);
+            }
+        }
+    }
+
     /// Sends an SMTP command
     pub fn command<C: Display>(&mut self, command: C) -> Result<Response, Error> {
+        self.command_reply(command)?.into_result()
+    }
+
+    /// Sends an SMTP command and returns the reply, even if it is negative
+    fn command_reply<C: Display>(&mut self, command: C) -> Result<Response, Error> {
         self.write(command.to_string().as_bytes())?;
-        self.read_response()
+        self.read_reply()
     }
 
     /// Writes a string to the server
@@ -273,22 +525,18 @@ impl SmtpConnection {
 
     /// Gets the SMTP response
     pub fn read_response(&mut self) -> Result<Response, Error> {
+        self.read_reply()?.into_result()
+    }
+
+    /// Gets the SMTP response, even if it is negative
+    fn read_reply(&mut self) -> Result<Response, Error> {
         let mut buffer = String::with_capacity(100);
 
         while self.stream.read_line(&mut buffer).map_err(error::network)? > 0 {
             #[cfg(feature = "tracing")]
             tracing::debug!("<< {}", escape_crlf(&buffer));
             match parse_response(&buffer) {
-                Ok((_remaining, response)) => {
-                    return if response.is_positive() {
-                        Ok(response)
-                    } else {
-                        Err(error::code(
-                            response.code(),
-                            Some(response.message().collect()),
-                        ))
-                    };
-                }
+                Ok((_remaining, response)) => return Ok(response),
                 Err(nom::Err::Failure(e)) => {
                     return Err(error::response(e.to_string()));
                 }
@@ -314,3 +562,229 @@ impl SmtpConnection {
         self.stream.get_ref().certificate_chain()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::{SocketAddr, TcpListener},
+        str,
+        thread::{self, JoinHandle},
+    };
+
+    use super::*;
+
+    /// Runs a server supporting PIPELINING, answering each group of commands
+    /// ending with `end` with `reply`
+    ///
+    /// Returns the groups of commands it received, with the number of reads
+    /// each one took.
+    fn pipelining_server(
+        script: Vec<(&'static str, &'static str)>,
+    ) -> (SocketAddr, JoinHandle<Vec<(String, usize)>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(b"220 localhost ESMTP\r\n").unwrap();
+
+            let ehlo = ("\r\n", "250-localhost\r\n250 PIPELINING\r\n");
+            let mut groups = Vec::new();
+            let mut buf = [0; 4096];
+            for (end, reply) in [ehlo].into_iter().chain(script) {
+                let mut group = String::new();
+                let mut reads = 0;
+                while !group.ends_with(end) {
+                    let len = stream.read(&mut buf).unwrap();
+                    if len == 0 {
+                        return groups;
+                    }
+                    group.push_str(str::from_utf8(&buf[..len]).unwrap());
+                    reads += 1;
+                }
+                stream.write_all(reply.as_bytes()).unwrap();
+                groups.push((group, reads));
+            }
+            groups
+        });
+        (addr, server)
+    }
+
+    fn connect(addr: SocketAddr) -> SmtpConnection {
+        SmtpConnection::connect(
+            addr,
+            Some(Duration::from_secs(10)),
+            &ClientId::Domain("localhost".to_owned()),
+            None,
+            None,
+        )
+        .unwrap()
+    }
+
+    fn envelope(to: &[&str]) -> Envelope {
+        Envelope::new(
+            Some("nobody@domain.tld".parse().unwrap()),
+            to.iter().map(|to| to.parse().unwrap()).collect(),
+        )
+        .unwrap()
+    }
+
+    const EMAIL: &[u8] = b"Subject: Happy new year\r\n\r\nBe happy!\r\n";
+
+    #[test]
+    fn test_send_pipelined() {
+        let (addr, server) = pipelining_server(vec![
+            (
+                "DATA\r\n",
+                "250 2.1.0 Ok\r\n250 2.1.5 Ok\r\n550 5.1.1 No such user\r\n354 Go ahead\r\n",
+            ),
+            ("\r\n.\r\n", "250 2.0.0 Queued\r\n"),
+        ]);
+
+        let mut conn = connect(addr);
+        let response = conn
+            .send_partial(&envelope(&["hei@domain.tld", "ko@domain.tld"]), EMAIL)
+            .unwrap();
+        assert_eq!(response.code().to_string(), "250");
+        let accepted = response.accepted_recipients();
+        assert_eq!(accepted.len(), 1);
+        assert_eq!(accepted[0].address().to_string(), "hei@domain.tld");
+        let rejected = response.rejected_recipients();
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(rejected[0].address().to_string(), "ko@domain.tld");
+        assert_eq!(rejected[0].code().to_string(), "550");
+
+        drop(conn);
+        let groups = server.join().unwrap();
+        // MAIL, RCPT and DATA were sent at once
+        assert_eq!(
+            groups[1],
+            (
+                "MAIL FROM:<nobody@domain.tld>\r\nRCPT TO:<hei@domain.tld>\r\nRCPT TO:<ko@domain.tld>\r\nDATA\r\n"
+                    .to_owned(),
+                1
+            )
+        );
+    }
+
+    #[test]
+    fn test_send_pipelined_not_partial() {
+        let (addr, server) = pipelining_server(vec![
+            (
+                "DATA\r\n",
+                "250 2.1.0 Ok\r\n250 2.1.5 Ok\r\n550 5.1.1 No such user\r\n354 Go ahead\r\n",
+            ),
+            (".\r\n", "250 2.0.0 Queued\r\n"),
+        ]);
+
+        // Without partial delivery, a rejected recipient fails the whole email
+        let mut conn = connect(addr);
+        let error = conn
+            .send(&envelope(&["hei@domain.tld", "ko@domain.tld"]), EMAIL)
+            .unwrap_err();
+        assert_eq!(error.status().map(u16::from), Some(550));
+        // Closing the transaction would deliver an empty message to the
+        // accepted recipient, so the connection is dropped instead
+        assert!(conn.has_broken());
+        assert!(!conn.has_sent_content());
+
+        drop(conn);
+        assert_eq!(server.join().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_send_pipelined_rejected() {
+        // DATA is accepted although every recipient was rejected
+        let (addr, server) = pipelining_server(vec![
+            (
+                "DATA\r\n",
+                "250 2.1.0 Ok\r\n550 5.1.1 No such user\r\n450 4.2.1 Try again\r\n354 Go ahead\r\n",
+            ),
+            (".\r\n", "554 5.5.1 No valid recipients\r\n"),
+            ("NOOP\r\n", "250 2.0.0 Ok\r\n"),
+        ]);
+
+        let mut conn = connect(addr);
+        let error = conn
+            .send(&envelope(&["ko@domain.tld", "later@domain.tld"]), EMAIL)
+            .unwrap_err();
+        assert_eq!(error.status().map(u16::from), Some(550));
+        // The transaction was closed with an empty message
+        assert!(!conn.has_broken());
+        assert!(conn.test_connected());
+
+        drop(conn);
+        let groups = server.join().unwrap();
+        assert_eq!(groups[2], (".\r\n".to_owned(), 1));
+    }
+
+    #[test]
+    fn test_send_pipelined_data_rejected() {
+        let (addr, server) = pipelining_server(vec![
+            (
+                "DATA\r\n",
+                "250 2.1.0 Ok\r\n550 5.1.1 No such user\r\n554 5.5.1 No valid recipients\r\n",
+            ),
+            ("RSET\r\n", "250 2.0.0 Ok\r\n"),
+        ]);
+
+        let mut conn = connect(addr);
+        let error = conn.send(&envelope(&["ko@domain.tld"]), EMAIL).unwrap_err();
+        assert_eq!(error.status().map(u16::from), Some(550));
+        assert!(!conn.has_broken());
+
+        drop(conn);
+        assert_eq!(server.join().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_send_message_too_large() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // Server advertising a 10 bytes limit, returning the commands it received
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut reader = BufReader::new(stream);
+            writer.write_all(b"220 localhost ESMTP\r\n").unwrap();
+
+            let mut commands = Vec::new();
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 0 {
+                if line.starts_with("EHLO") {
+                    writer
+                        .write_all(b"250-localhost\r\n250 SIZE 10\r\n")
+                        .unwrap();
+                }
+                commands.push(line.trim_end().to_owned());
+                line.clear();
+            }
+            commands
+        });
+
+        let mut conn = SmtpConnection::connect(
+            addr,
+            Some(Duration::from_secs(10)),
+            &ClientId::Domain("localhost".to_owned()),
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(conn.server_info().max_size(), Some(10));
+
+        let envelope = Envelope::new(
+            Some("nobody@domain.tld".parse().unwrap()),
+            vec!["hei@domain.tld".parse().unwrap()],
+        )
+        .unwrap();
+        let error = conn
+            .send(&envelope, b"Subject: Happy new year\r\n\r\nBe happy!\r\n")
+            .unwrap_err();
+        assert!(error.is_message_too_large());
+
+        drop(conn);
+        assert_eq!(server.join().unwrap(), ["EHLO localhost"]);
+    }
+}
@@ -31,15 +31,23 @@ pub use self::async_connection::AsyncSmtpConnection;
 pub use self::async_net::AsyncNetworkStream;
 #[cfg(feature = "tokio1")]
 pub use self::async_net::AsyncTokioStream;
-use self::net::NetworkStream;
+pub(crate) use self::net::NetworkStream;
 #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
 pub(super) use self::tls::InnerTlsParameters;
 #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
 pub use self::tls::TlsVersion;
 pub use self::{
     connection::SmtpConnection,
+    socket::SocketOptions,
     tls::{Certificate, CertificateStore, Identity, Tls, TlsParameters, TlsParametersBuilder},
 };
+use crate::{
+    transport::smtp::{
+        response::{RecipientResponse, Response},
+        Error,
+    },
+    Address,
+};
 
 #[cfg(any(feature = "tokio1", feature = "async-std1"))]
 mod async_connection;
@@ -47,6 +55,7 @@ mod async_connection;
 mod async_net;
 mod connection;
 mod net;
+mod socket;
 mod tls;
 
 /// The codec used for transparency
@@ -101,6 +110,93 @@ enum CodecStatus {
     StartOfNewLine,
 }
 
+/// Size of the chunks sent with `BDAT`
+const BDAT_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Checks if the message can only be sent as `BINARYMIME`
+///
+/// 7BIT and 8BITMIME content is made of lines of at most 998 bytes
+/// separated by CRLF, without any NUL byte.
+fn is_binary(message: &[u8]) -> bool {
+    let mut line_length = 0;
+    let mut bytes = message.iter();
+    while let Some(&b) = bytes.next() {
+        match b {
+            b'\0' | b'\n' => return true,
+            b'\r' => {
+                if bytes.next() != Some(&b'\n') {
+                    return true;
+                }
+                line_length = 0;
+            }
+            _ => {
+                line_length += 1;
+                if line_length > 998 {
+                    return true;
+                }
+            }
+        }
+    }
+    false
+}
+
+/// Responses to the `RCPT TO` commands of a transaction
+#[derive(Debug)]
+struct Recipients {
+    /// Keep going when some recipients are rejected
+    partial: bool,
+    accepted: Vec<(Address, Response)>,
+    rejected: Vec<(Address, Response)>,
+}
+
+impl Recipients {
+    fn new(partial: bool) -> Self {
+        Self {
+            partial,
+            accepted: Vec::new(),
+            rejected: Vec::new(),
+        }
+    }
+
+    /// Records the reply to `RCPT TO:<to>`
+    ///
+    /// A rejection is only an error when partial delivery is disabled
+    fn push(&mut self, to: &Address, reply: Response) -> Result<(), Error> {
+        if !self.partial {
+            return reply.into_result().map(|_| ());
+        }
+
+        if reply.is_positive() {
+            self.accepted.push((to.clone(), reply));
+        } else {
+            self.rejected.push((to.clone(), reply));
+        }
+        Ok(())
+    }
+
+    /// Fails with the first rejection if no recipient was accepted
+    fn check(&self) -> Result<(), Error> {
+        match self.rejected.first() {
+            Some((_, reply)) if self.accepted.is_empty() => reply.clone().into_result().map(|_| ()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Attaches the recipients to the final response
+    fn into_response(self, mut response: Response) -> Response {
+        if self.partial {
+            let into_responses = |recipients: Vec<(Address, Response)>| {
+                recipients
+                    .into_iter()
+                    .map(|(address, reply)| RecipientResponse::new(address, reply))
+                    .collect()
+            };
+            response.set_recipients(into_responses(self.accepted), into_responses(self.rejected));
+        }
+        response
+    }
+}
+
 /// Returns the string replacing all the CRLF with "\<CRLF\>"
 /// Used for debug displays
 #[cfg(feature = "tracing")]
@@ -138,6 +234,51 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_is_binary() {
+        assert!(!is_binary(b""));
+        assert!(!is_binary(b"Subject: test\r\n\r\nb\xc3\xa9b\xc3\xa9\r\n"));
+        assert!(!is_binary(&[b'a'; 998]));
+        assert!(is_binary(&[b'a'; 999]));
+        assert!(is_binary(b"test\ntest"));
+        assert!(is_binary(b"test\rtest"));
+        assert!(is_binary(b"test\r"));
+        assert!(is_binary(b"te\0st"));
+    }
+
+    #[test]
+    fn test_recipients() {
+        let accepted = "250 2.1.5 Ok\r\n".parse::<Response>().unwrap();
+        let rejected = "550 5.1.1 No such user\r\n".parse::<Response>().unwrap();
+        let ok = "ok@example.com".parse::<Address>().unwrap();
+        let ko = "ko@example.com".parse::<Address>().unwrap();
+
+        let mut recipients = Recipients::new(false);
+        assert!(recipients.push(&ok, accepted.clone()).is_ok());
+        assert!(recipients
+            .push(&ko, rejected.clone())
+            .unwrap_err()
+            .is_permanent());
+
+        let mut recipients = Recipients::new(true);
+        assert!(recipients.push(&ko, rejected.clone()).is_ok());
+        assert!(recipients.check().unwrap_err().is_permanent());
+        assert!(recipients.push(&ok, accepted.clone()).is_ok());
+        assert!(recipients.check().is_ok());
+
+        let response = recipients.into_response(accepted.clone());
+        assert_eq!(
+            response.accepted_recipients(),
+            &[RecipientResponse::new(ok, accepted.clone())]
+        );
+        assert_eq!(
+            response.rejected_recipients(),
+            &[RecipientResponse::new(ko, rejected)]
+        );
+        // The recipients aren't part of the response itself
+        assert_eq!(response, accepted);
+    }
+
     #[test]
     #[cfg(feature = "tracing")]
     fn test_escape_crlf() {
@@ -4,6 +4,8 @@ use std::{
     io::{self, Read, Write},
     mem,
     net::{IpAddr, Ipv4Addr, Shutdown, SocketAddr, SocketAddrV4, TcpStream, ToSocketAddrs},
+    sync::mpsc,
+    thread,
     time::Duration,
 };
 
@@ -17,7 +19,7 @@ use socket2::{Domain, Protocol, Type};
 
 #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
 use super::InnerTlsParameters;
-use super::TlsParameters;
+use super::{SocketOptions, TlsParameters};
 use crate::transport::smtp::{error, Error};
 
 /// A network stream
@@ -94,49 +96,38 @@ impl NetworkStream {
         server: T,
         timeout: Option<Duration>,
         tls_parameters: Option<&TlsParameters>,
-        local_addr: Option<IpAddr>,
+        socket_options: &SocketOptions,
     ) -> Result<NetworkStream, Error> {
         fn try_connect<T: ToSocketAddrs>(
             server: T,
             timeout: Option<Duration>,
-            local_addr: Option<IpAddr>,
+            socket_options: &SocketOptions,
         ) -> Result<TcpStream, Error> {
+            let local_addr = socket_options.get_source_address();
             let addrs = server
                 .to_socket_addrs()
                 .map_err(error::connection)?
-                .filter(|resolved_addr| resolved_address_filter(resolved_addr, local_addr));
+                .filter(|resolved_addr| resolved_address_filter(resolved_addr, local_addr))
+                .collect();
+            let addrs = interleave_addresses(addrs);
 
-            let mut last_err = None;
+            if let Some(delay) = socket_options.get_happy_eyeballs() {
+                if addrs.len() > 1 {
+                    return race(addrs, timeout, socket_options, delay);
+                }
+            }
 
+            let mut last_err = None;
             for addr in addrs {
-                let socket = socket2::Socket::new(
-                    Domain::for_address(addr),
-                    Type::STREAM,
-                    Some(Protocol::TCP),
-                )
-                .map_err(error::connection)?;
-                bind_local_address(&socket, &addr, local_addr)?;
-
-                if let Some(timeout) = timeout {
-                    match socket.connect_timeout(&addr.into(), timeout) {
-                        Ok(()) => return Ok(socket.into()),
-                        Err(err) => last_err = Some(err),
-                    }
-                } else {
-                    match socket.connect(&addr.into()) {
-                        Ok(()) => return Ok(socket.into()),
-                        Err(err) => last_err = Some(err),
-                    }
+                match connect_address(addr, timeout, socket_options) {
+                    Ok(stream) => return Ok(stream),
+                    Err(err) => last_err = Some(err),
                 }
             }
-
-            Err(match last_err {
-                Some(last_err) => error::connection(last_err),
-                None => error::connection("could not resolve to any address"),
-            })
+            Err(last_err.unwrap_or_else(|| error::connection("could not resolve to any address")))
         }
 
-        let tcp_stream = try_connect(server, timeout, local_addr)?;
+        let tcp_stream = try_connect(server, timeout, socket_options)?;
         let mut stream = NetworkStream::new(InnerNetworkStream::Tcp(tcp_stream));
         if let Some(tls_parameters) = tls_parameters {
             stream.upgrade_tls(tls_parameters)?;
@@ -366,6 +357,90 @@ impl Write for NetworkStream {
     }
 }
 
+fn connect_address(
+    addr: SocketAddr,
+    timeout: Option<Duration>,
+    socket_options: &SocketOptions,
+) -> Result<TcpStream, Error> {
+    let socket = socket2::Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))
+        .map_err(error::connection)?;
+    socket_options.apply(&socket).map_err(error::connection)?;
+    bind_local_address(&socket, &addr, socket_options.get_source_address())?;
+
+    match timeout {
+        Some(timeout) => socket.connect_timeout(&addr.into(), timeout),
+        None => socket.connect(&addr.into()),
+    }
+    .map_err(error::connection)?;
+    Ok(socket.into())
+}
+
+/// Connects to the first of `addrs` accepting the connection, starting the next
+/// attempt after `delay`, or as soon as the previous one failed (RFC 8305)
+fn race(
+    addrs: Vec<SocketAddr>,
+    timeout: Option<Duration>,
+    socket_options: &SocketOptions,
+    delay: Duration,
+) -> Result<TcpStream, Error> {
+    let (sender, receiver) = mpsc::channel();
+    let mut addrs = addrs.into_iter();
+    let mut pending = 0;
+    let mut last_err = None;
+
+    loop {
+        if let Some(addr) = addrs.next() {
+            let sender = sender.clone();
+            let socket_options = socket_options.clone();
+            thread::spawn(move || {
+                // Fails when another attempt already won the race
+                let _ = sender.send(connect_address(addr, timeout, &socket_options));
+            });
+            pending += 1;
+        } else if pending == 0 {
+            break;
+        }
+
+        let result = if addrs.as_slice().is_empty() {
+            receiver.recv().expect("the sender is kept alive")
+        } else {
+            match receiver.recv_timeout(delay) {
+                Ok(result) => result,
+                Err(_) => continue,
+            }
+        };
+        pending -= 1;
+        match result {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_err = Some(err),
+        }
+    }
+
+    Err(last_err.unwrap_or_else(|| error::connection("could not resolve to any address")))
+}
+
+/// Orders the addresses alternating between IP versions, starting with
+/// the version of the first one (RFC 8305)
+pub(crate) fn interleave_addresses(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let Some(first) = addrs.first() else {
+        return addrs;
+    };
+    let first_ipv6 = first.is_ipv6();
+    let mut interleaved = Vec::with_capacity(addrs.len());
+    let (preferred, other): (Vec<_>, Vec<_>) = addrs
+        .into_iter()
+        .partition(|addr| addr.is_ipv6() == first_ipv6);
+
+    let mut preferred = preferred.into_iter();
+    let mut other = other.into_iter();
+    loop {
+        match (preferred.next(), other.next()) {
+            (None, None) => return interleaved,
+            (a, b) => interleaved.extend(a.into_iter().chain(b)),
+        }
+    }
+}
+
 /// If the local address is set, binds the socket to this address.
 /// If local address is not set, then destination address is required to determine the default
 /// local address on some platforms.
@@ -409,3 +484,56 @@ pub(crate) fn resolved_address_filter(
         None => true,
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::{net::TcpListener, time::Instant};
+
+    use super::*;
+
+    #[test]
+    fn test_interleave_addresses() {
+        let v4 = |i: u8| SocketAddr::from(([192, 0, 2, i], 25));
+        let v6 = |i: u8| SocketAddr::from(([0x2001, 0xdb8, 0, 0, 0, 0, 0, u16::from(i)], 25));
+
+        assert_eq!(
+            interleave_addresses(vec![v6(1), v6(2), v6(3), v4(1)]),
+            [v6(1), v4(1), v6(2), v6(3)]
+        );
+        assert_eq!(
+            interleave_addresses(vec![v4(1), v4(2), v6(1), v6(2)]),
+            [v4(1), v6(1), v4(2), v6(2)]
+        );
+        assert!(interleave_addresses(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn test_race() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // Listener with a full backlog, further connections hang
+        let blackhole = socket2::Socket::new(Domain::IPV4, Type::STREAM, None).unwrap();
+        blackhole
+            .bind(&SocketAddr::from(([127, 0, 0, 1], 0)).into())
+            .unwrap();
+        blackhole.listen(0).unwrap();
+        let blackhole_addr = blackhole.local_addr().unwrap().as_socket().unwrap();
+        let _queued = (0..3)
+            .filter_map(|_| {
+                TcpStream::connect_timeout(&blackhole_addr, Duration::from_millis(100)).ok()
+            })
+            .collect::<Vec<_>>();
+
+        let start = Instant::now();
+        let stream = race(
+            vec![blackhole_addr, addr],
+            Some(Duration::from_secs(10)),
+            &SocketOptions::new(),
+            Duration::from_millis(50),
+        )
+        .unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), addr);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
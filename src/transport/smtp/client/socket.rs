@@ -0,0 +1,159 @@
+use std::{
+    fmt::{self, Debug},
+    io,
+    net::IpAddr,
+    sync::Arc,
+    time::Duration,
+};
+
+use socket2::{Socket, TcpKeepalive};
+
+/// Connection attempt delay recommended by RFC 8305
+const DEFAULT_HAPPY_EYEBALLS_DELAY: Duration = Duration::from_millis(250);
+
+type SocketHook = dyn Fn(&Socket) -> io::Result<()> + Send + Sync;
+
+/// Options of the TCP sockets used to connect to the server, and of how
+/// their addresses are tried
+///
+/// With async-std, the sockets are only available once connected: the options
+/// are applied at this point, and binding a source address isn't supported.
+///
+/// # Examples
+///
+/// ```rust
+/// # #[cfg(any(feature = "native-tls", feature = "rustls-tls"))]
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use std::{net::Ipv4Addr, time::Duration};
+///
+/// use lettre::{transport::smtp::client::SocketOptions, SmtpTransport};
+///
+/// let sender = SmtpTransport::relay("smtp.example.com")?
+///     .socket_options(
+///         SocketOptions::new()
+///             .source_address(Ipv4Addr::new(192, 0, 2, 10).into())
+///             .tcp_keepalive(Duration::from_secs(60))
+///             .tcp_nodelay(true),
+///     )
+///     .build();
+/// # Ok(())
+/// # }
+/// # #[cfg(not(any(feature = "native-tls", feature = "rustls-tls")))]
+/// # fn main() {}
+/// ```
+#[derive(Clone)]
+pub struct SocketOptions {
+    source_address: Option<IpAddr>,
+    keepalive: Option<Duration>,
+    nodelay: bool,
+    hook: Option<Arc<SocketHook>>,
+    happy_eyeballs: Option<Duration>,
+}
+
+impl SocketOptions {
+    /// Create options leaving the sockets as the operating system creates them
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bind the sockets to `address` before connecting
+    ///
+    /// Only the addresses of the server of the same IP version are tried.
+    pub fn source_address(mut self, address: IpAddr) -> Self {
+        self.source_address = Some(address);
+        self
+    }
+
+    /// Enable TCP keepalive, probing connections idle for `time`
+    pub fn tcp_keepalive(mut self, time: Duration) -> Self {
+        self.keepalive = Some(time);
+        self
+    }
+
+    /// Set `TCP_NODELAY`, disabling Nagle's algorithm
+    ///
+    /// Defaults to `false`
+    pub fn tcp_nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = nodelay;
+        self
+    }
+
+    /// Customize the sockets with `hook` before connecting, after the other
+    /// options have been applied
+    ///
+    /// Connecting fails with the error returned by `hook`, if any.
+    pub fn configure<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&Socket) -> io::Result<()> + Send + Sync + 'static,
+    {
+        self.hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// Set the delay after which the next address of the server is tried,
+    /// while the previous attempts are still pending, or `None` to try the
+    /// addresses one after the other
+    ///
+    /// The addresses are tried alternating between IPv6 and IPv4, so that
+    /// a broken route doesn't delay the connections until they time out,
+    /// as described in [RFC 8305](https://tools.ietf.org/html/rfc8305).
+    ///
+    /// Defaults to 250 milliseconds
+    pub fn happy_eyeballs(mut self, delay: Option<Duration>) -> Self {
+        self.happy_eyeballs = delay;
+        self
+    }
+
+    pub(crate) fn with_source_address(address: Option<IpAddr>) -> Self {
+        Self {
+            source_address: address,
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn get_source_address(&self) -> Option<IpAddr> {
+        self.source_address
+    }
+
+    pub(crate) fn get_happy_eyeballs(&self) -> Option<Duration> {
+        self.happy_eyeballs
+    }
+
+    /// Applies the options, except the source address
+    pub(crate) fn apply(&self, socket: &Socket) -> io::Result<()> {
+        if self.nodelay {
+            socket.set_nodelay(true)?;
+        }
+        if let Some(time) = self.keepalive {
+            socket.set_tcp_keepalive(&TcpKeepalive::new().with_time(time))?;
+        }
+        if let Some(hook) = &self.hook {
+            hook(socket)?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for SocketOptions {
+    fn default() -> Self {
+        Self {
+            source_address: None,
+            keepalive: None,
+            nodelay: false,
+            hook: None,
+            happy_eyeballs: Some(DEFAULT_HAPPY_EYEBALLS_DELAY),
+        }
+    }
+}
+
+impl Debug for SocketOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SocketOptions")
+            .field("source_address", &self.source_address)
+            .field("keepalive", &self.keepalive)
+            .field("nodelay", &self.nodelay)
+            .field("hook", &self.hook.is_some())
+            .field("happy_eyeballs", &self.happy_eyeballs)
+            .finish()
+    }
+}
@@ -111,6 +111,33 @@ impl Display for Data {
     }
 }
 
+/// BDAT command
+///
+/// Defined in [RFC 3030](https://tools.ietf.org/html/rfc3030)
+#[derive(PartialEq, Eq, Clone, Debug, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Bdat {
+    size: usize,
+    last: bool,
+}
+
+impl Display for Bdat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "BDAT {}", self.size)?;
+        if self.last {
+            f.write_str(" LAST")?;
+        }
+        f.write_str("\r\n")
+    }
+}
+
+impl Bdat {
+    /// Creates a BDAT command announcing a chunk of `size` bytes
+    pub fn new(size: usize, last: bool) -> Bdat {
+        Bdat { size, last }
+    }
+}
+
 /// QUIT command
 #[derive(PartialEq, Eq, Clone, Debug, Copy)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
@@ -222,7 +249,7 @@ impl Display for Auth {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         let encoded_response = self.response.as_ref().map(crate::base64::encode);
 
-        if self.mechanism.supports_initial_response() {
+        if self.mechanism.supports_initial_response() && self.challenge.is_none() {
             write!(f, "AUTH {} {}", self.mechanism, encoded_response.unwrap())?;
         } else {
             match encoded_response {
@@ -261,6 +288,34 @@ impl Auth {
         credentials: Credentials,
         response: &Response,
     ) -> Result<Auth, Error> {
+        let decoded_challenge = Self::decode_challenge(response)?;
+        let response = Some(mechanism.response(&credentials, Some(decoded_challenge.as_ref()))?);
+
+        Ok(Auth {
+            mechanism,
+            credentials,
+            challenge: Some(decoded_challenge),
+            response,
+        })
+    }
+
+    /// Creates an AUTH command with an already computed response
+    pub(crate) fn with_response(
+        mechanism: Mechanism,
+        credentials: Credentials,
+        challenge: Option<String>,
+        response: String,
+    ) -> Auth {
+        Auth {
+            mechanism,
+            credentials,
+            challenge,
+            response: Some(response),
+        }
+    }
+
+    /// Decodes the challenge of a response (with 334 response code)
+    pub(crate) fn decode_challenge(response: &Response) -> Result<String, Error> {
         if !response.has_code(334) {
             return Err(error::response("Expecting a challenge"));
         }
@@ -276,14 +331,7 @@ impl Auth {
         #[cfg(feature = "tracing")]
         tracing::debug!("auth decoded challenge: {}", decoded_challenge);
 
-        let response = Some(mechanism.response(&credentials, Some(decoded_challenge.as_ref()))?);
-
-        Ok(Auth {
-            mechanism,
-            credentials,
-            challenge: Some(decoded_challenge),
-            response,
-        })
+        Ok(decoded_challenge)
     }
 }
 
@@ -343,6 +391,8 @@ mod test {
         );
         assert_eq!(format!("{Quit}"), "QUIT\r\n");
         assert_eq!(format!("{Data}"), "DATA\r\n");
+        assert_eq!(format!("{}", Bdat::new(42, false)), "BDAT 42\r\n");
+        assert_eq!(format!("{}", Bdat::new(0, true)), "BDAT 0 LAST\r\n");
         assert_eq!(format!("{Noop}"), "NOOP\r\n");
         assert_eq!(format!("{}", Help::new(None)), "HELP\r\n");
         assert_eq!(
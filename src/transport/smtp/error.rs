@@ -3,7 +3,13 @@
 use std::{error::Error as StdError, fmt};
 
 use crate::{
-    transport::smtp::response::{Code, Severity},
+    transport::{
+        smtp::{
+            authentication::OAuthBearerError,
+            response::{Code, Severity},
+        },
+        RetryableError,
+    },
     BoxError,
 };
 
@@ -17,6 +23,8 @@ pub struct Error {
 struct Inner {
     kind: Kind,
     source: Option<BoxError>,
+    /// Whether the error happened after the message content was sent
+    content_sent: bool,
 }
 
 impl Error {
@@ -28,10 +36,17 @@ impl Error {
             inner: Box::new(Inner {
                 kind,
                 source: source.map(Into::into),
+                content_sent: false,
             }),
         }
     }
 
+    /// Marks the error as happening after the message content was sent
+    pub(crate) fn content_sent(mut self) -> Error {
+        self.inner.content_sent = true;
+        self
+    }
+
     /// Returns true if the error is from response
     pub fn is_response(&self) -> bool {
         matches!(self.inner.kind, Kind::Response)
@@ -42,6 +57,12 @@ impl Error {
         matches!(self.inner.kind, Kind::Client)
     }
 
+    /// Returns true if the message was not sent because it is larger
+    /// than the maximum size advertised by the server
+    pub fn is_message_too_large(&self) -> bool {
+        matches!(self.inner.kind, Kind::MessageTooLarge)
+    }
+
     /// Returns true if the error is a transient SMTP error
     pub fn is_transient(&self) -> bool {
         matches!(self.inner.kind, Kind::Transient(_))
@@ -52,6 +73,27 @@ impl Error {
         matches!(self.inner.kind, Kind::Permanent(_))
     }
 
+    /// Returns true if no connection of the pool became available before the
+    /// [acquire timeout](crate::transport::smtp::PoolConfig::acquire_timeout)
+    #[cfg(feature = "pool")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "pool")))]
+    pub fn is_pool_timeout(&self) -> bool {
+        matches!(self.inner.kind, Kind::PoolTimeout)
+    }
+
+    /// Returns true if connecting to the server failed, or the connection was lost
+    pub(crate) fn is_network(&self) -> bool {
+        matches!(self.inner.kind, Kind::Connection | Kind::Network)
+    }
+
+    /// Returns true if the error happened after the message content was sent
+    ///
+    /// The server may then have accepted the email, even though the final
+    /// reply was never received, and sending it again could deliver it twice.
+    pub fn has_sent_content(&self) -> bool {
+        self.inner.content_sent
+    }
+
     /// Returns true if the error is caused by a timeout
     pub fn is_timeout(&self) -> bool {
         let mut source = self.source();
@@ -67,6 +109,24 @@ impl Error {
         false
     }
 
+    /// Returns the error sent by the server when it rejected an OAUTHBEARER token
+    ///
+    /// The token should be refreshed before trying again when
+    /// [`OAuthBearerError::status`] is `invalid_token`.
+    pub fn oauth_bearer_error(&self) -> Option<&OAuthBearerError> {
+        let mut source = self.source();
+
+        while let Some(err) = source {
+            if let Some(oauth_bearer_error) = err.downcast_ref::<OAuthBearerError>() {
+                return Some(oauth_bearer_error);
+            }
+
+            source = err.source();
+        }
+
+        None
+    }
+
     /// Returns true if the error is from TLS
     #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
     #[cfg_attr(
@@ -86,6 +146,22 @@ impl Error {
     }
 }
 
+impl RetryableError for Error {
+    /// Transient SMTP errors, connection and network errors, and timeouts
+    /// waiting for a pooled connection are retryable
+    ///
+    /// Network errors happening once the message content was sent aren't, as
+    /// the server may already have accepted the email.
+    fn is_retryable(&self) -> bool {
+        #[cfg(feature = "pool")]
+        if self.is_pool_timeout() {
+            return true;
+        }
+
+        self.is_transient() || (self.is_network() && !self.has_sent_content())
+    }
+}
+
 #[derive(Debug)]
 pub(crate) enum Kind {
     /// Transient SMTP error, 4xx reply code
@@ -100,10 +176,17 @@ pub(crate) enum Kind {
     Response,
     /// Internal client error
     Client,
+    /// Message larger than the `SIZE` advertised by the server
+    ///
+    /// [RFC 1870](https://tools.ietf.org/html/rfc1870)
+    MessageTooLarge,
     /// Connection error
     Connection,
     /// Underlying network i/o error
     Network,
+    /// No pooled connection became available in time
+    #[cfg(feature = "pool")]
+    PoolTimeout,
     /// TLS error
     #[cfg_attr(
         docsrs,
@@ -118,6 +201,9 @@ impl fmt::Debug for Error {
         let mut builder = f.debug_struct("lettre::transport::smtp::Error");
 
         builder.field("kind", &self.inner.kind);
+        if self.inner.content_sent {
+            builder.field("content_sent", &true);
+        }
 
         if let Some(source) = &self.inner.source {
             builder.field("source", source);
@@ -132,8 +218,11 @@ impl fmt::Display for Error {
         match &self.inner.kind {
             Kind::Response => f.write_str("response error")?,
             Kind::Client => f.write_str("internal client error")?,
+            Kind::MessageTooLarge => f.write_str("message too large")?,
             Kind::Network => f.write_str("network error")?,
             Kind::Connection => f.write_str("Connection error")?,
+            #[cfg(feature = "pool")]
+            Kind::PoolTimeout => f.write_str("timed out waiting for a pooled connection")?,
             #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
             Kind::Tls => f.write_str("tls error")?,
             Kind::Transient(code) => {
@@ -161,7 +250,7 @@ impl StdError for Error {
     }
 }
 
-pub(crate) fn code(c: Code, s: Option<String>) -> Error {
+pub(crate) fn code<E: Into<BoxError>>(c: Code, s: Option<E>) -> Error {
     match c.severity {
         Severity::TransientNegativeCompletion => Error::new(Kind::Transient(c), s),
         Severity::PermanentNegativeCompletion => Error::new(Kind::Permanent(c), s),
@@ -177,6 +266,15 @@ pub(crate) fn client<E: Into<BoxError>>(e: E) -> Error {
     Error::new(Kind::Client, Some(e))
 }
 
+pub(crate) fn message_too_large(size: usize, max_size: usize) -> Error {
+    Error::new(
+        Kind::MessageTooLarge,
+        Some(format!(
+            "message is {size} bytes but the server accepts at most {max_size} bytes"
+        )),
+    )
+}
+
 pub(crate) fn network<E: Into<BoxError>>(e: E) -> Error {
     Error::new(Kind::Network, Some(e))
 }
@@ -185,6 +283,11 @@ pub(crate) fn connection<E: Into<BoxError>>(e: E) -> Error {
     Error::new(Kind::Connection, Some(e))
 }
 
+#[cfg(feature = "pool")]
+pub(crate) fn pool_timeout() -> Error {
+    Error::new(Kind::PoolTimeout, None::<BoxError>)
+}
+
 #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
 pub(crate) fn tls<E: Into<BoxError>>(e: E) -> Error {
     Error::new(Kind::Tls, Some(e))
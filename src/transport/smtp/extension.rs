@@ -1,16 +1,19 @@
 //! ESMTP features
 
 use std::{
-    collections::HashSet,
-    fmt::{self, Display, Formatter},
+    collections::{BTreeMap, HashSet},
+    fmt::{self, Display, Formatter, Write},
     net::{Ipv4Addr, Ipv6Addr},
 };
 
-use crate::transport::smtp::{
-    authentication::Mechanism,
-    error::{self, Error},
-    response::Response,
-    util::XText,
+use crate::{
+    address::Address,
+    transport::smtp::{
+        authentication::Mechanism,
+        error::{self, Error},
+        response::Response,
+        util::XText,
+    },
 };
 
 /// Client identifier, the parameter to `EHLO`
@@ -86,6 +89,34 @@ pub enum Extension {
     ///
     /// Defined in [RFC 2487](https://tools.ietf.org/html/rfc2487)
     StartTls,
+    /// PIPELINING keyword
+    ///
+    /// Defined in [RFC 2920](https://tools.ietf.org/html/rfc2920)
+    Pipelining,
+    /// SIZE keyword
+    ///
+    /// Defined in [RFC 1870](https://tools.ietf.org/html/rfc1870)
+    Size,
+    /// CHUNKING keyword
+    ///
+    /// Defined in [RFC 3030](https://tools.ietf.org/html/rfc3030)
+    Chunking,
+    /// BINARYMIME keyword
+    ///
+    /// Defined in [RFC 3030](https://tools.ietf.org/html/rfc3030)
+    BinaryMime,
+    /// REQUIRETLS keyword
+    ///
+    /// Defined in [RFC 8689](https://tools.ietf.org/html/rfc8689)
+    RequireTls,
+    /// MT-PRIORITY keyword
+    ///
+    /// Defined in [RFC 6710](https://tools.ietf.org/html/rfc6710)
+    MtPriority,
+    /// DSN keyword
+    ///
+    /// Defined in [RFC 3461](https://tools.ietf.org/html/rfc3461)
+    Dsn,
     /// AUTH mechanism
     Authentication(Mechanism),
 }
@@ -96,6 +127,13 @@ impl Display for Extension {
             Extension::EightBitMime => f.write_str("8BITMIME"),
             Extension::SmtpUtfEight => f.write_str("SMTPUTF8"),
             Extension::StartTls => f.write_str("STARTTLS"),
+            Extension::Pipelining => f.write_str("PIPELINING"),
+            Extension::Size => f.write_str("SIZE"),
+            Extension::Chunking => f.write_str("CHUNKING"),
+            Extension::BinaryMime => f.write_str("BINARYMIME"),
+            Extension::RequireTls => f.write_str("REQUIRETLS"),
+            Extension::MtPriority => f.write_str("MT-PRIORITY"),
+            Extension::Dsn => f.write_str("DSN"),
             Extension::Authentication(mechanism) => write!(f, "AUTH {mechanism}"),
         }
     }
@@ -113,6 +151,16 @@ pub struct ServerInfo {
     ///
     /// It contains the features supported by the server and known by the `Extension` module.
     features: HashSet<Extension>,
+    /// Maximum message size accepted by the server
+    ///
+    /// The value of the `SIZE` keyword, if there is a fixed limit.
+    #[cfg_attr(feature = "serde", serde(default))]
+    max_size: Option<usize>,
+    /// All the keywords advertised by the server, with their parameters
+    ///
+    /// Keywords are stored in upper case.
+    #[cfg_attr(feature = "serde", serde(default))]
+    keywords: BTreeMap<String, Vec<String>>,
 }
 
 impl Display for ServerInfo {
@@ -135,14 +183,22 @@ impl ServerInfo {
         };
 
         let mut features: HashSet<Extension> = HashSet::new();
+        let mut max_size = None;
+        let mut keywords = BTreeMap::new();
 
-        for line in response.message() {
+        // The first line holds the server name and greeting
+        for line in response.message().skip(1) {
             if line.is_empty() {
                 continue;
             }
 
             let mut split = line.split_whitespace();
-            match split.next().unwrap() {
+            let keyword = split.next().unwrap();
+            keywords.insert(
+                keyword.to_ascii_uppercase(),
+                split.clone().map(ToOwned::to_owned).collect(),
+            );
+            match keyword {
                 "8BITMIME" => {
                     features.insert(Extension::EightBitMime);
                 }
@@ -152,6 +208,32 @@ impl ServerInfo {
                 "STARTTLS" => {
                     features.insert(Extension::StartTls);
                 }
+                "PIPELINING" => {
+                    features.insert(Extension::Pipelining);
+                }
+                "SIZE" => {
+                    features.insert(Extension::Size);
+                    // A missing or zero value means there is no fixed limit
+                    max_size = split
+                        .next()
+                        .and_then(|size| size.parse::<usize>().ok())
+                        .filter(|&size| size > 0);
+                }
+                "CHUNKING" => {
+                    features.insert(Extension::Chunking);
+                }
+                "BINARYMIME" => {
+                    features.insert(Extension::BinaryMime);
+                }
+                "REQUIRETLS" => {
+                    features.insert(Extension::RequireTls);
+                }
+                "MT-PRIORITY" => {
+                    features.insert(Extension::MtPriority);
+                }
+                "DSN" => {
+                    features.insert(Extension::Dsn);
+                }
                 "AUTH" => {
                     for mechanism in split {
                         match mechanism {
@@ -164,6 +246,15 @@ impl ServerInfo {
                             "XOAUTH2" => {
                                 features.insert(Extension::Authentication(Mechanism::Xoauth2));
                             }
+                            "OAUTHBEARER" => {
+                                features.insert(Extension::Authentication(Mechanism::OAuthBearer));
+                            }
+                            "CRAM-MD5" => {
+                                features.insert(Extension::Authentication(Mechanism::CramMd5));
+                            }
+                            "SCRAM-SHA-256" => {
+                                features.insert(Extension::Authentication(Mechanism::ScramSha256));
+                            }
                             _ => (),
                         }
                     }
@@ -175,6 +266,8 @@ impl ServerInfo {
         Ok(ServerInfo {
             name: name.to_owned(),
             features,
+            max_size,
+            keywords,
         })
     }
 
@@ -203,6 +296,41 @@ impl ServerInfo {
     pub fn name(&self) -> &str {
         self.name.as_ref()
     }
+
+    /// Checks if the server advertised an EHLO keyword, known by lettre or not
+    ///
+    /// The comparison is case-insensitive.
+    pub fn supports_keyword(&self, keyword: &str) -> bool {
+        self.keywords.contains_key(&keyword.to_ascii_uppercase())
+    }
+
+    /// The parameters advertised with an EHLO keyword
+    ///
+    /// For example `["PLAIN", "LOGIN"]` for `AUTH PLAIN LOGIN`. Returns `None`
+    /// if the server didn't advertise the keyword.
+    pub fn keyword_parameters(&self, keyword: &str) -> Option<&[String]> {
+        self.keywords
+            .get(&keyword.to_ascii_uppercase())
+            .map(Vec::as_slice)
+    }
+
+    /// All the EHLO keywords advertised by the server, in upper case
+    pub fn keywords(&self) -> impl Iterator<Item = &str> {
+        self.keywords.keys().map(String::as_str)
+    }
+
+    /// The authentication mechanisms advertised by the server, including
+    /// the ones not supported by lettre
+    pub fn auth_mechanisms(&self) -> &[String] {
+        self.keyword_parameters("AUTH").unwrap_or_default()
+    }
+
+    /// The maximum message size accepted by the server, in bytes
+    ///
+    /// Returns `None` if the server doesn't advertise a fixed limit.
+    pub fn max_size(&self) -> Option<usize> {
+        self.max_size
+    }
 }
 
 /// A `MAIL FROM` extension parameter
@@ -215,6 +343,24 @@ pub enum MailParameter {
     Size(usize),
     /// `SMTPUTF8` parameter
     SmtpUtfEight,
+    /// `RET` parameter
+    ///
+    /// Defined in [RFC 3461](https://tools.ietf.org/html/rfc3461)
+    Ret(MailRetParameter),
+    /// `ENVID` parameter
+    ///
+    /// Defined in [RFC 3461](https://tools.ietf.org/html/rfc3461)
+    EnvId(String),
+    /// `REQUIRETLS` parameter
+    ///
+    /// The message must only be relayed over TLS connections.
+    ///
+    /// Defined in [RFC 8689](https://tools.ietf.org/html/rfc8689)
+    RequireTls,
+    /// `MT-PRIORITY` parameter, from -9 (lowest) to 9 (highest)
+    ///
+    /// Defined in [RFC 6710](https://tools.ietf.org/html/rfc6710)
+    MtPriority(i8),
     /// Custom parameter
     Other {
         /// Parameter keyword
@@ -230,6 +376,10 @@ impl Display for MailParameter {
             MailParameter::Body(value) => write!(f, "BODY={value}"),
             MailParameter::Size(size) => write!(f, "SIZE={size}"),
             MailParameter::SmtpUtfEight => f.write_str("SMTPUTF8"),
+            MailParameter::Ret(value) => write!(f, "RET={value}"),
+            MailParameter::EnvId(value) => write!(f, "ENVID={}", XText(value)),
+            MailParameter::RequireTls => f.write_str("REQUIRETLS"),
+            MailParameter::MtPriority(priority) => write!(f, "MT-PRIORITY={priority}"),
             MailParameter::Other {
                 keyword,
                 value: Some(value),
@@ -250,6 +400,10 @@ pub enum MailBodyParameter {
     SevenBit,
     /// `8BITMIME`
     EightBitMime,
+    /// `BINARYMIME`
+    ///
+    /// Defined in [RFC 3030](https://tools.ietf.org/html/rfc3030)
+    BinaryMime,
 }
 
 impl Display for MailBodyParameter {
@@ -257,6 +411,33 @@ impl Display for MailBodyParameter {
         match *self {
             MailBodyParameter::SevenBit => f.write_str("7BIT"),
             MailBodyParameter::EightBitMime => f.write_str("8BITMIME"),
+            MailBodyParameter::BinaryMime => f.write_str("BINARYMIME"),
+        }
+    }
+}
+
+impl MailParameter {
+    /// Whether this parameter is only allowed when the server supports DSN
+    pub(crate) fn is_dsn(&self) -> bool {
+        matches!(self, MailParameter::Ret(_) | MailParameter::EnvId(_))
+    }
+}
+
+/// Values for the `RET` parameter to `MAIL FROM`
+#[derive(PartialEq, Eq, Clone, Debug, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MailRetParameter {
+    /// `FULL`, return the full message in failure notifications
+    Full,
+    /// `HDRS`, only return the headers of the message
+    Headers,
+}
+
+impl Display for MailRetParameter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            MailRetParameter::Full => f.write_str("FULL"),
+            MailRetParameter::Headers => f.write_str("HDRS"),
         }
     }
 }
@@ -265,6 +446,16 @@ impl Display for MailBodyParameter {
 #[derive(PartialEq, Eq, Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum RcptParameter {
+    /// `NOTIFY` parameter
+    ///
+    /// An empty list is sent as `NOTIFY=NEVER`.
+    ///
+    /// Defined in [RFC 3461](https://tools.ietf.org/html/rfc3461)
+    Notify(Vec<RcptNotifyParameter>),
+    /// `ORCPT` parameter, with the `rfc822` address type
+    ///
+    /// Defined in [RFC 3461](https://tools.ietf.org/html/rfc3461)
+    Orcpt(Address),
     /// Custom parameter
     Other {
         /// Parameter keyword
@@ -277,6 +468,22 @@ pub enum RcptParameter {
 impl Display for RcptParameter {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match &self {
+            RcptParameter::Notify(conditions) if conditions.is_empty() => {
+                f.write_str("NOTIFY=NEVER")
+            }
+            RcptParameter::Notify(conditions) => {
+                f.write_str("NOTIFY=")?;
+                for (i, condition) in conditions.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    condition.fmt(f)?;
+                }
+                Ok(())
+            }
+            RcptParameter::Orcpt(address) => {
+                write!(f, "ORCPT=rfc822;{}", XText(address.as_ref()))
+            }
             RcptParameter::Other {
                 keyword,
                 value: Some(value),
@@ -289,6 +496,35 @@ impl Display for RcptParameter {
     }
 }
 
+impl RcptParameter {
+    /// Whether this parameter is only allowed when the server supports DSN
+    pub(crate) fn is_dsn(&self) -> bool {
+        matches!(self, RcptParameter::Notify(_) | RcptParameter::Orcpt(_))
+    }
+}
+
+/// Conditions for the `NOTIFY` parameter to `RCPT TO`
+#[derive(PartialEq, Eq, Clone, Debug, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RcptNotifyParameter {
+    /// `SUCCESS`
+    Success,
+    /// `FAILURE`
+    Failure,
+    /// `DELAY`
+    Delay,
+}
+
+impl Display for RcptNotifyParameter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            RcptNotifyParameter::Success => f.write_str("SUCCESS"),
+            RcptNotifyParameter::Failure => f.write_str("FAILURE"),
+            RcptNotifyParameter::Delay => f.write_str("DELAY"),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -313,6 +549,87 @@ mod test {
             format!("{}", Extension::Authentication(Mechanism::Plain)),
             "AUTH PLAIN".to_owned()
         );
+        assert_eq!(format!("{}", Extension::Dsn), "DSN".to_owned());
+    }
+
+    #[test]
+    fn test_mail_parameters_fmt() {
+        assert_eq!(format!("{}", MailParameter::RequireTls), "REQUIRETLS");
+        assert_eq!(
+            format!("{}", MailParameter::MtPriority(-4)),
+            "MT-PRIORITY=-4"
+        );
+        assert_eq!(format!("{}", MailParameter::MtPriority(3)), "MT-PRIORITY=3");
+    }
+
+    #[test]
+    fn test_dsn_parameters_fmt() {
+        assert_eq!(
+            format!("{}", MailParameter::Ret(MailRetParameter::Headers)),
+            "RET=HDRS"
+        );
+        assert_eq!(
+            format!("{}", MailParameter::EnvId("QQ314159 +".to_owned())),
+            "ENVID=QQ314159+20+2B"
+        );
+        assert_eq!(format!("{}", RcptParameter::Notify(vec![])), "NOTIFY=NEVER");
+        assert_eq!(
+            format!(
+                "{}",
+                RcptParameter::Notify(vec![
+                    RcptNotifyParameter::Success,
+                    RcptNotifyParameter::Failure,
+                    RcptNotifyParameter::Delay,
+                ])
+            ),
+            "NOTIFY=SUCCESS,FAILURE,DELAY"
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                RcptParameter::Orcpt("user@example.com".parse().unwrap())
+            ),
+            "ORCPT=rfc822;user@example.com"
+        );
+    }
+
+    #[test]
+    fn test_serverinfo_extensions() {
+        let response = Response::new(
+            Code::new(
+                Severity::PositiveCompletion,
+                Category::MailSystem,
+                Detail::Zero,
+            ),
+            vec![
+                "me".to_owned(),
+                "DSN".to_owned(),
+                "SIZE 0".to_owned(),
+                "CHUNKING".to_owned(),
+                "BINARYMIME".to_owned(),
+                "REQUIRETLS".to_owned(),
+                "MT-PRIORITY MIXER".to_owned(),
+                "x-vendor-ext on".to_owned(),
+            ],
+        );
+
+        let server_info = ServerInfo::from_response(&response).unwrap();
+        assert!(server_info.supports_feature(Extension::Dsn));
+        assert!(server_info.supports_feature(Extension::Size));
+        assert_eq!(server_info.max_size(), None);
+        assert!(server_info.supports_feature(Extension::Chunking));
+        assert!(server_info.supports_feature(Extension::BinaryMime));
+        assert!(server_info.supports_feature(Extension::RequireTls));
+        assert!(server_info.supports_feature(Extension::MtPriority));
+        assert_eq!(
+            server_info.keyword_parameters("MT-PRIORITY"),
+            Some(["MIXER".to_owned()].as_slice())
+        );
+        assert!(server_info.supports_keyword("X-VENDOR-EXT"));
+        assert_eq!(
+            server_info.keyword_parameters("x-vendor-ext"),
+            Some(["on".to_owned()].as_slice())
+        );
     }
 
     #[test]
@@ -326,6 +643,7 @@ mod test {
                 ServerInfo {
                     name: "name".to_owned(),
                     features: eightbitmime,
+                    ..Default::default()
                 }
             ),
             "name with {EightBitMime}".to_owned()
@@ -339,6 +657,7 @@ mod test {
                 ServerInfo {
                     name: "name".to_owned(),
                     features: empty,
+                    ..Default::default()
                 }
             ),
             "name with no supported features".to_owned()
@@ -353,6 +672,7 @@ mod test {
                 ServerInfo {
                     name: "name".to_owned(),
                     features: plain,
+                    ..Default::default()
                 }
             ),
             "name with {Authentication(Plain)}".to_owned()
@@ -372,16 +692,30 @@ mod test {
 
         let mut features = HashSet::new();
         assert!(features.insert(Extension::EightBitMime));
+        assert!(features.insert(Extension::Size));
 
         let server_info = ServerInfo {
             name: "me".to_owned(),
             features,
+            max_size: Some(42),
+            keywords: BTreeMap::from([
+                ("8BITMIME".to_owned(), vec![]),
+                ("SIZE".to_owned(), vec!["42".to_owned()]),
+            ]),
         };
 
         assert_eq!(ServerInfo::from_response(&response).unwrap(), server_info);
 
         assert!(server_info.supports_feature(Extension::EightBitMime));
         assert!(!server_info.supports_feature(Extension::StartTls));
+        assert_eq!(server_info.max_size(), Some(42));
+        assert!(server_info.supports_keyword("size"));
+        assert_eq!(
+            server_info.keyword_parameters("SIZE"),
+            Some(["42".to_owned()].as_slice())
+        );
+        assert_eq!(server_info.keyword_parameters("STARTTLS"), None);
+        assert!(server_info.auth_mechanisms().is_empty());
 
         let response2 = Response::new(
             Code::new(
@@ -393,24 +727,54 @@ mod test {
                 "me".to_owned(),
                 "AUTH PLAIN CRAM-MD5 XOAUTH2 OTHER".to_owned(),
                 "8BITMIME".to_owned(),
+                "PIPELINING".to_owned(),
                 "SIZE 42".to_owned(),
             ],
         );
 
         let mut features2 = HashSet::new();
         assert!(features2.insert(Extension::EightBitMime));
+        assert!(features2.insert(Extension::Pipelining));
+        assert!(features2.insert(Extension::Size));
         assert!(features2.insert(Extension::Authentication(Mechanism::Plain),));
         assert!(features2.insert(Extension::Authentication(Mechanism::Xoauth2),));
+        assert!(features2.insert(Extension::Authentication(Mechanism::CramMd5),));
 
         let server_info2 = ServerInfo {
             name: "me".to_owned(),
             features: features2,
+            max_size: Some(42),
+            keywords: BTreeMap::from([
+                (
+                    "AUTH".to_owned(),
+                    vec![
+                        "PLAIN".to_owned(),
+                        "CRAM-MD5".to_owned(),
+                        "XOAUTH2".to_owned(),
+                        "OTHER".to_owned(),
+                    ],
+                ),
+                ("8BITMIME".to_owned(), vec![]),
+                ("PIPELINING".to_owned(), vec![]),
+                ("SIZE".to_owned(), vec!["42".to_owned()]),
+            ]),
         };
 
         assert_eq!(ServerInfo::from_response(&response2).unwrap(), server_info2);
 
         assert!(server_info2.supports_feature(Extension::EightBitMime));
         assert!(server_info2.supports_auth_mechanism(Mechanism::Plain));
+        assert!(server_info2.supports_auth_mechanism(Mechanism::CramMd5));
+        assert!(!server_info2.supports_auth_mechanism(Mechanism::ScramSha256));
+        assert!(server_info2.supports_feature(Extension::Pipelining));
         assert!(!server_info2.supports_feature(Extension::StartTls));
+        assert_eq!(
+            server_info2.auth_mechanisms(),
+            ["PLAIN", "CRAM-MD5", "XOAUTH2", "OTHER"]
+        );
+        assert_eq!(
+            server_info2.keywords().collect::<Vec<_>>(),
+            ["8BITMIME", "AUTH", "PIPELINING", "SIZE"]
+        );
     }
 }
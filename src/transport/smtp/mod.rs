@@ -22,7 +22,8 @@
 //! * Fast: supports connection reuse and pooling
 //!
 //! This client is designed to send emails to a relay server, and should *not* be used to send
-//! emails directly to the destination server.
+//! emails directly to the destination server. Use the `mx` module (behind the `mx-transport`
+//! feature) for that.
 //!
 //! The relay server can be the local email server, a specific host or a third-party service.
 //!
@@ -133,23 +134,29 @@
 //! # }
 //! ```
 
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
 use client::Tls;
 
 #[cfg(any(feature = "tokio1", feature = "async-std1"))]
 pub use self::async_transport::{AsyncSmtpTransport, AsyncSmtpTransportBuilder};
 #[cfg(feature = "pool")]
-pub use self::pool::PoolConfig;
+pub use self::pool::{CheckoutValidation, PoolConfig, PoolStats};
+pub use self::rate_limit::RateLimit;
+#[cfg(any(feature = "tokio1", feature = "async-std1"))]
+pub use self::resolver::AsyncResolver;
 pub use self::{
     error::Error,
+    resolver::{Resolver, StaticResolver},
     transport::{SmtpTransport, SmtpTransportBuilder},
 };
+#[cfg(any(feature = "tokio1", feature = "async-std1"))]
+use crate::transport::smtp::authentication::AsyncCredentialsProvider;
 #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
 use crate::transport::smtp::client::TlsParameters;
 use crate::transport::smtp::{
-    authentication::{Credentials, Mechanism, DEFAULT_MECHANISMS},
-    client::SmtpConnection,
+    authentication::{Credentials, CredentialsProvider, Mechanism, DEFAULT_MECHANISMS},
+    client::{SmtpConnection, SocketOptions},
     extension::ClientId,
     response::Response,
 };
@@ -162,9 +169,15 @@ pub mod commands;
 mod connection_url;
 mod error;
 pub mod extension;
+#[cfg(feature = "mx-transport")]
+#[cfg_attr(docsrs, doc(cfg(feature = "mx-transport")))]
+pub mod mx;
 #[cfg(feature = "pool")]
 mod pool;
+mod rate_limit;
+mod resolver;
 pub mod response;
+mod semaphore;
 mod transport;
 pub(super) mod util;
 
@@ -198,9 +211,23 @@ struct SmtpInfo {
     authentication: Vec<Mechanism>,
     /// Credentials
     credentials: Option<Credentials>,
+    /// Credentials provider, consulted instead of `credentials` when set
+    credentials_provider: Option<Arc<dyn CredentialsProvider>>,
+    /// Async credentials provider, consulted instead of `credentials` when set
+    #[cfg(any(feature = "tokio1", feature = "async-std1"))]
+    async_credentials_provider: Option<Arc<dyn AsyncCredentialsProvider>>,
     /// Define network timeout
     /// It can be changed later for specific needs (like a different timeout for each SMTP command)
     timeout: Option<Duration>,
+    /// Deliver to the accepted recipients when some are rejected
+    partial_delivery: bool,
+    /// Options of the TCP sockets
+    socket_options: SocketOptions,
+    /// Resolver of the server host name, the system one is used when unset
+    resolver: Option<Arc<dyn Resolver>>,
+    /// Async resolver of the server host name, the runtime one is used when unset
+    #[cfg(any(feature = "tokio1", feature = "async-std1"))]
+    async_resolver: Option<Arc<dyn AsyncResolver>>,
 }
 
 impl Default for SmtpInfo {
@@ -210,9 +237,17 @@ impl Default for SmtpInfo {
             port: SMTP_PORT,
             hello_name: ClientId::default(),
             credentials: None,
+            credentials_provider: None,
+            #[cfg(any(feature = "tokio1", feature = "async-std1"))]
+            async_credentials_provider: None,
             authentication: DEFAULT_MECHANISMS.into(),
             timeout: Some(DEFAULT_TIMEOUT),
             tls: Tls::None,
+            partial_delivery: false,
+            socket_options: SocketOptions::default(),
+            resolver: None,
+            #[cfg(any(feature = "tokio1", feature = "async-std1"))]
+            async_resolver: None,
         }
     }
 }
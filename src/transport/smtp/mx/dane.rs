@@ -0,0 +1,273 @@
+//! DANE authentication of mail exchangers
+//!
+//! Defined in [RFC 7672](https://tools.ietf.org/html/rfc7672)
+
+#[cfg(feature = "rustls-tls")]
+use rustls::{
+    pki_types::{CertificateDer, ServerName, UnixTime},
+    server::ParsedCertificate,
+    RootCertStore,
+};
+use sha2::{Digest, Sha256, Sha512};
+
+use super::TlsaRecord;
+
+/// `DANE-TA` certificate usage, matching a trust anchor of the server chain
+const USAGE_DANE_TA: u8 = 2;
+/// `DANE-EE` certificate usage, matching the certificate of the server
+const USAGE_DANE_EE: u8 = 3;
+
+/// Checks if the DER encoded certificate `chain` of the server `host`,
+/// starting with its own certificate, is authenticated by its TLSA records
+///
+/// `DANE-EE` records are matched against the certificate of the server.
+/// `DANE-TA` records are matched against the other certificates of the chain,
+/// and the certificate of the server must then be issued for `host` and chain
+/// up to the matched trust anchor, which is only checked with rustls.
+///
+/// Fails closed: records which can't be checked, like the `PKIX-TA` and
+/// `PKIX-EE` usages, never authenticate the server.
+pub(super) fn verify(records: &[TlsaRecord], chain: &[Vec<u8>], host: &str) -> bool {
+    let Some((certificate, issuers)) = chain.split_first() else {
+        return false;
+    };
+
+    records.iter().any(|record| match record.usage {
+        USAGE_DANE_EE => matches(record, certificate),
+        USAGE_DANE_TA => issuers
+            .iter()
+            .any(|anchor| matches(record, anchor) && verify_chain(chain, anchor, host)),
+        _ => false,
+    })
+}
+
+/// Checks if the first certificate of `chain` is valid for `host` and issued
+/// by the `anchor` trust anchor
+///
+/// See [RFC 7672, section 3.2](https://tools.ietf.org/html/rfc7672#section-3.2).
+#[cfg(feature = "rustls-tls")]
+fn verify_chain(chain: &[Vec<u8>], anchor: &[u8], host: &str) -> bool {
+    let mut roots = RootCertStore::empty();
+    if roots.add(CertificateDer::from(anchor.to_vec())).is_err() {
+        return false;
+    }
+    let Ok(server_name) = ServerName::try_from(host) else {
+        return false;
+    };
+
+    let certificates = chain
+        .iter()
+        .map(|certificate| CertificateDer::from(certificate.as_slice()))
+        .collect::<Vec<_>>();
+    let Ok(certificate) = ParsedCertificate::try_from(&certificates[0]) else {
+        return false;
+    };
+
+    rustls::client::verify_server_cert_signed_by_trust_anchor(
+        &certificate,
+        &roots,
+        &certificates[1..],
+        UnixTime::now(),
+        rustls::crypto::ring::default_provider()
+            .signature_verification_algorithms
+            .all,
+    )
+    .is_ok()
+        && rustls::client::verify_server_name(&certificate, &server_name).is_ok()
+}
+
+/// Chains can't be verified without rustls, `DANE-TA` records never match
+#[cfg(not(feature = "rustls-tls"))]
+fn verify_chain(_chain: &[Vec<u8>], _anchor: &[u8], _host: &str) -> bool {
+    false
+}
+
+/// Checks if the DER encoded `certificate` matches a TLSA record
+fn matches(record: &TlsaRecord, certificate: &[u8]) -> bool {
+    let selected = match record.selector {
+        // Full certificate
+        0 => certificate,
+        // SubjectPublicKeyInfo
+        1 => match subject_public_key_info(certificate) {
+            Some(spki) => spki,
+            None => return false,
+        },
+        _ => return false,
+    };
+
+    match record.matching_type {
+        0 => selected == record.data.as_slice(),
+        1 => Sha256::digest(selected).as_slice() == record.data.as_slice(),
+        2 => Sha512::digest(selected).as_slice() == record.data.as_slice(),
+        _ => false,
+    }
+}
+
+/// A DER element
+struct Element<'a> {
+    tag: u8,
+    /// The whole element, including its header
+    raw: &'a [u8],
+    content: &'a [u8],
+}
+
+/// Reads a DER element, returning it with the remaining input
+fn read_element(input: &[u8]) -> Option<(Element<'_>, &[u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (length, rest) = if first < 0x80 {
+        (usize::from(first), rest)
+    } else {
+        let count = usize::from(first & 0x7f);
+        if count == 0 || count > 4 || rest.len() < count {
+            return None;
+        }
+        let (bytes, rest) = rest.split_at(count);
+        let length = bytes
+            .iter()
+            .fold(0, |length, &b| (length << 8) | usize::from(b));
+        (length, rest)
+    };
+    if rest.len() < length {
+        return None;
+    }
+
+    let header = input.len() - rest.len();
+    let (content, remaining) = rest.split_at(length);
+    let element = Element {
+        tag,
+        raw: &input[..header + length],
+        content,
+    };
+    Some((element, remaining))
+}
+
+/// Extracts the DER encoded `SubjectPublicKeyInfo` of an X.509 certificate
+fn subject_public_key_info(certificate: &[u8]) -> Option<&[u8]> {
+    const SEQUENCE: u8 = 0x30;
+    const VERSION: u8 = 0xa0;
+
+    let (certificate, _) = read_element(certificate)?;
+    if certificate.tag != SEQUENCE {
+        return None;
+    }
+    let (tbs_certificate, _) = read_element(certificate.content)?;
+    if tbs_certificate.tag != SEQUENCE {
+        return None;
+    }
+
+    // version, serialNumber, signature, issuer, validity and subject come first
+    let mut fields = tbs_certificate.content;
+    let mut skipped = 0;
+    while skipped < 5 {
+        let (field, rest) = read_element(fields)?;
+        fields = rest;
+        if field.tag != VERSION {
+            skipped += 1;
+        }
+    }
+
+    let (spki, _) = read_element(fields)?;
+    (spki.tag == SEQUENCE).then_some(spki.raw)
+}
+
+#[cfg(test)]
+mod test {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    use super::*;
+
+    /// Self-signed certificate for `mx.example.com`
+    const CERTIFICATE: &str = "MIIBiTCCAS+gAwIBAgIUPt9RFBmO7TyahNrLohkHkJdy954wCgYIKoZIzj0EAwIwGTEXMBUGA1UEAwwObXguZXhhbXBsZS5jb20wIBcNMjYxMDE2MTk0NzA5WhgPMjEyNjA5MjIxOTQ3MDlaMBkxFzAVBgNVBAMMDm14LmV4YW1wbGUuY29tMFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAEz+2ZzgvrzBamJjEqdjk6gEiAxcjNanb939HvubUuS/Vf6Qw41dtXR0v1xj7ru0L9ZkmwPVuHLxythq3QGwRh+aNTMFEwHQYDVR0OBBYEFC7QRc6kIIeszBEPX8VKbIjiUuBmMB8GA1UdIwQYMBaAFC7QRc6kIIeszBEPX8VKbIjiUuBmMA8GA1UdEwEB/wQFMAMBAf8wCgYIKoZIzj0EAwIDSAAwRQIgJZwR1f3dJfohRxlGR8S9Spkoy9+mKFsFUnPSaxhdV/gCIQD/vdx34UZiVih39XsAVXRAul1nagQrbpYDTpDI/Iu3hQ==";
+
+    /// Certificate for `mx.example.com`, issued by [`CA_CERTIFICATE`]
+    const LEAF_CERTIFICATE: &str = "MIIBwDCCAWegAwIBAgIUXmv6gyOnQC0ezfKNNfUViPwaqB8wCgYIKoZIzj0EAwIwEjEQMA4GA1UEAwwHREFORSBjYTAgFw0yNjEwMTYyMjEyMjJaGA8yMTI2MDkyMjIyMTIyMlowGTEXMBUGA1UEAwwObXguZXhhbXBsZS5jb20wWTATBgcqhkjOPQIBBggqhkjOPQMBBwNCAARybsYlk7JksCyazkpiLtpUk5B3vapHaJ2heGlYv5Vp+d/9EZGMejRBsVEII83cfpbSJI+YxgwGa7Xgn1q3YoMZo4GRMIGOMAwGA1UdEwEB/wQCMAAwDgYDVR0PAQH/BAQDAgeAMBMGA1UdJQQMMAoGCCsGAQUFBwMBMBkGA1UdEQQSMBCCDm14LmV4YW1wbGUuY29tMB0GA1UdDgQWBBRB/Dy7cmeSoqHFBWOVkTNZyd0kuzAfBgNVHSMEGDAWgBTkHZriZBYDFBh0h9rNHjGcpQ6W0zAKBggqhkjOPQQDAgNHADBEAiBtc4oRryC+RgzE9UMPW3kIX5/ISz9RAM724YyRzlJWaQIgT+AFtYPixXJMhfE31146PCQ3f4YQLDyMwNDrVt483Kw=";
+
+    /// Self-signed certificate authority
+    const CA_CERTIFICATE: &str = "MIIBijCCATGgAwIBAgIUFKRmCr0GMH5iA2bEMtdqFqflXHswCgYIKoZIzj0EAwIwEjEQMA4GA1UEAwwHREFORSBjYTAgFw0yNjEwMTYyMjEyMjJaGA8yMTI2MDkyMjIyMTIyMlowEjEQMA4GA1UEAwwHREFORSBjYTBZMBMGByqGSM49AgEGCCqGSM49AwEHA0IABJLBoeuIydGi0mxaLi5nwyTJl9Sk+HpDxx1e+TAfcyWeclqyOcwUCLbG0S0rnwxlL0xF53iOkCaza/pXlOWDdaSjYzBhMB0GA1UdDgQWBBTkHZriZBYDFBh0h9rNHjGcpQ6W0zAfBgNVHSMEGDAWgBTkHZriZBYDFBh0h9rNHjGcpQ6W0zAPBgNVHRMBAf8EBTADAQH/MA4GA1UdDwEB/wQEAwIBBjAKBggqhkjOPQQDAgNHADBEAiAI8ypJO8Cvcon5lBAR4aDIlU5mFMYhbBjwBZIUnCcNRgIgAqxrtseBpQKePFh4f65sG1rykMfiau4iyF1C8+S7n/4=";
+
+    /// Another self-signed certificate authority
+    const OTHER_CA_CERTIFICATE: &str = "MIIBkjCCATegAwIBAgIUdvQL67m/Bk2soEAiqstmb5K5egIwCgYIKoZIzj0EAwIwFTETMBEGA1UEAwwKREFORSBvdGhlcjAgFw0yNjEwMTYyMjEyMjJaGA8yMTI2MDkyMjIyMTIyMlowFTETMBEGA1UEAwwKREFORSBvdGhlcjBZMBMGByqGSM49AgEGCCqGSM49AwEHA0IABFTwhSeYjQxmBJyDxUiP64mRZJNPh91sqL9xY1D9ZK2NEWHuC2KoeMS77X3UjY0aJ81mQmbF/2wf0ds45K8DJGajYzBhMB0GA1UdDgQWBBQttla5Czmgy2OELL9o38mwX/LeZjAfBgNVHSMEGDAWgBQttla5Czmgy2OELL9o38mwX/LeZjAPBgNVHRMBAf8EBTADAQH/MA4GA1UdDwEB/wQEAwIBBjAKBggqhkjOPQQDAgNJADBGAiEAqW993sc4rGoqr11HZyjBq/jVpD8a/a704BZC58nDdvMCIQCa/XkRfIcaO2bc/VjUAqwtqbLz3Vj6Dzq+l4yhBwSrhA==";
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_verify() {
+        let certificate = STANDARD.decode(CERTIFICATE).unwrap();
+        let chain = [certificate.clone()];
+        let spki_sha256 = hex("2483d79602f0299e73c4b0c42c2c92350a7ab185f98c72cbeacf64f660278873");
+        let cert_sha256 = hex("0b9613cbfab561092795dded9177d649221e4c34a33c6059f6c662d81af1fd69");
+        let host = "mx.example.com";
+
+        assert!(verify(
+            &[TlsaRecord::new(3, 1, 1, spki_sha256.clone())],
+            &chain,
+            host
+        ));
+        assert!(verify(
+            &[TlsaRecord::new(3, 0, 1, cert_sha256.clone())],
+            &chain,
+            host
+        ));
+        assert!(verify(
+            &[TlsaRecord::new(3, 0, 0, certificate.clone())],
+            &chain,
+            host
+        ));
+        assert!(!verify(
+            &[TlsaRecord::new(3, 1, 1, cert_sha256)],
+            &chain,
+            host
+        ));
+        // PKIX-EE records can't be checked
+        assert!(!verify(
+            &[TlsaRecord::new(1, 1, 1, spki_sha256)],
+            &chain,
+            host
+        ));
+        assert!(!verify(&[], &chain, host));
+    }
+
+    #[test]
+    fn test_verify_trust_anchor() {
+        let leaf = STANDARD.decode(LEAF_CERTIFICATE).unwrap();
+        let ca = STANDARD.decode(CA_CERTIFICATE).unwrap();
+        let ca_spki_sha256 =
+            hex("f57aeab49c99f08ad5a7488a49ddf5cb6d18eebbfe1ed71b7177842877adacaf");
+        let chain = [leaf.clone(), ca.clone()];
+        let records = [TlsaRecord::new(2, 1, 1, ca_spki_sha256)];
+
+        // Chains are only verified with rustls, failing closed otherwise
+        assert_eq!(
+            verify(&records, &chain, "mx.example.com"),
+            cfg!(feature = "rustls-tls")
+        );
+        assert!(!verify(&records, &chain, "mx.example.org"));
+        // The trust anchor must be sent by the server
+        assert!(!verify(&records, &[leaf], "mx.example.com"));
+    }
+
+    #[test]
+    fn test_verify_trust_anchor_mismatch() {
+        let leaf = STANDARD.decode(LEAF_CERTIFICATE).unwrap();
+        let ca = STANDARD.decode(CA_CERTIFICATE).unwrap();
+        let other = STANDARD.decode(OTHER_CA_CERTIFICATE).unwrap();
+        let other_spki_sha256 =
+            hex("c61582b12af557261175241106b124ddb828b78d1dd5990924ae430dee4c1c73");
+        let records = [TlsaRecord::new(2, 1, 1, other_spki_sha256)];
+
+        // No certificate of the chain matches
+        assert!(!verify(
+            &records,
+            &[leaf.clone(), ca.clone()],
+            "mx.example.com"
+        ));
+        // The matching certificate didn't issue the certificate of the server
+        assert!(!verify(&records, &[leaf, other], "mx.example.com"));
+    }
+}
@@ -0,0 +1,791 @@
+//! Direct delivery to the mail exchangers of the recipient domains
+//!
+//! Unlike [`SmtpTransport`](super::SmtpTransport), which hands every message
+//! to a single relay, [`MxTransport`] groups the recipients of the envelope
+//! by domain and connects to the servers responsible for each domain, as
+//! described in [RFC 5321, section 5.1](https://tools.ietf.org/html/rfc5321#section-5.1):
+//!
+//! * the MX records of the domain are tried in preference order
+//! * when the domain has no MX record, the domain itself is used
+//! * a domain publishing a "null MX" ([RFC 7505](https://tools.ietf.org/html/rfc7505))
+//!   doesn't accept any email
+//!
+//! Connections are made to port 25, and use STARTTLS when the server supports it
+//! and a TLS backend is enabled. As is usual for server to server delivery,
+//! certificates are not verified by default. Domains can require
+//! authenticated TLS connections to their mail exchangers with:
+//!
+//! * MTA-STS ([RFC 8461](https://tools.ietf.org/html/rfc8461)), enabled with
+//!   [`MxTransportBuilder::mta_sts`]
+//! * DANE ([RFC 7672](https://tools.ietf.org/html/rfc7672)), enabled with
+//!   [`MxTransportBuilder::dane`]
+//!
+//! Mail exchangers violating the policy of their domain are never sent the message.
+//!
+//! ```rust,no_run
+//! # #[cfg(feature = "builder")]
+//! # fn test() -> Result<(), Box<dyn std::error::Error>> {
+//! use lettre::{
+//!     transport::smtp::{
+//!         extension::ClientId,
+//!         mx::{DnsResolver, MxTransport},
+//!     },
+//!     Message, Transport,
+//! };
+//!
+//! let email = Message::builder()
+//!     .from("NoBody <nobody@domain.tld>".parse()?)
+//!     .to("Hei <hei@example.com>".parse()?)
+//!     .to("Yuin <yuin@example.org>".parse()?)
+//!     .subject("Happy new year")
+//!     .body(String::from("Be happy!"))?;
+//!
+//! let sender = MxTransport::builder(DnsResolver::from_system_conf()?)
+//!     .hello_name(ClientId::Domain("mail.domain.tld".to_owned()))
+//!     .build();
+//! let response = sender.send(&email)?;
+//! for (domain, error) in response.failed() {
+//!     println!("could not deliver to {domain}: {error}");
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    error::Error as StdError,
+    fmt::{self, Debug},
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use hickory_resolver::{error::ResolveErrorKind, proto::op::ResponseCode, Resolver};
+
+#[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+pub use self::mta_sts::{HttpsFetcher, MtaStsFetcher};
+#[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+use self::mta_sts::{MtaSts, MtaStsPolicy};
+#[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+use super::client::TlsParameters;
+use super::{
+    client::SmtpConnection, error, extension::ClientId, Error, Response, DEFAULT_TIMEOUT, SMTP_PORT,
+};
+use crate::{address::Envelope, Address, Transport};
+
+#[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+mod dane;
+#[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+mod mta_sts;
+
+/// A MX record, naming a mail exchanger of a domain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MxRecord {
+    preference: u16,
+    exchange: String,
+}
+
+impl MxRecord {
+    /// Creates a MX record
+    ///
+    /// Mail exchangers with a lower `preference` are tried first.
+    pub fn new(preference: u16, exchange: String) -> Self {
+        Self {
+            preference,
+            exchange,
+        }
+    }
+
+    /// The preference of the mail exchanger, lower is tried first
+    pub fn preference(&self) -> u16 {
+        self.preference
+    }
+
+    /// The host name of the mail exchanger
+    pub fn exchange(&self) -> &str {
+        &self.exchange
+    }
+
+    /// Checks if this is a "null MX", meaning the domain doesn't accept email
+    ///
+    /// Defined in [RFC 7505](https://tools.ietf.org/html/rfc7505)
+    fn is_null(&self) -> bool {
+        self.exchange.is_empty() || self.exchange == "."
+    }
+}
+
+/// A TLSA record, authenticating the certificate of a server for DANE
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsaRecord {
+    usage: u8,
+    selector: u8,
+    matching_type: u8,
+    data: Vec<u8>,
+}
+
+impl TlsaRecord {
+    /// Creates a TLSA record from its certificate usage, selector, matching
+    /// type and certificate association data
+    pub fn new(usage: u8, selector: u8, matching_type: u8, data: Vec<u8>) -> Self {
+        Self {
+            usage,
+            selector,
+            matching_type,
+            data,
+        }
+    }
+}
+
+/// DNS lookups needed to deliver email to a domain
+///
+/// [`DnsResolver`] queries the DNS servers of the system, other
+/// implementations can be used to stub DNS in tests.
+pub trait MxResolver: Debug + Send + Sync {
+    /// Returns the MX records of `domain`
+    ///
+    /// An empty list means that the domain exists but has no MX record, in
+    /// which case email is delivered to the domain itself.
+    fn mx(&self, domain: &str) -> Result<Vec<MxRecord>, Box<dyn StdError + Send + Sync>>;
+
+    /// Returns the IP addresses of `host`
+    fn ip(&self, host: &str) -> Result<Vec<IpAddr>, Box<dyn StdError + Send + Sync>>;
+
+    /// Returns the TXT records of `name`, each one as a single string
+    ///
+    /// Used to discover MTA-STS policies. The default implementation
+    /// returns no record.
+    fn txt(&self, name: &str) -> Result<Vec<String>, Box<dyn StdError + Send + Sync>> {
+        let _ = name;
+        Ok(Vec::new())
+    }
+
+    /// Returns the TLSA records of `name`, like `_25._tcp.mx.example.com`
+    ///
+    /// Used for DANE. The records must have been validated with DNSSEC, as
+    /// they are trusted to authenticate the servers. The default implementation
+    /// returns no record.
+    fn tlsa(&self, name: &str) -> Result<Vec<TlsaRecord>, Box<dyn StdError + Send + Sync>> {
+        let _ = name;
+        Ok(Vec::new())
+    }
+}
+
+/// [`MxResolver`] using the DNS servers configured on the system
+///
+/// Also implements [`Resolver`](super::Resolver), to resolve the relays
+/// of the SMTP transports with the same DNS servers.
+///
+/// DNSSEC isn't validated by this resolver: DANE is only secure when the
+/// configured recursive resolver validates it.
+pub struct DnsResolver {
+    inner: Resolver,
+}
+
+impl DnsResolver {
+    /// Creates a resolver from the system configuration
+    ///
+    /// This uses `/etc/resolv.conf` on Unix and the registry on Windows.
+    pub fn from_system_conf() -> Result<Self, Error> {
+        let inner = Resolver::from_system_conf().map_err(error::network)?;
+        Ok(Self { inner })
+    }
+}
+
+impl Debug for DnsResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DnsResolver").finish_non_exhaustive()
+    }
+}
+
+/// Checks if the lookup failed because the name exists without records of
+/// the requested type
+fn is_no_records(kind: &ResolveErrorKind) -> bool {
+    matches!(
+        kind,
+        ResolveErrorKind::NoRecordsFound {
+            response_code: ResponseCode::NoError,
+            ..
+        }
+    )
+}
+
+impl MxResolver for DnsResolver {
+    fn mx(&self, domain: &str) -> Result<Vec<MxRecord>, Box<dyn StdError + Send + Sync>> {
+        match self.inner.mx_lookup(fqdn(domain)) {
+            Ok(lookup) => Ok(lookup
+                .iter()
+                .map(|mx| {
+                    let exchange = mx.exchange().to_utf8();
+                    MxRecord::new(mx.preference(), exchange.trim_end_matches('.').to_owned())
+                })
+                .collect()),
+            Err(err) if is_no_records(err.kind()) => Ok(Vec::new()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn ip(&self, host: &str) -> Result<Vec<IpAddr>, Box<dyn StdError + Send + Sync>> {
+        match self.inner.lookup_ip(fqdn(host)) {
+            Ok(lookup) => Ok(lookup.iter().collect()),
+            Err(err) if is_no_records(err.kind()) => Ok(Vec::new()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn txt(&self, name: &str) -> Result<Vec<String>, Box<dyn StdError + Send + Sync>> {
+        match self.inner.txt_lookup(fqdn(name)) {
+            Ok(lookup) => Ok(lookup
+                .iter()
+                .map(|txt| {
+                    txt.iter()
+                        .map(|data| String::from_utf8_lossy(data))
+                        .collect::<String>()
+                })
+                .collect()),
+            Err(err) if is_no_records(err.kind()) => Ok(Vec::new()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn tlsa(&self, name: &str) -> Result<Vec<TlsaRecord>, Box<dyn StdError + Send + Sync>> {
+        match self.inner.tlsa_lookup(fqdn(name)) {
+            Ok(lookup) => Ok(lookup
+                .iter()
+                .map(|tlsa| {
+                    TlsaRecord::new(
+                        tlsa.cert_usage().into(),
+                        tlsa.selector().into(),
+                        tlsa.matching().into(),
+                        tlsa.cert_data().to_vec(),
+                    )
+                })
+                .collect()),
+            Err(err) if is_no_records(err.kind()) => Ok(Vec::new()),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+impl super::Resolver for DnsResolver {
+    fn resolve(
+        &self,
+        host: &str,
+        port: u16,
+    ) -> Result<Vec<SocketAddr>, Box<dyn StdError + Send + Sync>> {
+        relay_addresses(self, host, port)
+    }
+}
+
+/// Resolves the relay `host` with the address lookups of `resolver`
+///
+/// Unlike mail exchangers, relays must have an address.
+fn relay_addresses(
+    resolver: &dyn MxResolver,
+    host: &str,
+    port: u16,
+) -> Result<Vec<SocketAddr>, Box<dyn StdError + Send + Sync>> {
+    let addresses = resolver.ip(host)?;
+    if addresses.is_empty() {
+        return Err(format!("no address found for {host}").into());
+    }
+    Ok(addresses
+        .into_iter()
+        .map(|address| SocketAddr::new(address, port))
+        .collect())
+}
+
+/// Makes `name` fully qualified, to avoid applying the search domains
+fn fqdn(name: &str) -> String {
+    if name.ends_with('.') {
+        name.to_owned()
+    } else {
+        format!("{name}.")
+    }
+}
+
+/// Synchronously send emails directly to the mail exchangers of the recipients
+///
+/// See the [module documentation](self) for details.
+#[cfg_attr(docsrs, doc(cfg(feature = "mx-transport")))]
+#[derive(Debug, Clone)]
+pub struct MxTransport {
+    resolver: Arc<dyn MxResolver>,
+    hello_name: ClientId,
+    port: u16,
+    timeout: Option<Duration>,
+    partial_delivery: bool,
+    #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+    mta_sts: Option<Arc<MtaSts>>,
+    #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+    dane: bool,
+}
+
+/// How the connection to a mail exchanger is secured
+enum Security {
+    /// STARTTLS when supported, without authenticating the server
+    Opportunistic,
+    /// STARTTLS with a certificate valid for the name of the server, for MTA-STS
+    #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+    Verified,
+    /// STARTTLS with a certificate matching the TLSA records of the server
+    #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+    Dane(Vec<TlsaRecord>),
+}
+
+/// Contains options for configuring an [`MxTransport`]
+#[derive(Debug, Clone)]
+pub struct MxTransportBuilder {
+    transport: MxTransport,
+}
+
+impl MxTransport {
+    /// Creates a new builder, looking up mail exchangers with `resolver`
+    ///
+    /// Defaults are:
+    ///
+    /// * Port 25
+    /// * STARTTLS when supported by the server
+    /// * A 60-seconds timeout for smtp commands
+    pub fn builder<R: MxResolver + 'static>(resolver: R) -> MxTransportBuilder {
+        MxTransportBuilder {
+            transport: MxTransport {
+                resolver: Arc::new(resolver),
+                hello_name: ClientId::default(),
+                port: SMTP_PORT,
+                timeout: Some(DEFAULT_TIMEOUT),
+                partial_delivery: false,
+                #[cfg(any(
+                    feature = "native-tls",
+                    feature = "rustls-tls",
+                    feature = "boring-tls"
+                ))]
+                mta_sts: None,
+                #[cfg(any(
+                    feature = "native-tls",
+                    feature = "rustls-tls",
+                    feature = "boring-tls"
+                ))]
+                dane: false,
+            },
+        }
+    }
+
+    /// Delivers the message to the servers of `domain`
+    fn deliver(&self, domain: &str, envelope: &Envelope, email: &[u8]) -> Result<Response, Error> {
+        let hosts = self.hosts(domain)?;
+        #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+        let policy = self
+            .mta_sts
+            .as_ref()
+            .and_then(|mta_sts| mta_sts.policy(&*self.resolver, domain));
+        #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+        let hosts = match &policy {
+            Some(policy) => mta_sts::filter_hosts(policy, domain, hosts)?,
+            None => hosts,
+        };
+
+        let mut last_error = None;
+        for host in hosts {
+            #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+            let security = match self.security(&host, policy.as_ref()) {
+                Ok(security) => security,
+                Err(err) => {
+                    last_error = Some(err);
+                    continue;
+                }
+            };
+            #[cfg(not(any(
+                feature = "native-tls",
+                feature = "rustls-tls",
+                feature = "boring-tls"
+            )))]
+            let security = Security::Opportunistic;
+
+            let addresses = match host_address(&host) {
+                Some(address) => vec![address],
+                None => match self.resolver.ip(&host) {
+                    Ok(addresses) => addresses,
+                    Err(err) => {
+                        last_error = Some(error::network(err));
+                        continue;
+                    }
+                },
+            };
+
+            for address in addresses {
+                match self.deliver_to(&host, address, &security, envelope, email) {
+                    Ok(response) => return Ok(response),
+                    // The server refused the message, other servers of the domain won't accept it
+                    Err(err) if err.is_permanent() => return Err(err),
+                    // The server may have accepted the message
+                    Err(err) if err.has_sent_content() => return Err(err),
+                    Err(err) => last_error = Some(err),
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| {
+            error::connection(format!("No mail server address found for {domain}"))
+        }))
+    }
+
+    /// The mail exchangers of `domain`, in preference order
+    fn hosts(&self, domain: &str) -> Result<Vec<String>, Error> {
+        if host_address(domain).is_some() {
+            return Ok(vec![domain.to_owned()]);
+        }
+
+        let mut records = self.resolver.mx(domain).map_err(error::network)?;
+        if records.is_empty() {
+            // Implicit MX
+            return Ok(vec![domain.to_owned()]);
+        }
+        if records.iter().any(MxRecord::is_null) {
+            return Err(error::client(format!(
+                "{domain} does not accept email (null MX)"
+            )));
+        }
+
+        records.sort_by_key(MxRecord::preference);
+        Ok(records.into_iter().map(|record| record.exchange).collect())
+    }
+
+    /// How the connection to the `host` mail exchanger must be secured
+    #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+    fn security(&self, host: &str, policy: Option<&MtaStsPolicy>) -> Result<Security, Error> {
+        // DANE takes precedence over MTA-STS
+        if self.dane && host_address(host).is_none() {
+            let records = self
+                .resolver
+                .tlsa(&format!("_{}._tcp.{host}", self.port))
+                .map_err(error::network)?;
+            if !records.is_empty() {
+                return Ok(Security::Dane(records));
+            }
+        }
+
+        if policy.is_some_and(MtaStsPolicy::is_enforced) {
+            Ok(Security::Verified)
+        } else {
+            Ok(Security::Opportunistic)
+        }
+    }
+
+    /// Delivers the message to one mail exchanger
+    #[allow(unused_variables)]
+    fn deliver_to(
+        &self,
+        host: &str,
+        address: IpAddr,
+        security: &Security,
+        envelope: &Envelope,
+        email: &[u8],
+    ) -> Result<Response, Error> {
+        let mut conn = SmtpConnection::connect(
+            SocketAddr::new(address, self.port),
+            self.timeout,
+            &self.hello_name,
+            None,
+            None,
+        )?;
+
+        match security {
+            Security::Opportunistic =>
+            {
+                #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+                if conn.can_starttls() {
+                    let tls_parameters = TlsParameters::builder(host.to_owned())
+                        .dangerous_accept_invalid_certs(true)
+                        .dangerous_accept_invalid_hostnames(true)
+                        .build()?;
+                    conn.starttls(&tls_parameters, &self.hello_name)?;
+                }
+            }
+            #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+            Security::Verified => {
+                if !conn.can_starttls() {
+                    conn.abort();
+                    return Err(error::tls(format!(
+                        "{host} does not support STARTTLS, required by MTA-STS"
+                    )));
+                }
+                let tls_parameters = TlsParameters::new(host.to_owned())?;
+                conn.starttls(&tls_parameters, &self.hello_name)?;
+            }
+            #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+            Security::Dane(records) => {
+                if !conn.can_starttls() {
+                    conn.abort();
+                    return Err(error::tls(format!(
+                        "{host} does not support STARTTLS, required by its TLSA records"
+                    )));
+                }
+                // The certificate is authenticated by the TLSA records instead
+                let tls_parameters = TlsParameters::builder(host.to_owned())
+                    .dangerous_accept_invalid_certs(true)
+                    .dangerous_accept_invalid_hostnames(true)
+                    .build()?;
+                conn.starttls(&tls_parameters, &self.hello_name)?;
+                // Only rustls gives access to the whole chain of the server
+                #[cfg(feature = "rustls-tls")]
+                let chain = conn.certificate_chain()?;
+                #[cfg(not(feature = "rustls-tls"))]
+                let chain = vec![conn.peer_certificate()?];
+                if !dane::verify(records, &chain, host) {
+                    conn.abort();
+                    return Err(error::tls(format!(
+                        "The certificate of {host} does not match its TLSA records"
+                    )));
+                }
+            }
+        }
+
+        let response = if self.partial_delivery {
+            conn.send_partial(envelope, email)?
+        } else {
+            conn.send(envelope, email)?
+        };
+        let _ = conn.quit();
+        Ok(response)
+    }
+}
+
+/// Parses an address literal, like `[192.0.2.1]` or `[IPv6:2001:db8::1]`
+fn host_address(domain: &str) -> Option<IpAddr> {
+    let literal = domain.strip_prefix('[')?.strip_suffix(']')?;
+    let literal = literal.strip_prefix("IPv6:").unwrap_or(literal);
+    literal.parse().ok()
+}
+
+impl MxTransportBuilder {
+    /// Set the name used during EHLO
+    pub fn hello_name(mut self, name: ClientId) -> Self {
+        self.transport.hello_name = name;
+        self
+    }
+
+    /// Set the port to use
+    ///
+    /// Only useful for testing, mail exchangers listen on port 25.
+    pub fn port(mut self, port: u16) -> Self {
+        self.transport.port = port;
+        self
+    }
+
+    /// Set the timeout duration
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.transport.timeout = timeout;
+        self
+    }
+
+    /// Deliver the message to the accepted recipients of a domain when its
+    /// server rejects some of them
+    ///
+    /// See [`SmtpTransportBuilder::partial_delivery`](super::SmtpTransportBuilder::partial_delivery).
+    pub fn partial_delivery(mut self, partial_delivery: bool) -> Self {
+        self.transport.partial_delivery = partial_delivery;
+        self
+    }
+
+    /// Enforce the MTA-STS policies of the recipient domains
+    ///
+    /// Policies are fetched with `fetcher`, usually an [`HttpsFetcher`], and
+    /// cached until their id changes or they expire. When a domain enforces its
+    /// policy, only the mail exchangers it lists are used, over STARTTLS with a
+    /// valid certificate.
+    #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+    #[cfg_attr(
+        docsrs,
+        doc(cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls")))
+    )]
+    pub fn mta_sts<F: MtaStsFetcher + 'static>(mut self, fetcher: F) -> Self {
+        self.transport.mta_sts = Some(Arc::new(MtaSts::new(Arc::new(fetcher))));
+        self
+    }
+
+    /// Authenticate the mail exchangers publishing TLSA records (DANE)
+    ///
+    /// Their certificate must then match one of their `DANE-EE` records, or be
+    /// valid for their name and issued by a certificate of their chain matching
+    /// one of their `DANE-TA` records. `DANE-TA` records can only be checked
+    /// with the `rustls-tls` feature, and records which can't be checked never
+    /// authenticate a server. See [`MxResolver::tlsa`] about DNSSEC.
+    #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+    #[cfg_attr(
+        docsrs,
+        doc(cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls")))
+    )]
+    pub fn dane(mut self, dane: bool) -> Self {
+        self.transport.dane = dane;
+        self
+    }
+
+    /// Build the transport
+    pub fn build(self) -> MxTransport {
+        self.transport
+    }
+}
+
+/// Outcome of the delivery of a message to each recipient domain
+#[derive(Debug)]
+pub struct MxResponse {
+    domains: Vec<(String, Result<Response, Error>)>,
+}
+
+impl MxResponse {
+    /// The domains the message was delivered to, with the last response
+    /// of their server
+    pub fn delivered(&self) -> impl Iterator<Item = (&str, &Response)> {
+        self.domains
+            .iter()
+            .filter_map(|(domain, result)| Some((domain.as_str(), result.as_ref().ok()?)))
+    }
+
+    /// The domains the message could not be delivered to
+    pub fn failed(&self) -> impl Iterator<Item = (&str, &Error)> {
+        self.domains
+            .iter()
+            .filter_map(|(domain, result)| Some((domain.as_str(), result.as_ref().err()?)))
+    }
+}
+
+/// Groups the recipients by domain, in order of first appearance
+fn recipients_by_domain(to: &[Address]) -> Vec<(String, Vec<Address>)> {
+    let mut domains: Vec<(String, Vec<Address>)> = Vec::new();
+    for address in to {
+        let domain = address.domain().to_ascii_lowercase();
+        match domains.iter_mut().find(|(d, _)| *d == domain) {
+            Some((_, addresses)) => addresses.push(address.clone()),
+            None => domains.push((domain, vec![address.clone()])),
+        }
+    }
+    domains
+}
+
+impl Transport for MxTransport {
+    type Ok = MxResponse;
+    type Error = Error;
+
+    /// Sends an email to the mail exchangers of each recipient domain
+    ///
+    /// An error is only returned if the message couldn't be delivered
+    /// to any domain, see [`MxResponse::failed`] otherwise.
+    fn send_raw(&self, envelope: &Envelope, email: &[u8]) -> Result<Self::Ok, Self::Error> {
+        let mut domains = Vec::new();
+        for (domain, to) in recipients_by_domain(envelope.to()) {
+            let result = self.deliver(&domain, &envelope.with_recipients(to), email);
+            #[cfg(feature = "tracing")]
+            if let Err(err) = &result {
+                tracing::debug!("delivery to {domain} failed: {err}");
+            }
+            domains.push((domain, result));
+        }
+
+        if domains.iter().all(|(_, result)| result.is_err()) {
+            let (_, result) = domains.swap_remove(0);
+            return Err(result.unwrap_err());
+        }
+        Ok(MxResponse { domains })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[derive(Debug, Default)]
+    struct StubResolver {
+        mx: HashMap<&'static str, Vec<MxRecord>>,
+        ip: HashMap<&'static str, Vec<IpAddr>>,
+    }
+
+    impl MxResolver for StubResolver {
+        fn mx(&self, domain: &str) -> Result<Vec<MxRecord>, Box<dyn StdError + Send + Sync>> {
+            self.mx
+                .get(domain)
+                .cloned()
+                .ok_or_else(|| "no such domain".into())
+        }
+
+        fn ip(&self, host: &str) -> Result<Vec<IpAddr>, Box<dyn StdError + Send + Sync>> {
+            self.ip
+                .get(host)
+                .cloned()
+                .ok_or_else(|| "no such host".into())
+        }
+    }
+
+    #[test]
+    fn test_recipients_by_domain() {
+        let to = ["a@example.com", "b@example.org", "c@Example.com"]
+            .iter()
+            .map(|address| address.parse().unwrap())
+            .collect::<Vec<Address>>();
+
+        let domains = recipients_by_domain(&to);
+        assert_eq!(
+            domains,
+            [
+                ("example.com".to_owned(), vec![to[0].clone(), to[2].clone()]),
+                ("example.org".to_owned(), vec![to[1].clone()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hosts() {
+        let transport = MxTransport::builder(StubResolver {
+            mx: HashMap::from([
+                (
+                    "example.com",
+                    vec![
+                        MxRecord::new(20, "mx2.example.com".to_owned()),
+                        MxRecord::new(10, "mx1.example.com".to_owned()),
+                    ],
+                ),
+                ("example.org", vec![]),
+                ("example.net", vec![MxRecord::new(0, ".".to_owned())]),
+            ]),
+            ..Default::default()
+        })
+        .build();
+
+        assert_eq!(
+            transport.hosts("example.com").unwrap(),
+            ["mx1.example.com", "mx2.example.com"]
+        );
+        assert_eq!(transport.hosts("example.org").unwrap(), ["example.org"]);
+        assert!(transport.hosts("example.net").unwrap_err().is_client());
+        assert!(transport.hosts("example.invalid").is_err());
+        assert_eq!(transport.hosts("[127.0.0.1]").unwrap(), ["[127.0.0.1]"]);
+    }
+
+    #[test]
+    fn test_relay_addresses() {
+        let resolver = StubResolver {
+            ip: HashMap::from([
+                ("smtp.example.com", vec![IpAddr::from([192, 0, 2, 1])]),
+                ("empty.example.com", vec![]),
+            ]),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            relay_addresses(&resolver, "smtp.example.com", 587).unwrap(),
+            [SocketAddr::from(([192, 0, 2, 1], 587))]
+        );
+        assert!(relay_addresses(&resolver, "empty.example.com", 587).is_err());
+        assert!(relay_addresses(&resolver, "example.invalid", 587).is_err());
+    }
+
+    #[test]
+    fn test_host_address() {
+        assert_eq!(host_address("[127.0.0.1]"), Some([127, 0, 0, 1].into()));
+        assert_eq!(
+            host_address("[IPv6:::1]"),
+            Some(IpAddr::from(std::net::Ipv6Addr::LOCALHOST))
+        );
+        assert_eq!(host_address("example.com"), None);
+    }
+}
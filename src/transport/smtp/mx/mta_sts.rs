@@ -0,0 +1,513 @@
+//! MTA-STS policies
+//!
+//! Defined in [RFC 8461](https://tools.ietf.org/html/rfc8461)
+
+use std::{
+    collections::HashMap,
+    error::Error as StdError,
+    fmt::Debug,
+    io::{self, Read, Write},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use super::MxResolver;
+use crate::transport::smtp::{
+    client::{NetworkStream, SocketOptions, TlsParameters},
+    error,
+};
+
+/// Policies are cached for at most a year
+const MAX_MAX_AGE: u64 = 31_557_600;
+
+/// Maximum size of a policy, in bytes
+const MAX_POLICY_SIZE: u64 = 64 * 1024;
+
+/// Maximum size of the status line and headers of a policy response, in bytes
+const MAX_HEAD_SIZE: u64 = 8 * 1024;
+
+/// Fetches the MTA-STS policy of a domain
+///
+/// [`HttpsFetcher`] downloads policies from the policy host of the domain,
+/// other implementations can be used to stub them in tests.
+pub trait MtaStsFetcher: Debug + Send + Sync {
+    /// Returns the text of the policy of `domain`, served at
+    /// `https://mta-sts.{domain}/.well-known/mta-sts.txt`
+    fn fetch(&self, domain: &str) -> Result<String, Box<dyn StdError + Send + Sync>>;
+}
+
+/// [`MtaStsFetcher`] downloading policies over HTTPS
+///
+/// Responses which aren't `text/plain`, are larger than 64 KiB or could have
+/// been truncated are rejected.
+#[derive(Debug, Clone, Copy)]
+pub struct HttpsFetcher {
+    timeout: Option<Duration>,
+}
+
+impl HttpsFetcher {
+    /// Creates a fetcher, giving up on policy hosts after `timeout`
+    pub fn new(timeout: Option<Duration>) -> Self {
+        Self { timeout }
+    }
+}
+
+impl MtaStsFetcher for HttpsFetcher {
+    fn fetch(&self, domain: &str) -> Result<String, Box<dyn StdError + Send + Sync>> {
+        let host = format!("mta-sts.{domain}");
+        let tls_parameters = TlsParameters::new(host.clone())?;
+        let mut stream = NetworkStream::connect(
+            (host.as_str(), 443),
+            self.timeout,
+            Some(&tls_parameters),
+            &SocketOptions::new(),
+        )?;
+        stream.set_read_timeout(self.timeout)?;
+        stream.set_write_timeout(self.timeout)?;
+
+        // HTTP/1.0 avoids chunked responses, the server closes the connection when done
+        write!(
+            stream,
+            "GET /.well-known/mta-sts.txt HTTP/1.0\r\nHost: {host}\r\n\r\n"
+        )?;
+        stream.flush()?;
+
+        let mut response = Vec::new();
+        let closed = match stream
+            .take(MAX_HEAD_SIZE + MAX_POLICY_SIZE + 1)
+            .read_to_end(&mut response)
+        {
+            Ok(_) => true,
+            // Some servers don't bother closing the TLS session
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => false,
+            Err(err) => return Err(err.into()),
+        };
+
+        parse_response(&response, closed)
+    }
+}
+
+/// Extracts the policy from an HTTP `response`
+///
+/// When the TLS session wasn't `closed` properly, the response could have been
+/// truncated by an attacker, so its length must be given by its `Content-Length`.
+fn parse_response(
+    response: &[u8],
+    closed: bool,
+) -> Result<String, Box<dyn StdError + Send + Sync>> {
+    if response.len() as u64 > MAX_HEAD_SIZE + MAX_POLICY_SIZE {
+        return Err("HTTP response too large".into());
+    }
+    let response = std::str::from_utf8(response)?;
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .ok_or("incomplete HTTP response")?;
+    if head.len() as u64 > MAX_HEAD_SIZE {
+        return Err("HTTP response head too large".into());
+    }
+
+    let mut lines = head.split("\r\n");
+    let status = lines.next().and_then(|line| line.split_whitespace().nth(1));
+    if status != Some("200") {
+        return Err(format!("unexpected HTTP status {}", status.unwrap_or_default()).into());
+    }
+
+    let mut content_type = None;
+    let mut content_length = None;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-type") {
+            content_type = Some(value);
+        } else if name.eq_ignore_ascii_case("content-length") {
+            content_length = Some(value.parse::<usize>()?);
+        }
+    }
+
+    // Parameters, like the charset, are allowed
+    let media_type = content_type.and_then(|value| value.split(';').next());
+    if !media_type.is_some_and(|media_type| media_type.trim().eq_ignore_ascii_case("text/plain")) {
+        return Err(format!(
+            "unexpected policy content type {}",
+            content_type.unwrap_or_default()
+        )
+        .into());
+    }
+    if body.len() as u64 > MAX_POLICY_SIZE {
+        return Err("policy too large".into());
+    }
+    match content_length {
+        Some(length) if length != body.len() => return Err("truncated HTTP response".into()),
+        None if !closed => return Err("truncated HTTP response".into()),
+        _ => {}
+    }
+    Ok(body.to_owned())
+}
+
+/// How the policy is applied
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Enforce,
+    Testing,
+    None,
+}
+
+/// A parsed MTA-STS policy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct MtaStsPolicy {
+    mode: Mode,
+    mx: Vec<String>,
+    max_age: u64,
+}
+
+impl MtaStsPolicy {
+    fn parse(text: &str) -> Result<Self, Box<dyn StdError + Send + Sync>> {
+        let mut version = None;
+        let mut mode = None;
+        let mut mx = Vec::new();
+        let mut max_age = None;
+
+        for line in text.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim() {
+                "version" => version = Some(value),
+                "mode" => {
+                    mode = Some(match value {
+                        "enforce" => Mode::Enforce,
+                        "testing" => Mode::Testing,
+                        "none" => Mode::None,
+                        _ => return Err(format!("invalid policy mode {value}").into()),
+                    });
+                }
+                "mx" => mx.push(value.to_ascii_lowercase()),
+                "max_age" => max_age = Some(value.parse::<u64>()?.min(MAX_MAX_AGE)),
+                _ => {}
+            }
+        }
+
+        if version != Some("STSv1") {
+            return Err("unsupported policy version".into());
+        }
+        let mode = mode.ok_or("missing policy mode")?;
+        let max_age = max_age.ok_or("missing policy max_age")?;
+        if mx.is_empty() && mode != Mode::None {
+            return Err("missing policy mx".into());
+        }
+        Ok(Self { mode, mx, max_age })
+    }
+
+    /// Checks if mail exchangers not matching the policy must be skipped
+    pub(super) fn is_enforced(&self) -> bool {
+        self.mode == Mode::Enforce
+    }
+
+    /// Checks if the policy allows the `host` mail exchanger
+    pub(super) fn matches(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        self.mx
+            .iter()
+            .any(|pattern| match pattern.strip_prefix("*.") {
+                // The wildcard only matches the left-most label
+                Some(suffix) => host
+                    .split_once('.')
+                    .is_some_and(|(label, rest)| !label.is_empty() && rest == suffix),
+                None => *pattern == host,
+            })
+    }
+}
+
+/// Extracts the policy id from the `_mta-sts` TXT records
+fn txt_id(records: &[String]) -> Option<String> {
+    let mut records = records
+        .iter()
+        .filter(|record| record.trim_start().starts_with("v=STSv1"));
+    let record = records.next()?;
+    if records.next().is_some() {
+        // Multiple records must be ignored
+        return None;
+    }
+
+    record.split(';').find_map(|field| {
+        let (key, value) = field.split_once('=')?;
+        (key.trim() == "id").then(|| value.trim().to_owned())
+    })
+}
+
+#[derive(Debug, Clone)]
+struct CachedPolicy {
+    id: String,
+    policy: MtaStsPolicy,
+    expires: Instant,
+}
+
+/// Fetches and caches MTA-STS policies
+#[derive(Debug)]
+pub(super) struct MtaSts {
+    fetcher: Arc<dyn MtaStsFetcher>,
+    cache: Mutex<HashMap<String, CachedPolicy>>,
+}
+
+impl MtaSts {
+    pub(super) fn new(fetcher: Arc<dyn MtaStsFetcher>) -> Self {
+        Self {
+            fetcher,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The policy applying to `domain`, if any
+    ///
+    /// A new policy is only fetched when its id, published in DNS, changes.
+    pub(super) fn policy(&self, resolver: &dyn MxResolver, domain: &str) -> Option<MtaStsPolicy> {
+        let id = resolver
+            .txt(&format!("_mta-sts.{domain}"))
+            .ok()
+            .and_then(|records| txt_id(&records));
+        let now = Instant::now();
+        let cached = self
+            .cache
+            .lock()
+            .unwrap()
+            .get(domain)
+            .filter(|cached| cached.expires > now)
+            .cloned();
+
+        let policy = match (id, cached) {
+            // Keep using the cached policy when the TXT record disappears
+            (None, cached) => cached.map(|cached| cached.policy),
+            (Some(id), Some(cached)) if cached.id == id => Some(cached.policy),
+            (Some(id), cached) => match self
+                .fetcher
+                .fetch(domain)
+                .and_then(|text| MtaStsPolicy::parse(&text))
+            {
+                Ok(policy) => {
+                    self.cache.lock().unwrap().insert(
+                        domain.to_owned(),
+                        CachedPolicy {
+                            id,
+                            policy: policy.clone(),
+                            expires: now + Duration::from_secs(policy.max_age),
+                        },
+                    );
+                    Some(policy)
+                }
+                Err(err) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!("could not fetch the MTA-STS policy of {domain}: {err}");
+                    #[cfg(not(feature = "tracing"))]
+                    let _ = err;
+                    cached.map(|cached| cached.policy)
+                }
+            },
+        };
+        policy.filter(|policy| policy.mode != Mode::None)
+    }
+}
+
+/// Selects the mail exchangers allowed by the policy of `domain`
+pub(super) fn filter_hosts(
+    policy: &MtaStsPolicy,
+    domain: &str,
+    hosts: Vec<String>,
+) -> Result<Vec<String>, error::Error> {
+    if !policy.is_enforced() {
+        #[cfg(feature = "tracing")]
+        for host in hosts.iter().filter(|host| !policy.matches(host)) {
+            tracing::debug!("{host} does not match the MTA-STS policy of {domain}");
+        }
+        return Ok(hosts);
+    }
+
+    let hosts = hosts
+        .into_iter()
+        .filter(|host| policy.matches(host))
+        .collect::<Vec<_>>();
+    if hosts.is_empty() {
+        return Err(error::tls(format!(
+            "No mail exchanger of {domain} matches its MTA-STS policy"
+        )));
+    }
+    Ok(hosts)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        net::IpAddr,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use super::*;
+    use crate::transport::smtp::mx::MxRecord;
+
+    const POLICY: &str = "version: STSv1\r\nmode: enforce\r\nmx: mail.example.com\r\nmx: *.example.net\r\nmax_age: 604800\r\n";
+
+    #[test]
+    fn test_parse() {
+        let policy = MtaStsPolicy::parse(POLICY).unwrap();
+        assert_eq!(
+            policy,
+            MtaStsPolicy {
+                mode: Mode::Enforce,
+                mx: vec!["mail.example.com".to_owned(), "*.example.net".to_owned()],
+                max_age: 604800,
+            }
+        );
+
+        assert!(MtaStsPolicy::parse("version: STSv1\nmode: none\nmax_age: 86400\n").is_ok());
+        assert!(MtaStsPolicy::parse("version: STSv1\nmode: enforce\nmax_age: 86400\n").is_err());
+        assert!(MtaStsPolicy::parse("version: STSv2\nmode: none\nmax_age: 86400\n").is_err());
+        assert!(MtaStsPolicy::parse("version: STSv1\nmode: strict\nmax_age: 86400\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\n\r\n{POLICY}",
+            POLICY.len()
+        );
+        assert_eq!(parse_response(response.as_bytes(), true).unwrap(), POLICY);
+        // The length is known, the response is complete
+        assert_eq!(parse_response(response.as_bytes(), false).unwrap(), POLICY);
+
+        let response = format!("HTTP/1.0 200 OK\r\nContent-Type: TEXT/PLAIN\r\n\r\n{POLICY}");
+        assert_eq!(parse_response(response.as_bytes(), true).unwrap(), POLICY);
+        assert!(parse_response(response.as_bytes(), false).is_err());
+
+        let response =
+            format!("HTTP/1.0 404 Not Found\r\nContent-Type: text/plain\r\n\r\n{POLICY}");
+        assert!(parse_response(response.as_bytes(), true).is_err());
+    }
+
+    #[test]
+    fn test_parse_response_invalid() {
+        // Wrong or missing content type
+        let response = format!("HTTP/1.0 200 OK\r\nContent-Type: text/html\r\n\r\n{POLICY}");
+        assert!(parse_response(response.as_bytes(), true).is_err());
+        let response = format!("HTTP/1.0 200 OK\r\n\r\n{POLICY}");
+        assert!(parse_response(response.as_bytes(), true).is_err());
+
+        // Truncated
+        let response = format!(
+            "HTTP/1.0 200 OK\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n\r\n{POLICY}",
+            POLICY.len() + 10
+        );
+        assert!(parse_response(response.as_bytes(), true).is_err());
+
+        // Oversized
+        let response = format!(
+            "HTTP/1.0 200 OK\r\nContent-Type: text/plain\r\n\r\n{POLICY}{}",
+            "#".repeat(MAX_POLICY_SIZE as usize)
+        );
+        assert!(parse_response(response.as_bytes(), true).is_err());
+    }
+
+    #[test]
+    fn test_matches() {
+        let policy = MtaStsPolicy::parse(POLICY).unwrap();
+        assert!(policy.matches("mail.example.com"));
+        assert!(policy.matches("MAIL.example.com."));
+        assert!(policy.matches("mx1.example.net"));
+        assert!(!policy.matches("example.net"));
+        assert!(!policy.matches("a.mx1.example.net"));
+        assert!(!policy.matches("mail.example.org"));
+    }
+
+    #[test]
+    fn test_txt_id() {
+        assert_eq!(
+            txt_id(&["v=STSv1; id=20160831085700Z;".to_owned()]),
+            Some("20160831085700Z".to_owned())
+        );
+        assert_eq!(txt_id(&["v=spf1 -all".to_owned()]), None);
+        assert_eq!(
+            txt_id(&["v=STSv1; id=1".to_owned(), "v=STSv1; id=2".to_owned()]),
+            None
+        );
+    }
+
+    #[derive(Debug)]
+    struct StubResolver(Mutex<Vec<String>>);
+
+    impl MxResolver for StubResolver {
+        fn mx(&self, _domain: &str) -> Result<Vec<MxRecord>, Box<dyn StdError + Send + Sync>> {
+            Ok(Vec::new())
+        }
+
+        fn ip(&self, _host: &str) -> Result<Vec<IpAddr>, Box<dyn StdError + Send + Sync>> {
+            Ok(Vec::new())
+        }
+
+        fn txt(&self, name: &str) -> Result<Vec<String>, Box<dyn StdError + Send + Sync>> {
+            assert_eq!(name, "_mta-sts.example.com");
+            Ok(self.0.lock().unwrap().clone())
+        }
+    }
+
+    #[derive(Debug, Default)]
+    struct StubFetcher(AtomicUsize);
+
+    impl MtaStsFetcher for Arc<StubFetcher> {
+        fn fetch(&self, domain: &str) -> Result<String, Box<dyn StdError + Send + Sync>> {
+            assert_eq!(domain, "example.com");
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(POLICY.to_owned())
+        }
+    }
+
+    #[test]
+    fn test_policy_cache() {
+        let resolver = StubResolver(Mutex::new(Vec::new()));
+        let fetcher = Arc::new(StubFetcher::default());
+        let mta_sts = MtaSts::new(Arc::new(Arc::clone(&fetcher)));
+
+        // No TXT record, no policy
+        assert_eq!(mta_sts.policy(&resolver, "example.com"), None);
+        assert_eq!(fetcher.0.load(Ordering::SeqCst), 0);
+
+        *resolver.0.lock().unwrap() = vec!["v=STSv1; id=1".to_owned()];
+        assert!(mta_sts.policy(&resolver, "example.com").is_some());
+        assert!(mta_sts.policy(&resolver, "example.com").is_some());
+        assert_eq!(fetcher.0.load(Ordering::SeqCst), 1);
+
+        // The policy is fetched again when its id changes
+        *resolver.0.lock().unwrap() = vec!["v=STSv1; id=2".to_owned()];
+        assert!(mta_sts.policy(&resolver, "example.com").is_some());
+        assert_eq!(fetcher.0.load(Ordering::SeqCst), 2);
+
+        // And kept when the TXT record disappears
+        resolver.0.lock().unwrap().clear();
+        assert!(mta_sts.policy(&resolver, "example.com").is_some());
+        assert_eq!(fetcher.0.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_filter_hosts() {
+        let policy = MtaStsPolicy::parse(POLICY).unwrap();
+        let hosts = vec!["mail.example.com".to_owned(), "mail.example.org".to_owned()];
+        assert_eq!(
+            filter_hosts(&policy, "example.com", hosts).unwrap(),
+            ["mail.example.com"]
+        );
+        assert!(
+            filter_hosts(&policy, "example.com", vec!["mail.example.org".to_owned()])
+                .unwrap_err()
+                .is_tls()
+        );
+
+        let testing = MtaStsPolicy {
+            mode: Mode::Testing,
+            ..policy
+        };
+        assert_eq!(
+            filter_hosts(&testing, "example.com", vec!["mail.example.org".to_owned()]).unwrap(),
+            ["mail.example.org"]
+        );
+    }
+}
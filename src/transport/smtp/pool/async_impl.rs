@@ -2,24 +2,38 @@ use std::{
     fmt::{self, Debug},
     mem,
     ops::{Deref, DerefMut},
-    sync::{Arc, OnceLock},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, OnceLock,
+    },
     time::{Duration, Instant},
 };
 
 use futures_util::{
+    future::{self, Either},
     lock::Mutex,
     stream::{self, StreamExt},
 };
 
 use super::{
-    super::{client::AsyncSmtpConnection, Error},
-    PoolConfig,
+    super::{
+        client::AsyncSmtpConnection,
+        error,
+        semaphore::{Permit, Semaphore},
+        Error,
+    },
+    is_stale, Metrics, PoolConfig, PoolStats, Usage,
 };
 use crate::{executor::SpawnHandle, transport::smtp::async_transport::AsyncSmtpClient, Executor};
 
 pub struct Pool<E: Executor> {
     config: PoolConfig,
     connections: Mutex<Vec<ParkedConnection>>,
+    metrics: Metrics,
+    closed: AtomicBool,
+    filling: Mutex<()>,
+    /// A permit is held by each connection in use
+    slots: Arc<Semaphore>,
     client: AsyncSmtpClient<E>,
     handle: OnceLock<E::Handle>,
 }
@@ -27,18 +41,28 @@ pub struct Pool<E: Executor> {
 struct ParkedConnection {
     conn: AsyncSmtpConnection,
     since: Instant,
+    /// When the connection was last known to be alive
+    checked: Instant,
+    usage: Usage,
 }
 
 pub struct PooledConnection<E: Executor> {
     conn: Option<AsyncSmtpConnection>,
     pool: Arc<Pool<E>>,
+    usage: Usage,
+    permit: Option<Permit>,
 }
 
 impl<E: Executor> Pool<E> {
     pub fn new(config: PoolConfig, client: AsyncSmtpClient<E>) -> Arc<Self> {
+        let slots = Semaphore::new(config.max_size.max(1) as usize);
         let pool = Arc::new(Self {
             config,
             connections: Mutex::new(Vec::new()),
+            metrics: Metrics::default(),
+            closed: AtomicBool::new(false),
+            filling: Mutex::new(()),
+            slots,
             client,
             handle: OnceLock::new(),
         });
@@ -46,8 +70,8 @@ impl<E: Executor> Pool<E> {
         {
             let pool_ = Arc::clone(&pool);
 
-            let min_idle = pool_.config.min_idle;
             let idle_timeout = pool_.config.idle_timeout;
+            let cleanup_interval = pool_.config.cleanup_interval();
             let pool = Arc::downgrade(&pool_);
 
             let handle = E::spawn(async move {
@@ -56,62 +80,49 @@ impl<E: Executor> Pool<E> {
                     tracing::trace!("running cleanup tasks");
 
                     match pool.upgrade() {
+                        Some(pool) if pool.is_closed() => break,
                         Some(pool) => {
                             #[allow(clippy::needless_collect)]
-                            let (count, dropped) = {
+                            let dropped = {
                                 let mut connections = pool.connections.lock().await;
 
                                 let to_drop = connections
                                     .iter()
                                     .enumerate()
                                     .rev()
-                                    .filter(|(_, conn)| conn.idle_duration() > idle_timeout)
+                                    .filter(|(_, conn)| {
+                                        conn.idle_duration() > idle_timeout
+                                            || conn.usage.is_exhausted(&pool.config)
+                                    })
                                     .map(|(i, _)| i)
                                     .collect::<Vec<_>>();
-                                let dropped = to_drop
+                                to_drop
                                     .into_iter()
                                     .map(|i| connections.remove(i))
-                                    .collect::<Vec<_>>();
-
-                                (connections.len(), dropped)
+                                    .collect::<Vec<_>>()
                             };
 
-                            #[cfg(feature = "tracing")]
-                            let mut created = 0;
-                            for _ in count..(min_idle as usize) {
-                                let conn = match pool.client.connection().await {
-                                    Ok(conn) => conn,
-                                    Err(err) => {
-                                        #[cfg(feature = "tracing")]
-                                        tracing::warn!("couldn't create idle connection {}", err);
-                                        #[cfg(not(feature = "tracing"))]
-                                        let _ = err;
-
-                                        break;
-                                    }
-                                };
-
-                                let mut connections = pool.connections.lock().await;
-                                connections.push(ParkedConnection::park(conn));
-
+                            if let Err(err) = pool.fill().await {
                                 #[cfg(feature = "tracing")]
-                                {
-                                    created += 1;
-                                }
+                                tracing::warn!("couldn't create idle connection {}", err);
+                                #[cfg(not(feature = "tracing"))]
+                                let _ = err;
                             }
 
-                            #[cfg(feature = "tracing")]
-                            if created > 0 {
-                                tracing::debug!("created {} idle connections", created);
-                            }
+                            pool.keep_alive().await;
 
                             if !dropped.is_empty() {
                                 #[cfg(feature = "tracing")]
                                 tracing::debug!("dropped {} idle connections", dropped.len());
 
+                                pool.metrics.dropped(dropped.len());
                                 abort_concurrent(dropped.into_iter().map(|conn| conn.unpark()))
                                     .await;
                             }
+
+                            if let Some(on_stats) = &pool.config.on_stats {
+                                on_stats(&pool.stats().await);
+                            }
                         }
                         None => {
                             #[cfg(feature = "tracing")]
@@ -122,7 +133,7 @@ impl<E: Executor> Pool<E> {
                         }
                     }
 
-                    E::sleep(idle_timeout).await;
+                    E::sleep(cleanup_interval).await;
                 }
             });
             pool_
@@ -135,6 +146,12 @@ impl<E: Executor> Pool<E> {
     }
 
     pub async fn connection(self: &Arc<Self>) -> Result<PooledConnection<E>, Error> {
+        if self.is_closed() {
+            return Err(error::client("the connection pool is closed"));
+        }
+
+        let start = Instant::now();
+        let permit = self.acquire().await?;
         loop {
             let conn = {
                 let mut connections = self.connections.lock().await;
@@ -143,13 +160,24 @@ impl<E: Executor> Pool<E> {
 
             match conn {
                 Some(conn) => {
+                    let usage = conn.usage;
+                    let validate = self.config.validation.is_needed(conn.checked.elapsed());
                     let mut conn = conn.unpark();
 
-                    // TODO: handle the client try another connection if this one isn't good
-                    if !conn.test_connected().await {
+                    if usage.is_exhausted(&self.config) {
+                        #[cfg(feature = "tracing")]
+                        tracing::debug!("dropping a connection which reached its lifetime");
+
+                        self.metrics.dropped(1);
+                        conn.abort().await;
+                        continue;
+                    }
+
+                    if validate && !conn.test_connected().await {
                         #[cfg(feature = "tracing")]
                         tracing::debug!("dropping a broken connection");
 
+                        self.metrics.broken();
                         conn.abort().await;
                         continue;
                     }
@@ -157,26 +185,203 @@ impl<E: Executor> Pool<E> {
                     #[cfg(feature = "tracing")]
                     tracing::debug!("reusing a pooled connection");
 
-                    return Ok(PooledConnection::wrap(conn, Arc::clone(self)));
+                    self.metrics.checked_out(start.elapsed());
+                    return Ok(PooledConnection::wrap(
+                        conn,
+                        Arc::clone(self),
+                        usage,
+                        permit,
+                    ));
                 }
-                None => {
-                    #[cfg(feature = "tracing")]
-                    tracing::debug!("creating a new connection");
+                None => return self.open(start, permit).await,
+            }
+        }
+    }
 
-                    let conn = self.client.connection().await?;
-                    return Ok(PooledConnection::wrap(conn, Arc::clone(self)));
-                }
+    /// Waits for one of the `max_size` slots to be free
+    async fn acquire(&self) -> Result<Permit, Error> {
+        let acquire = self.slots.acquire_async();
+        let Some(timeout) = self.config.acquire_timeout else {
+            return Ok(acquire.await);
+        };
+
+        match future::select(acquire, Box::pin(E::sleep(timeout))).await {
+            Either::Left((permit, _)) => Ok(permit),
+            Either::Right(_) => Err(error::pool_timeout()),
+        }
+    }
+
+    async fn open(
+        self: &Arc<Self>,
+        start: Instant,
+        permit: Permit,
+    ) -> Result<PooledConnection<E>, Error> {
+        #[cfg(feature = "tracing")]
+        tracing::debug!("creating a new connection");
+
+        let conn = self.client.connection().await?;
+        self.metrics.created();
+        self.metrics.checked_out(start.elapsed());
+        Ok(PooledConnection::wrap(
+            conn,
+            Arc::clone(self),
+            Usage::new(),
+            permit,
+        ))
+    }
+
+    /// Opens the missing idle connections, reporting errors
+    pub async fn warm_up(&self) -> Result<(), Error> {
+        if self.is_closed() {
+            return Err(error::client("the connection pool is closed"));
+        }
+
+        self.fill().await
+    }
+
+    /// Opens connections until there are `min_idle` idle connections
+    async fn fill(&self) -> Result<(), Error> {
+        // Keeps the cleanup task and `warm_up` from both opening the missing connections
+        let _filling = self.filling.lock().await;
+
+        let count = self.connections.lock().await.len();
+        for _ in count..(self.config.min_idle as usize) {
+            if self.is_closed() {
+                break;
+            }
+            // Only open connections which could be used right away
+            let Some(_permit) = self.slots.try_acquire() else {
+                break;
+            };
+            if self.connections.lock().await.len() > self.slots.available() {
+                break;
+            }
+
+            let mut conn = self.client.connection().await?;
+            self.metrics.created();
+            let mut connections = self.connections.lock().await;
+            // The pool may have been shut down while connecting
+            if self.is_closed() {
+                drop(connections);
+                self.metrics.dropped(1);
+                conn.abort().await;
+                break;
+            }
+            connections.push(ParkedConnection::park(conn, Usage::new()));
+            drop(connections);
+
+            #[cfg(feature = "tracing")]
+            tracing::debug!("created an idle connection");
+        }
+        Ok(())
+    }
+
+    /// Closes the idle connections with a `QUIT`, and the other ones as
+    /// soon as they are released
+    ///
+    /// Returns the first error, after trying to close all the connections.
+    pub async fn shutdown(&self) -> Result<(), Error> {
+        #[cfg(feature = "tracing")]
+        tracing::debug!("shutting down the pool");
+
+        self.closed.store(true, Ordering::Relaxed);
+        let connections = {
+            // Waits for a fill in progress, which could park connections afterwards
+            let _filling = self.filling.lock().await;
+            mem::take(&mut *self.connections.lock().await)
+        };
+        self.metrics.dropped(connections.len());
+
+        let mut result = Ok(());
+        for conn in connections {
+            let mut conn = conn.unpark();
+            result = result.and(conn.quit().await.map(drop));
+        }
+        result
+    }
+
+    /// Sends a `NOOP` to the connections idle for the keepalive interval,
+    /// dropping the broken ones
+    async fn keep_alive(&self) {
+        let Some(interval) = self.config.keepalive_interval else {
+            return;
+        };
+
+        #[allow(clippy::needless_collect)]
+        let idle = {
+            let mut connections = self.connections.lock().await;
+
+            let to_check = connections
+                .iter()
+                .enumerate()
+                .rev()
+                .filter(|(_, conn)| conn.checked.elapsed() >= interval)
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>();
+            let mut idle = Vec::with_capacity(to_check.len());
+            for i in to_check {
+                // Checked connections count as in use, so that the pool
+                // doesn't open new ones past `max_size` meanwhile
+                let Some(permit) = self.slots.try_acquire() else {
+                    break;
+                };
+                idle.push((connections.remove(i), permit));
+            }
+            idle
+        };
+
+        for (mut conn, permit) in idle {
+            if conn.conn.test_connected().await {
+                conn.checked = Instant::now();
+                // Keep the least recently used connections first
+                self.connections.lock().await.insert(0, conn);
+            } else {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("dropping a broken idle connection");
+
+                self.metrics.broken();
+                drop(permit);
+                conn.unpark().abort().await;
             }
         }
     }
 
-    async fn recycle(&self, mut conn: AsyncSmtpConnection) {
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
+
+    pub async fn stats(&self) -> PoolStats {
+        let idle = self.connections.lock().await.len();
+        self.metrics.stats(idle)
+    }
+
+    /// Parks a released connection, or closes it
+    ///
+    /// The `permit` is released once the connection is back in the pool, or
+    /// before closing it so that waiters don't wait on the network.
+    async fn recycle(&self, mut conn: AsyncSmtpConnection, usage: Usage, permit: Option<Permit>) {
         if conn.has_broken() {
             #[cfg(feature = "tracing")]
             tracing::debug!("dropping a broken connection instead of recycling it");
 
+            self.metrics.broken();
+            drop(permit);
             conn.abort().await;
             drop(conn);
+        } else if self.is_closed() {
+            #[cfg(feature = "tracing")]
+            tracing::debug!("closing connection released after the pool shutdown");
+
+            self.metrics.dropped(1);
+            drop(permit);
+            conn.abort().await;
+        } else if usage.is_exhausted(&self.config) {
+            #[cfg(feature = "tracing")]
+            tracing::debug!("closing a connection which reached its limits");
+
+            self.metrics.dropped(1);
+            drop(permit);
+            conn.abort().await;
         } else {
             #[cfg(feature = "tracing")]
             tracing::debug!("recycling connection");
@@ -184,10 +389,15 @@ impl<E: Executor> Pool<E> {
             let mut connections = self.connections.lock().await;
             if connections.len() >= self.config.max_size as usize {
                 drop(connections);
+                self.metrics.dropped(1);
+                drop(permit);
                 conn.abort().await;
             } else {
-                let conn = ParkedConnection::park(conn);
+                let conn = ParkedConnection::park(conn, usage);
                 connections.push(conn);
+                drop(connections);
+                self.metrics.recycled();
+                drop(permit);
             }
         }
     }
@@ -235,10 +445,13 @@ impl<E: Executor> Drop for Pool<E> {
 }
 
 impl ParkedConnection {
-    fn park(conn: AsyncSmtpConnection) -> Self {
+    fn park(conn: AsyncSmtpConnection, usage: Usage) -> Self {
+        let now = Instant::now();
         Self {
             conn,
-            since: Instant::now(),
+            since: now,
+            checked: now,
+            usage,
         }
     }
 
@@ -252,11 +465,43 @@ impl ParkedConnection {
 }
 
 impl<E: Executor> PooledConnection<E> {
-    fn wrap(conn: AsyncSmtpConnection, pool: Arc<Pool<E>>) -> Self {
+    fn wrap(
+        conn: AsyncSmtpConnection,
+        pool: Arc<Pool<E>>,
+        mut usage: Usage,
+        permit: Permit,
+    ) -> Self {
+        usage.uses += 1;
         Self {
             conn: Some(conn),
             pool,
+            usage,
+            permit: Some(permit),
+        }
+    }
+
+    /// Returns true if sending failed because this connection, taken from
+    /// the pool, had been closed by the server
+    ///
+    /// The email can then be sent again after [reconnecting](Self::reconnect).
+    pub fn is_stale(&self, error: &Error) -> bool {
+        is_stale(self.usage.uses > 1, self.has_sent_content(), error)
+    }
+
+    /// Replaces the connection with a new one
+    pub async fn reconnect(&mut self) -> Result<(), Error> {
+        let conn = self.pool.client.connection().await?;
+        self.pool.metrics.created();
+
+        let conn = self.conn.replace(conn);
+        self.usage = Usage::new();
+        self.usage.uses = 1;
+
+        if let Some(mut conn) = conn {
+            self.pool.metrics.broken();
+            conn.abort().await;
         }
+        Ok(())
     }
 }
 
@@ -280,10 +525,13 @@ impl<E: Executor> Drop for PooledConnection<E> {
             .conn
             .take()
             .expect("AsyncSmtpConnection hasn't been taken yet");
+        self.pool.metrics.checked_in();
         let pool = Arc::clone(&self.pool);
+        let usage = self.usage;
+        let permit = self.permit.take();
 
         E::spawn(async move {
-            pool.recycle(conn).await;
+            pool.recycle(conn, usage, permit).await;
         });
     }
 }
@@ -1,17 +1,34 @@
-use std::time::Duration;
+use std::{
+    fmt::{self, Debug},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use super::Error;
 
 #[cfg(any(feature = "tokio1", feature = "async-std1"))]
 pub mod async_impl;
 pub mod sync_impl;
 
+type StatsCallback = Arc<dyn Fn(&PoolStats) + Send + Sync>;
+
 /// Configuration for a connection pool
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 #[allow(missing_copy_implementations)]
 #[cfg_attr(docsrs, doc(cfg(feature = "pool")))]
 pub struct PoolConfig {
     min_idle: u32,
     max_size: u32,
     idle_timeout: Duration,
+    max_messages_per_connection: Option<u32>,
+    max_lifetime: Option<Duration>,
+    acquire_timeout: Option<Duration>,
+    validation: CheckoutValidation,
+    keepalive_interval: Option<Duration>,
+    on_stats: Option<StatsCallback>,
 }
 
 impl PoolConfig {
@@ -28,7 +45,10 @@ impl PoolConfig {
         self
     }
 
-    /// Maximum number of pooled connections
+    /// Maximum number of open connections, idle or in use
+    ///
+    /// When they are all in use, senders wait until one is released,
+    /// in the order they started waiting.
     ///
     /// Defaults to `10`
     pub fn max_size(mut self, max_size: u32) -> Self {
@@ -36,6 +56,12 @@ impl PoolConfig {
         self
     }
 
+    /// Lowers the maximum number of connections to `max_size`
+    pub(crate) fn limit_max_size(mut self, max_size: u32) -> Self {
+        self.max_size = self.max_size.min(max_size);
+        self
+    }
+
     /// Connection timeout
     ///
     /// Defaults to `30 seconds`
@@ -53,6 +79,93 @@ impl PoolConfig {
         self.idle_timeout = idle_timeout;
         self
     }
+
+    /// Maximum number of times a connection is used to send emails
+    ///
+    /// Connections are closed once they reach it, instead of being recycled.
+    /// Useful with servers closing their sessions after a number of emails.
+    ///
+    /// Defaults to no limit
+    pub fn max_messages_per_connection(mut self, max_messages: u32) -> Self {
+        self.max_messages_per_connection = Some(max_messages);
+        self
+    }
+
+    /// Maximum duration a connection is kept open
+    ///
+    /// Connections are closed once they have been open for longer, instead
+    /// of being recycled or reused.
+    ///
+    /// Defaults to no limit
+    pub fn max_lifetime(mut self, max_lifetime: Duration) -> Self {
+        self.max_lifetime = Some(max_lifetime);
+        self
+    }
+
+    /// Maximum duration to wait for a connection when [`max_size`](Self::max_size)
+    /// connections are in use
+    ///
+    /// Sending fails with an error for which
+    /// [`Error::is_pool_timeout`](super::Error::is_pool_timeout) returns true
+    /// when it elapses. `None` waits indefinitely.
+    ///
+    /// Defaults to `30 seconds`
+    pub fn acquire_timeout(mut self, acquire_timeout: Option<Duration>) -> Self {
+        self.acquire_timeout = acquire_timeout;
+        self
+    }
+
+    /// How idle connections are checked before being reused
+    ///
+    /// Defaults to [`CheckoutValidation::Always`]
+    pub fn validation(mut self, validation: CheckoutValidation) -> Self {
+        self.validation = validation;
+        self
+    }
+
+    /// Send a `NOOP` to the idle connections when they have been idle for
+    /// `interval`
+    ///
+    /// Keeps servers with a short idle timeout from closing them, and drops
+    /// the broken ones before they are reused.
+    ///
+    /// Defaults to no keepalive
+    pub fn keepalive_interval(mut self, interval: Duration) -> Self {
+        self.keepalive_interval = Some(interval);
+        self
+    }
+
+    /// Set a callback receiving the statistics of the pool
+    ///
+    /// It is called each time the pool runs its cleanup tasks, every
+    /// [`idle_timeout`](Self::idle_timeout) or
+    /// [`keepalive_interval`](Self::keepalive_interval) if it is shorter,
+    /// and can be used to export them to a metrics system.
+    pub fn on_stats<F>(mut self, on_stats: F) -> Self
+    where
+        F: Fn(&PoolStats) + Send + Sync + 'static,
+    {
+        self.on_stats = Some(Arc::new(on_stats));
+        self
+    }
+}
+
+impl Debug for PoolConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PoolConfig")
+            .field("min_idle", &self.min_idle)
+            .field("max_size", &self.max_size)
+            .field("idle_timeout", &self.idle_timeout)
+            .field(
+                "max_messages_per_connection",
+                &self.max_messages_per_connection,
+            )
+            .field("max_lifetime", &self.max_lifetime)
+            .field("acquire_timeout", &self.acquire_timeout)
+            .field("validation", &self.validation)
+            .field("keepalive_interval", &self.keepalive_interval)
+            .finish_non_exhaustive()
+    }
 }
 
 impl Default for PoolConfig {
@@ -61,6 +174,217 @@ impl Default for PoolConfig {
             min_idle: 0,
             max_size: 10,
             idle_timeout: Duration::from_secs(60),
+            max_messages_per_connection: None,
+            max_lifetime: None,
+            acquire_timeout: Some(Duration::from_secs(30)),
+            validation: CheckoutValidation::default(),
+            keepalive_interval: None,
+            on_stats: None,
+        }
+    }
+}
+
+impl PoolConfig {
+    /// Interval between two runs of the cleanup tasks
+    fn cleanup_interval(&self) -> Duration {
+        self.keepalive_interval
+            .map_or(self.idle_timeout, |interval| {
+                interval.min(self.idle_timeout)
+            })
+    }
+}
+
+/// How idle connections are checked before being reused
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(docsrs, doc(cfg(feature = "pool")))]
+pub enum CheckoutValidation {
+    /// Send a `NOOP` each time a connection is reused
+    #[default]
+    Always,
+    /// Send a `NOOP` if the connection was idle for longer than the duration,
+    /// since it was last used or checked
+    IdleLongerThan(Duration),
+    /// Never check the connection
+    ///
+    /// If the server closed it, it is replaced by a new one and the email
+    /// is sent again, as long as its content wasn't sent.
+    Never,
+}
+
+impl CheckoutValidation {
+    /// Returns true if a connection idle since `idle` must be checked
+    fn is_needed(self, idle: Duration) -> bool {
+        match self {
+            Self::Always => true,
+            Self::IdleLongerThan(max_idle) => idle > max_idle,
+            Self::Never => false,
+        }
+    }
+}
+
+/// Statistics about a connection pool
+///
+/// The counters are cumulative since the creation of the pool.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(docsrs, doc(cfg(feature = "pool")))]
+pub struct PoolStats {
+    idle: usize,
+    in_use: usize,
+    created: u64,
+    dropped: u64,
+    broken: u64,
+    recycled: u64,
+    checkouts: u64,
+    total_wait: Duration,
+    max_wait: Duration,
+}
+
+impl PoolStats {
+    /// Number of connections waiting in the pool
+    pub fn idle(&self) -> usize {
+        self.idle
+    }
+
+    /// Number of connections currently used to send emails
+    pub fn in_use(&self) -> usize {
+        self.in_use
+    }
+
+    /// Number of connections opened
+    pub fn created(&self) -> u64 {
+        self.created
+    }
+
+    /// Number of healthy connections closed, because they were idle for too
+    /// long or the pool was full
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Number of connections closed because they were broken
+    pub fn broken(&self) -> u64 {
+        self.broken
+    }
+
+    /// Number of connections put back in the pool after use
+    pub fn recycled(&self) -> u64 {
+        self.recycled
+    }
+
+    /// Number of connections taken from the pool
+    pub fn checkouts(&self) -> u64 {
+        self.checkouts
+    }
+
+    /// Total time spent waiting for a connection, including opening new ones
+    pub fn total_wait(&self) -> Duration {
+        self.total_wait
+    }
+
+    /// Longest time spent waiting for a connection
+    pub fn max_wait(&self) -> Duration {
+        self.max_wait
+    }
+
+    /// Average time spent waiting for a connection
+    pub fn average_wait(&self) -> Duration {
+        if self.checkouts == 0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(self.total_wait.as_secs_f64() / self.checkouts as f64)
+        }
+    }
+}
+
+/// When a connection was opened, and how many times it was used
+#[derive(Debug, Clone, Copy)]
+struct Usage {
+    opened: Instant,
+    uses: u32,
+}
+
+impl Usage {
+    fn new() -> Self {
+        Self {
+            opened: Instant::now(),
+            uses: 0,
+        }
+    }
+
+    /// Returns true if the connection has reached the limits of the configuration
+    fn is_exhausted(&self, config: &PoolConfig) -> bool {
+        config
+            .max_messages_per_connection
+            .is_some_and(|max_messages| self.uses >= max_messages)
+            || config
+                .max_lifetime
+                .is_some_and(|max_lifetime| self.opened.elapsed() >= max_lifetime)
+    }
+}
+
+/// Returns true if `error` means that a reused connection had been closed by
+/// the server, before the content of the email was sent
+///
+/// The email can't have been delivered, and is sent again with a new connection.
+fn is_stale(reused: bool, content_sent: bool, error: &Error) -> bool {
+    reused && !content_sent && (error.is_network() || error.status().map(u16::from) == Some(421))
+}
+
+/// Counters shared by the pool implementations
+#[derive(Debug, Default)]
+struct Metrics {
+    in_use: AtomicUsize,
+    created: AtomicU64,
+    dropped: AtomicU64,
+    broken: AtomicU64,
+    recycled: AtomicU64,
+    checkouts: AtomicU64,
+    /// In microseconds
+    total_wait: AtomicU64,
+    /// In microseconds
+    max_wait: AtomicU64,
+}
+
+impl Metrics {
+    fn created(&self) {
+        self.created.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn dropped(&self, count: usize) {
+        self.dropped.fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    fn broken(&self) {
+        self.broken.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn recycled(&self) {
+        self.recycled.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn checked_out(&self, wait: Duration) {
+        let wait = u64::try_from(wait.as_micros()).unwrap_or(u64::MAX);
+        self.in_use.fetch_add(1, Ordering::Relaxed);
+        self.checkouts.fetch_add(1, Ordering::Relaxed);
+        self.total_wait.fetch_add(wait, Ordering::Relaxed);
+        self.max_wait.fetch_max(wait, Ordering::Relaxed);
+    }
+
+    fn checked_in(&self) {
+        self.in_use.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    fn stats(&self, idle: usize) -> PoolStats {
+        PoolStats {
+            idle,
+            in_use: self.in_use.load(Ordering::Relaxed),
+            created: self.created.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            broken: self.broken.load(Ordering::Relaxed),
+            recycled: self.recycled.load(Ordering::Relaxed),
+            checkouts: self.checkouts.load(Ordering::Relaxed),
+            total_wait: Duration::from_micros(self.total_wait.load(Ordering::Relaxed)),
+            max_wait: Duration::from_micros(self.max_wait.load(Ordering::Relaxed)),
         }
     }
 }
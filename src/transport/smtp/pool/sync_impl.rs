@@ -2,114 +2,128 @@ use std::{
     fmt::{self, Debug},
     mem,
     ops::{Deref, DerefMut},
-    sync::{Arc, Mutex, TryLockError},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, TryLockError,
+    },
     thread,
     time::{Duration, Instant},
 };
 
 use super::{
-    super::{client::SmtpConnection, Error},
-    PoolConfig,
+    super::{
+        client::SmtpConnection,
+        error,
+        semaphore::{Permit, Semaphore},
+        Error,
+    },
+    is_stale, Metrics, PoolConfig, PoolStats, Usage,
 };
 use crate::transport::smtp::transport::SmtpClient;
 
 pub struct Pool {
     config: PoolConfig,
     connections: Mutex<Vec<ParkedConnection>>,
+    metrics: Metrics,
+    closed: AtomicBool,
+    filling: Mutex<()>,
+    /// A permit is held by each connection in use
+    slots: Arc<Semaphore>,
     client: SmtpClient,
 }
 
 struct ParkedConnection {
     conn: SmtpConnection,
     since: Instant,
+    /// When the connection was last known to be alive
+    checked: Instant,
+    usage: Usage,
 }
 
 pub struct PooledConnection {
     conn: Option<SmtpConnection>,
     pool: Arc<Pool>,
+    usage: Usage,
+    /// Released after the connection is back in the pool
+    permit: Option<Permit>,
 }
 
 impl Pool {
     pub fn new(config: PoolConfig, client: SmtpClient) -> Arc<Self> {
+        let slots = Semaphore::new(config.max_size.max(1) as usize);
         let pool = Arc::new(Self {
             config,
             connections: Mutex::new(Vec::new()),
+            metrics: Metrics::default(),
+            closed: AtomicBool::new(false),
+            filling: Mutex::new(()),
+            slots,
             client,
         });
 
         {
             let pool_ = Arc::clone(&pool);
 
-            let min_idle = pool_.config.min_idle;
             let idle_timeout = pool_.config.idle_timeout;
+            let cleanup_interval = pool_.config.cleanup_interval();
             let pool = Arc::downgrade(&pool_);
 
             thread::Builder::new()
                 .name("lettre-connection-pool".into())
                 .spawn(move || {
                     while let Some(pool) = pool.upgrade() {
+                        if pool.is_closed() {
+                            break;
+                        }
+
                         #[cfg(feature = "tracing")]
                         tracing::trace!("running cleanup tasks");
 
                         #[allow(clippy::needless_collect)]
-                        let (count, dropped) = {
+                        let dropped = {
                             let mut connections = pool.connections.lock().unwrap();
 
                             let to_drop = connections
                                 .iter()
                                 .enumerate()
                                 .rev()
-                                .filter(|(_, conn)| conn.idle_duration() > idle_timeout)
+                                .filter(|(_, conn)| {
+                                    conn.idle_duration() > idle_timeout
+                                        || conn.usage.is_exhausted(&pool.config)
+                                })
                                 .map(|(i, _)| i)
                                 .collect::<Vec<_>>();
-                            let dropped = to_drop
+                            to_drop
                                 .into_iter()
                                 .map(|i| connections.remove(i))
-                                .collect::<Vec<_>>();
-
-                            (connections.len(), dropped)
+                                .collect::<Vec<_>>()
                         };
 
-                        #[cfg(feature = "tracing")]
-                        let mut created = 0;
-                        for _ in count..(min_idle as usize) {
-                            let conn = match pool.client.connection() {
-                                Ok(conn) => conn,
-                                Err(err) => {
-                                    #[cfg(feature = "tracing")]
-                                    tracing::warn!("couldn't create idle connection {}", err);
-                                    #[cfg(not(feature = "tracing"))]
-                                    let _ = err;
-
-                                    break;
-                                }
-                            };
-
-                            let mut connections = pool.connections.lock().unwrap();
-                            connections.push(ParkedConnection::park(conn));
-
+                        if let Err(err) = pool.fill() {
                             #[cfg(feature = "tracing")]
-                            {
-                                created += 1;
-                            }
+                            tracing::warn!("couldn't create idle connection {}", err);
+                            #[cfg(not(feature = "tracing"))]
+                            let _ = err;
                         }
 
-                        #[cfg(feature = "tracing")]
-                        if created > 0 {
-                            tracing::debug!("created {} idle connections", created);
-                        }
+                        pool.keep_alive();
 
                         if !dropped.is_empty() {
                             #[cfg(feature = "tracing")]
                             tracing::debug!("dropped {} idle connections", dropped.len());
 
+                            pool.metrics.dropped(dropped.len());
                             for conn in dropped {
                                 let mut conn = conn.unpark();
                                 conn.abort();
                             }
                         }
 
-                        thread::sleep(idle_timeout);
+                        if let Some(on_stats) = &pool.config.on_stats {
+                            on_stats(&pool.stats());
+                        }
+
+                        thread::sleep(cleanup_interval);
                     }
                 })
                 .expect("couldn't spawn the Pool thread");
@@ -119,6 +133,12 @@ impl Pool {
     }
 
     pub fn connection(self: &Arc<Self>) -> Result<PooledConnection, Error> {
+        if self.is_closed() {
+            return Err(error::client("the connection pool is closed"));
+        }
+
+        let start = Instant::now();
+        let permit = self.acquire()?;
         loop {
             let conn = {
                 let mut connections = self.connections.lock().unwrap();
@@ -127,13 +147,24 @@ impl Pool {
 
             match conn {
                 Some(conn) => {
+                    let usage = conn.usage;
+                    let validate = self.config.validation.is_needed(conn.checked.elapsed());
                     let mut conn = conn.unpark();
 
-                    // TODO: handle the client try another connection if this one isn't good
-                    if !conn.test_connected() {
+                    if usage.is_exhausted(&self.config) {
+                        #[cfg(feature = "tracing")]
+                        tracing::debug!("dropping a connection which reached its lifetime");
+
+                        self.metrics.dropped(1);
+                        conn.abort();
+                        continue;
+                    }
+
+                    if validate && !conn.test_connected() {
                         #[cfg(feature = "tracing")]
                         tracing::debug!("dropping a broken connection");
 
+                        self.metrics.broken();
                         conn.abort();
                         continue;
                     }
@@ -141,26 +172,193 @@ impl Pool {
                     #[cfg(feature = "tracing")]
                     tracing::debug!("reusing a pooled connection");
 
-                    return Ok(PooledConnection::wrap(conn, Arc::clone(self)));
+                    self.metrics.checked_out(start.elapsed());
+                    return Ok(PooledConnection::wrap(
+                        conn,
+                        Arc::clone(self),
+                        usage,
+                        permit,
+                    ));
                 }
-                None => {
-                    #[cfg(feature = "tracing")]
-                    tracing::debug!("creating a new connection");
+                None => return self.open(start, permit),
+            }
+        }
+    }
 
-                    let conn = self.client.connection()?;
-                    return Ok(PooledConnection::wrap(conn, Arc::clone(self)));
-                }
+    /// Waits for one of the `max_size` slots to be free
+    fn acquire(&self) -> Result<Permit, Error> {
+        self.slots
+            .acquire(self.config.acquire_timeout)
+            .ok_or_else(error::pool_timeout)
+    }
+
+    fn open(self: &Arc<Self>, start: Instant, permit: Permit) -> Result<PooledConnection, Error> {
+        #[cfg(feature = "tracing")]
+        tracing::debug!("creating a new connection");
+
+        let conn = self.client.connection()?;
+        self.metrics.created();
+        self.metrics.checked_out(start.elapsed());
+        Ok(PooledConnection::wrap(
+            conn,
+            Arc::clone(self),
+            Usage::new(),
+            permit,
+        ))
+    }
+
+    /// Opens the missing idle connections, reporting errors
+    pub fn warm_up(&self) -> Result<(), Error> {
+        if self.is_closed() {
+            return Err(error::client("the connection pool is closed"));
+        }
+
+        self.fill()
+    }
+
+    /// Opens connections until there are `min_idle` idle connections
+    fn fill(&self) -> Result<(), Error> {
+        // Keeps the cleanup task and `warm_up` from both opening the missing connections
+        let _filling = self.filling.lock().unwrap();
+
+        let count = self.connections.lock().unwrap().len();
+        for _ in count..(self.config.min_idle as usize) {
+            if self.is_closed() {
+                break;
+            }
+            // Only open connections which could be used right away
+            let Some(_permit) = self.slots.try_acquire() else {
+                break;
+            };
+            if self.connections.lock().unwrap().len() > self.slots.available() {
+                break;
+            }
+
+            let mut conn = self.client.connection()?;
+            self.metrics.created();
+            let mut connections = self.connections.lock().unwrap();
+            // The pool may have been shut down while connecting
+            if self.is_closed() {
+                drop(connections);
+                self.metrics.dropped(1);
+                conn.abort();
+                break;
+            }
+            connections.push(ParkedConnection::park(conn, Usage::new()));
+            drop(connections);
+
+            #[cfg(feature = "tracing")]
+            tracing::debug!("created an idle connection");
+        }
+        Ok(())
+    }
+
+    /// Closes the idle connections with a `QUIT`, and the other ones as
+    /// soon as they are released
+    ///
+    /// Returns the first error, after trying to close all the connections.
+    pub fn shutdown(&self) -> Result<(), Error> {
+        #[cfg(feature = "tracing")]
+        tracing::debug!("shutting down the pool");
+
+        self.closed.store(true, Ordering::Relaxed);
+        let connections = {
+            // Waits for a fill in progress, which could park connections afterwards
+            let _filling = self.filling.lock().unwrap();
+            mem::take(&mut *self.connections.lock().unwrap())
+        };
+        self.metrics.dropped(connections.len());
+
+        let mut result = Ok(());
+        for conn in connections {
+            let mut conn = conn.unpark();
+            result = result.and(conn.quit().map(drop));
+        }
+        result
+    }
+
+    /// Sends a `NOOP` to the connections idle for the keepalive interval,
+    /// dropping the broken ones
+    fn keep_alive(&self) {
+        let Some(interval) = self.config.keepalive_interval else {
+            return;
+        };
+
+        #[allow(clippy::needless_collect)]
+        let idle = {
+            let mut connections = self.connections.lock().unwrap();
+
+            let to_check = connections
+                .iter()
+                .enumerate()
+                .rev()
+                .filter(|(_, conn)| conn.checked.elapsed() >= interval)
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>();
+            let mut idle = Vec::with_capacity(to_check.len());
+            for i in to_check {
+                // Checked connections count as in use, so that the pool
+                // doesn't open new ones past `max_size` meanwhile
+                let Some(permit) = self.slots.try_acquire() else {
+                    break;
+                };
+                idle.push((connections.remove(i), permit));
+            }
+            idle
+        };
+
+        for (mut conn, permit) in idle {
+            if conn.conn.test_connected() {
+                conn.checked = Instant::now();
+                // Keep the least recently used connections first
+                self.connections.lock().unwrap().insert(0, conn);
+            } else {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("dropping a broken idle connection");
+
+                self.metrics.broken();
+                drop(permit);
+                conn.unpark().abort();
             }
         }
     }
 
-    fn recycle(&self, mut conn: SmtpConnection) {
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        let idle = self.connections.lock().unwrap().len();
+        self.metrics.stats(idle)
+    }
+
+    /// Parks a released connection, or closes it
+    ///
+    /// The `permit` is released once the connection is back in the pool, or
+    /// before closing it so that waiters don't wait on the network.
+    fn recycle(&self, mut conn: SmtpConnection, usage: Usage, permit: Option<Permit>) {
         if conn.has_broken() {
             #[cfg(feature = "tracing")]
             tracing::debug!("dropping a broken connection instead of recycling it");
 
+            self.metrics.broken();
+            drop(permit);
             conn.abort();
             drop(conn);
+        } else if self.is_closed() {
+            #[cfg(feature = "tracing")]
+            tracing::debug!("closing connection released after the pool shutdown");
+
+            self.metrics.dropped(1);
+            drop(permit);
+            conn.abort();
+        } else if usage.is_exhausted(&self.config) {
+            #[cfg(feature = "tracing")]
+            tracing::debug!("closing a connection which reached its limits");
+
+            self.metrics.dropped(1);
+            drop(permit);
+            conn.abort();
         } else {
             #[cfg(feature = "tracing")]
             tracing::debug!("recycling connection");
@@ -168,10 +366,15 @@ impl Pool {
             let mut connections = self.connections.lock().unwrap();
             if connections.len() >= self.config.max_size as usize {
                 drop(connections);
+                self.metrics.dropped(1);
+                drop(permit);
                 conn.abort();
             } else {
-                let conn = ParkedConnection::park(conn);
+                let conn = ParkedConnection::park(conn, usage);
                 connections.push(conn);
+                drop(connections);
+                self.metrics.recycled();
+                drop(permit);
             }
         }
     }
@@ -209,10 +412,13 @@ impl Drop for Pool {
 }
 
 impl ParkedConnection {
-    fn park(conn: SmtpConnection) -> Self {
+    fn park(conn: SmtpConnection, usage: Usage) -> Self {
+        let now = Instant::now();
         Self {
             conn,
-            since: Instant::now(),
+            since: now,
+            checked: now,
+            usage,
         }
     }
 
@@ -226,11 +432,38 @@ impl ParkedConnection {
 }
 
 impl PooledConnection {
-    fn wrap(conn: SmtpConnection, pool: Arc<Pool>) -> Self {
+    fn wrap(conn: SmtpConnection, pool: Arc<Pool>, mut usage: Usage, permit: Permit) -> Self {
+        usage.uses += 1;
         Self {
             conn: Some(conn),
             pool,
+            usage,
+            permit: Some(permit),
+        }
+    }
+
+    /// Returns true if sending failed because this connection, taken from
+    /// the pool, had been closed by the server
+    ///
+    /// The email can then be sent again after [reconnecting](Self::reconnect).
+    pub fn is_stale(&self, error: &Error) -> bool {
+        is_stale(self.usage.uses > 1, self.has_sent_content(), error)
+    }
+
+    /// Replaces the connection with a new one
+    pub fn reconnect(&mut self) -> Result<(), Error> {
+        let conn = self.pool.client.connection()?;
+        self.pool.metrics.created();
+
+        let conn = self.conn.replace(conn);
+        self.usage = Usage::new();
+        self.usage.uses = 1;
+
+        if let Some(mut conn) = conn {
+            self.pool.metrics.broken();
+            conn.abort();
         }
+        Ok(())
     }
 }
 
@@ -254,6 +487,8 @@ impl Drop for PooledConnection {
             .conn
             .take()
             .expect("SmtpConnection hasn't been taken yet");
-        self.pool.recycle(conn);
+        self.pool.metrics.checked_in();
+        let permit = self.permit.take();
+        self.pool.recycle(conn, self.usage, permit);
     }
 }
@@ -0,0 +1,212 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use super::semaphore::{Permit, Semaphore};
+#[cfg(feature = "pool")]
+use super::PoolConfig;
+
+/// Limits how fast a SMTP transport sends emails
+///
+/// Senders exceeding the limits wait (or await with the async transport)
+/// until they can proceed, instead of being rejected by the server.
+/// The limits apply to all the clones of the transport.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RateLimit {
+    messages_per_second: u32,
+    recipients_per_minute: u32,
+    max_connections: u32,
+}
+
+impl RateLimit {
+    /// Create a new configuration, without any limit
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maximum number of emails sent per second
+    ///
+    /// Defaults to `0`, for no limit
+    pub fn messages_per_second(mut self, messages: u32) -> Self {
+        self.messages_per_second = messages;
+        self
+    }
+
+    /// Maximum number of recipients of the emails sent per minute
+    ///
+    /// Defaults to `0`, for no limit
+    pub fn recipients_per_minute(mut self, recipients: u32) -> Self {
+        self.recipients_per_minute = recipients;
+        self
+    }
+
+    /// Maximum number of connections to the server at the same time
+    ///
+    /// With the `pool` feature, this lowers the `max_size` of the pool, which
+    /// then counts all its connections, idle ones included.
+    /// Otherwise, each email is sent over its own connection and this limits
+    /// the number of emails being sent at the same time.
+    ///
+    /// Defaults to `0`, for no limit
+    pub fn max_connections(mut self, connections: u32) -> Self {
+        self.max_connections = connections;
+        self
+    }
+
+    /// Applies the connections limit to the pool, which enforces it
+    #[cfg(feature = "pool")]
+    pub(crate) fn limit_pool(&self, pool_config: PoolConfig) -> PoolConfig {
+        if self.max_connections > 0 {
+            pool_config.limit_max_size(self.max_connections)
+        } else {
+            pool_config
+        }
+    }
+}
+
+/// Enforces a [`RateLimit`]
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    messages: Option<Mutex<Bucket>>,
+    recipients: Option<Mutex<Bucket>>,
+    /// Without the `pool` feature, limits the emails being sent at the same time
+    connections: Option<Arc<Semaphore>>,
+}
+
+/// Token bucket, filled at a constant rate
+#[derive(Debug)]
+struct Bucket {
+    capacity: f64,
+    /// Tokens added per second
+    rate: f64,
+    /// Negative when tokens have been reserved in advance
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(limit: RateLimit) -> Arc<Self> {
+        Arc::new(Self {
+            messages: (limit.messages_per_second > 0).then(|| {
+                Mutex::new(Bucket::new(
+                    limit.messages_per_second,
+                    Duration::from_secs(1),
+                ))
+            }),
+            recipients: (limit.recipients_per_minute > 0).then(|| {
+                Mutex::new(Bucket::new(
+                    limit.recipients_per_minute,
+                    Duration::from_secs(60),
+                ))
+            }),
+            // The pool limits its connections itself
+            connections: (!cfg!(feature = "pool") && limit.max_connections > 0)
+                .then(|| Semaphore::new(limit.max_connections as usize)),
+        })
+    }
+
+    /// Waits for a connection to be allowed, the permit must be kept while it is used
+    pub(crate) fn connection(&self) -> Option<Permit> {
+        self.connections
+            .as_ref()
+            .map(|connections| connections.acquire(None).expect("no timeout"))
+    }
+
+    /// Waits asynchronously for a connection to be allowed, the permit must
+    /// be kept while it is used
+    #[cfg(any(feature = "tokio1", feature = "async-std1"))]
+    pub(crate) async fn connection_async(&self) -> Option<Permit> {
+        match &self.connections {
+            Some(connections) => Some(connections.acquire_async().await),
+            None => None,
+        }
+    }
+
+    /// Reserves sending an email to `recipients` recipients, returning how
+    /// long to wait before sending it
+    pub(crate) fn delay(&self, recipients: usize) -> Duration {
+        let now = Instant::now();
+        let messages = self.messages.as_ref().map_or(Duration::ZERO, |bucket| {
+            bucket.lock().unwrap().reserve(1.0, now)
+        });
+        let recipients = self.recipients.as_ref().map_or(Duration::ZERO, |bucket| {
+            bucket.lock().unwrap().reserve(recipients as f64, now)
+        });
+        messages.max(recipients)
+    }
+}
+
+impl Bucket {
+    fn new(count: u32, period: Duration) -> Self {
+        let capacity = f64::from(count);
+        Self {
+            capacity,
+            rate: capacity / period.as_secs_f64(),
+            tokens: capacity,
+            updated: Instant::now(),
+        }
+    }
+
+    /// Takes `tokens` tokens, returning how long to wait until they are available
+    fn reserve(&mut self, tokens: f64, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.updated = now;
+
+        self.tokens -= tokens;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_bucket() {
+        let now = Instant::now();
+        let mut bucket = Bucket {
+            capacity: 2.0,
+            rate: 2.0,
+            tokens: 2.0,
+            updated: now,
+        };
+
+        assert_eq!(bucket.reserve(1.0, now), Duration::ZERO);
+        assert_eq!(bucket.reserve(1.0, now), Duration::ZERO);
+        assert_eq!(bucket.reserve(1.0, now), Duration::from_millis(500));
+        assert_eq!(bucket.reserve(1.0, now), Duration::from_secs(1));
+
+        // The reserved tokens are paid back first
+        let later = now + Duration::from_secs(1);
+        assert_eq!(bucket.reserve(1.0, later), Duration::from_millis(500));
+
+        // Unused tokens don't accumulate beyond the capacity
+        let much_later = now + Duration::from_secs(60);
+        assert_eq!(bucket.reserve(2.0, much_later), Duration::ZERO);
+        assert_eq!(bucket.reserve(1.0, much_later), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new(RateLimit::new().recipients_per_minute(60));
+        assert_eq!(limiter.delay(60), Duration::ZERO);
+        assert!(limiter.delay(1) > Duration::from_millis(900));
+
+        let limiter = RateLimiter::new(RateLimit::new().max_connections(1));
+        let permit = limiter.connection();
+        if cfg!(feature = "pool") {
+            assert!(permit.is_none());
+        } else {
+            assert!(permit.is_some());
+            assert_eq!(limiter.connections.as_ref().unwrap().available(), 0);
+            drop(permit);
+            assert_eq!(limiter.connections.as_ref().unwrap().available(), 1);
+        }
+    }
+}
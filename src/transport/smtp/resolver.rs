@@ -0,0 +1,155 @@
+use std::{
+    collections::HashMap,
+    error::Error as StdError,
+    fmt::Debug,
+    net::{IpAddr, SocketAddr},
+};
+
+#[cfg(any(feature = "tokio1", feature = "async-std1"))]
+use async_trait::async_trait;
+
+/// Resolves the host name of the server to the addresses to connect to
+///
+/// By default, the host name is resolved by the system.
+/// See [`SmtpTransportBuilder::resolver`].
+///
+/// Unlike `mx::MxResolver`, which does all the DNS lookups needed to deliver
+/// to the mail exchangers of a domain, this only maps the host name of a
+/// relay to addresses. `mx::DnsResolver` implements both.
+///
+/// [`SmtpTransportBuilder::resolver`]: crate::transport::smtp::SmtpTransportBuilder::resolver
+pub trait Resolver: Debug + Send + Sync {
+    /// Returns the addresses of `host`, with `port`
+    fn resolve(
+        &self,
+        host: &str,
+        port: u16,
+    ) -> Result<Vec<SocketAddr>, Box<dyn StdError + Send + Sync>>;
+}
+
+/// Asynchronously resolves the host name of the server to the addresses to connect to
+///
+/// Async counterpart of [`Resolver`].
+/// See [`AsyncSmtpTransportBuilder::resolver`].
+///
+/// [`AsyncSmtpTransportBuilder::resolver`]: crate::transport::smtp::AsyncSmtpTransportBuilder::resolver
+#[cfg(any(feature = "tokio1", feature = "async-std1"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "tokio1", feature = "async-std1"))))]
+#[async_trait]
+pub trait AsyncResolver: Debug + Send + Sync {
+    /// Returns the addresses of `host`, with `port`
+    async fn resolve(
+        &self,
+        host: &str,
+        port: u16,
+    ) -> Result<Vec<SocketAddr>, Box<dyn StdError + Send + Sync>>;
+}
+
+/// [`Resolver`] returning fixed addresses for each host name
+///
+/// Useful to pin servers to known addresses, or to connect to local
+/// servers in tests. Host names without addresses fail to resolve.
+///
+/// # Examples
+///
+/// ```rust
+/// # #[cfg(any(feature = "native-tls", feature = "rustls-tls"))]
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use std::net::Ipv4Addr;
+///
+/// use lettre::{transport::smtp::StaticResolver, SmtpTransport};
+///
+/// let resolver =
+///     StaticResolver::new().host("smtp.example.com", [Ipv4Addr::new(192, 0, 2, 25).into()]);
+/// let sender = SmtpTransport::relay("smtp.example.com")?
+///     .resolver(resolver)
+///     .build();
+/// # Ok(())
+/// # }
+/// # #[cfg(not(any(feature = "native-tls", feature = "rustls-tls")))]
+/// # fn main() {}
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct StaticResolver {
+    hosts: HashMap<String, Vec<IpAddr>>,
+}
+
+impl StaticResolver {
+    /// Create a resolver without any host
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resolve `host` to `addresses`, tried in this order
+    ///
+    /// Host names are compared case-insensitively.
+    pub fn host<I>(mut self, host: &str, addresses: I) -> Self
+    where
+        I: IntoIterator<Item = IpAddr>,
+    {
+        self.hosts
+            .entry(host.to_ascii_lowercase())
+            .or_default()
+            .extend(addresses);
+        self
+    }
+
+    fn lookup(
+        &self,
+        host: &str,
+        port: u16,
+    ) -> Result<Vec<SocketAddr>, Box<dyn StdError + Send + Sync>> {
+        match self.hosts.get(&host.to_ascii_lowercase()) {
+            Some(addresses) if !addresses.is_empty() => Ok(addresses
+                .iter()
+                .map(|&address| SocketAddr::new(address, port))
+                .collect()),
+            _ => Err(format!("no address configured for {host}").into()),
+        }
+    }
+}
+
+impl Resolver for StaticResolver {
+    fn resolve(
+        &self,
+        host: &str,
+        port: u16,
+    ) -> Result<Vec<SocketAddr>, Box<dyn StdError + Send + Sync>> {
+        self.lookup(host, port)
+    }
+}
+
+#[cfg(any(feature = "tokio1", feature = "async-std1"))]
+#[async_trait]
+impl AsyncResolver for StaticResolver {
+    async fn resolve(
+        &self,
+        host: &str,
+        port: u16,
+    ) -> Result<Vec<SocketAddr>, Box<dyn StdError + Send + Sync>> {
+        self.lookup(host, port)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use super::*;
+
+    #[test]
+    fn test_static_resolver() {
+        let resolver = StaticResolver::new()
+            .host("smtp.example.com", [Ipv6Addr::LOCALHOST.into()])
+            .host("SMTP.example.com", [Ipv4Addr::LOCALHOST.into()]);
+
+        assert_eq!(
+            Resolver::resolve(&resolver, "smtp.EXAMPLE.com", 25).unwrap(),
+            [
+                SocketAddr::from((Ipv6Addr::LOCALHOST, 25)),
+                SocketAddr::from((Ipv4Addr::LOCALHOST, 25))
+            ]
+        );
+        assert!(Resolver::resolve(&resolver, "example.com", 25).is_err());
+    }
+}
@@ -16,7 +16,10 @@ use nom::{
     IResult,
 };
 
-use crate::transport::smtp::{error, Error};
+use crate::{
+    transport::smtp::{error, Error},
+    Address,
+};
 
 /// The first digit indicates severity
 #[derive(PartialEq, Eq, Copy, Clone, Debug)]
@@ -139,8 +142,9 @@ impl From<Code> for u16 {
 
 /// Contains an SMTP reply, with separated code and message
 ///
-/// The text message is optional, only the code is mandatory
-#[derive(PartialEq, Eq, Clone, Debug)]
+/// The text message is optional, only the code is mandatory. The recipients
+/// of a partial delivery are neither compared nor serialized.
+#[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Response {
     /// Response code
@@ -148,6 +152,53 @@ pub struct Response {
     /// Server response string (optional)
     /// Handle multiline responses
     message: Vec<String>,
+    /// Recipients accepted during a partial delivery
+    #[cfg_attr(feature = "serde", serde(skip))]
+    accepted_recipients: Vec<RecipientResponse>,
+    /// Recipients rejected during a partial delivery
+    #[cfg_attr(feature = "serde", serde(skip))]
+    rejected_recipients: Vec<RecipientResponse>,
+}
+
+impl PartialEq for Response {
+    fn eq(&self, other: &Self) -> bool {
+        self.code == other.code && self.message == other.message
+    }
+}
+
+impl Eq for Response {}
+
+/// The reply of the server to the `RCPT TO` command of a recipient
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct RecipientResponse {
+    address: Address,
+    code: Code,
+    message: Vec<String>,
+}
+
+impl RecipientResponse {
+    pub(crate) fn new(address: Address, response: Response) -> Self {
+        Self {
+            address,
+            code: response.code,
+            message: response.message,
+        }
+    }
+
+    /// The recipient
+    pub fn address(&self) -> &Address {
+        &self.address
+    }
+
+    /// Response code
+    pub fn code(&self) -> Code {
+        self.code
+    }
+
+    /// Server response string (array of lines)
+    pub fn message(&self) -> impl Iterator<Item = &str> {
+        self.message.iter().map(String::as_str)
+    }
 }
 
 impl FromStr for Response {
@@ -163,7 +214,12 @@ impl FromStr for Response {
 impl Response {
     /// Creates a new `Response`
     pub fn new(code: Code, message: Vec<String>) -> Response {
-        Response { code, message }
+        Response {
+            code,
+            message,
+            accepted_recipients: Vec::new(),
+            rejected_recipients: Vec::new(),
+        }
     }
 
     /// Tells if the response is positive
@@ -197,6 +253,46 @@ impl Response {
     pub fn message(&self) -> impl Iterator<Item = &str> {
         self.message.iter().map(String::as_str)
     }
+
+    /// Recipients accepted by the server, with their `RCPT TO` responses
+    ///
+    /// Only filled in the response to a message sent with partial delivery
+    /// enabled, see [`SmtpTransportBuilder::partial_delivery`].
+    ///
+    /// [`SmtpTransportBuilder::partial_delivery`]: crate::transport::smtp::SmtpTransportBuilder::partial_delivery
+    pub fn accepted_recipients(&self) -> &[RecipientResponse] {
+        &self.accepted_recipients
+    }
+
+    /// Recipients rejected by the server, with their `RCPT TO` responses
+    ///
+    /// Only filled in the response to a message sent with partial delivery
+    /// enabled, see [`SmtpTransportBuilder::partial_delivery`]. The message
+    /// was not delivered to these recipients.
+    ///
+    /// [`SmtpTransportBuilder::partial_delivery`]: crate::transport::smtp::SmtpTransportBuilder::partial_delivery
+    pub fn rejected_recipients(&self) -> &[RecipientResponse] {
+        &self.rejected_recipients
+    }
+
+    /// Attaches the `RCPT TO` responses of a partial delivery
+    pub(crate) fn set_recipients(
+        &mut self,
+        accepted: Vec<RecipientResponse>,
+        rejected: Vec<RecipientResponse>,
+    ) {
+        self.accepted_recipients = accepted;
+        self.rejected_recipients = rejected;
+    }
+
+    /// Turns a negative response into the matching error
+    pub(crate) fn into_result(self) -> result::Result<Response, Error> {
+        if self.is_positive() {
+            Ok(self)
+        } else {
+            Err(error::code(self.code, Some(self.message.concat())))
+        }
+    }
 }
 
 // Parsers (originally from tokio-smtp)
@@ -272,13 +368,7 @@ pub(crate) fn parse_response(i: &str) -> IResult<&str, Response> {
     let mut lines: Vec<String> = lines.into_iter().map(|(_, text, _)| text.into()).collect();
     lines.push(last_line.into());
 
-    Ok((
-        i,
-        Response {
-            code: last_code,
-            message: lines,
-        },
-    ))
+    Ok((i, Response::new(last_code, lines)))
 }
 
 #[cfg(test)]
@@ -338,19 +428,19 @@ mod test {
         let raw_response = "250-me\r\n250-8BITMIME\r\n250-SIZE 42\r\n250 AUTH PLAIN CRAM-MD5\r\n";
         assert_eq!(
             raw_response.parse::<Response>().unwrap(),
-            Response {
-                code: Code {
+            Response::new(
+                Code {
                     severity: Severity::PositiveCompletion,
                     category: Category::MailSystem,
                     detail: Detail::Zero,
                 },
-                message: vec![
+                vec![
                     "me".to_owned(),
                     "8BITMIME".to_owned(),
                     "SIZE 42".to_owned(),
                     "AUTH PLAIN CRAM-MD5".to_owned(),
                 ],
-            }
+            )
         );
 
         let wrong_code = "2506-me\r\n250-8BITMIME\r\n250-SIZE 42\r\n250 AUTH PLAIN CRAM-MD5\r\n";
@@ -0,0 +1,266 @@
+//! A fair semaphore, usable from both sync and async code
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Condvar, Mutex},
+    task::Waker,
+    time::{Duration, Instant},
+};
+#[cfg(any(feature = "tokio1", feature = "async-std1"))]
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Limits how many permits are held at the same time
+///
+/// Waiters are served in the order they started waiting.
+#[derive(Debug)]
+pub(crate) struct Semaphore {
+    state: Mutex<State>,
+    condvar: Condvar,
+}
+
+#[derive(Debug)]
+struct State {
+    permits: usize,
+    next_id: u64,
+    waiters: VecDeque<Waiter>,
+}
+
+#[derive(Debug)]
+struct Waiter {
+    id: u64,
+    waker: Option<Waker>,
+}
+
+/// A permit, given back to the semaphore when dropped
+#[derive(Debug)]
+pub(crate) struct Permit {
+    semaphore: Arc<Semaphore>,
+}
+
+impl Semaphore {
+    pub(crate) fn new(permits: usize) -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::new(State {
+                permits,
+                next_id: 0,
+                waiters: VecDeque::new(),
+            }),
+            condvar: Condvar::new(),
+        })
+    }
+
+    /// Waits for a permit, for at most `timeout`
+    ///
+    /// Returns `None` on timeout.
+    pub(crate) fn acquire(self: &Arc<Self>, timeout: Option<Duration>) -> Option<Permit> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut state = self.state.lock().unwrap();
+        if state.waiters.is_empty() && state.permits > 0 {
+            state.permits -= 1;
+            return Some(self.permit());
+        }
+
+        let id = state.enqueue();
+        loop {
+            if state.try_take(id) {
+                self.notify(&mut state);
+                return Some(self.permit());
+            }
+
+            state = match deadline {
+                Some(deadline) => {
+                    let Some(timeout) = deadline.checked_duration_since(Instant::now()) else {
+                        state.waiters.retain(|waiter| waiter.id != id);
+                        self.notify(&mut state);
+                        return None;
+                    };
+                    self.condvar.wait_timeout(state, timeout).unwrap().0
+                }
+                None => self.condvar.wait(state).unwrap(),
+            };
+        }
+    }
+
+    /// Takes a permit if one is available, without waiting
+    #[cfg(feature = "pool")]
+    pub(crate) fn try_acquire(self: &Arc<Self>) -> Option<Permit> {
+        let mut state = self.state.lock().unwrap();
+        if state.waiters.is_empty() && state.permits > 0 {
+            state.permits -= 1;
+            Some(self.permit())
+        } else {
+            None
+        }
+    }
+
+    /// Waits asynchronously for a permit
+    #[cfg(any(feature = "tokio1", feature = "async-std1"))]
+    pub(crate) fn acquire_async(self: &Arc<Self>) -> Acquire {
+        Acquire {
+            semaphore: Arc::clone(self),
+            id: None,
+        }
+    }
+
+    /// Number of permits currently available
+    #[cfg(any(test, feature = "pool"))]
+    pub(crate) fn available(&self) -> usize {
+        self.state.lock().unwrap().permits
+    }
+
+    fn permit(self: &Arc<Self>) -> Permit {
+        Permit {
+            semaphore: Arc::clone(self),
+        }
+    }
+
+    /// Wakes up the first waiter, if it can take a permit
+    fn notify(&self, state: &mut State) {
+        if state.permits == 0 {
+            return;
+        }
+
+        if let Some(waiter) = state.waiters.front_mut() {
+            if let Some(waker) = waiter.waker.take() {
+                waker.wake();
+            }
+            self.condvar.notify_all();
+        }
+    }
+}
+
+impl State {
+    fn enqueue(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.waiters.push_back(Waiter { id, waker: None });
+        id
+    }
+
+    /// Takes a permit if the `id` waiter is the first one
+    fn try_take(&mut self, id: u64) -> bool {
+        let first = self.waiters.front().is_some_and(|waiter| waiter.id == id);
+        if first && self.permits > 0 {
+            self.waiters.pop_front();
+            self.permits -= 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut state = self.semaphore.state.lock().unwrap();
+        state.permits += 1;
+        self.semaphore.notify(&mut state);
+    }
+}
+
+/// Future returned by [`Semaphore::acquire_async`]
+#[cfg(any(feature = "tokio1", feature = "async-std1"))]
+#[derive(Debug)]
+pub(crate) struct Acquire {
+    semaphore: Arc<Semaphore>,
+    id: Option<u64>,
+}
+
+#[cfg(any(feature = "tokio1", feature = "async-std1"))]
+impl Future for Acquire {
+    type Output = Permit;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Permit> {
+        let semaphore = Arc::clone(&self.semaphore);
+        let mut state = semaphore.state.lock().unwrap();
+
+        let id = match self.id {
+            Some(id) => id,
+            None if state.waiters.is_empty() && state.permits > 0 => {
+                state.permits -= 1;
+                return Poll::Ready(semaphore.permit());
+            }
+            None => {
+                let id = state.enqueue();
+                self.id = Some(id);
+                id
+            }
+        };
+
+        if state.try_take(id) {
+            self.id = None;
+            semaphore.notify(&mut state);
+            return Poll::Ready(semaphore.permit());
+        }
+
+        if let Some(waiter) = state.waiters.iter_mut().find(|waiter| waiter.id == id) {
+            waiter.waker = Some(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(any(feature = "tokio1", feature = "async-std1"))]
+impl Drop for Acquire {
+    fn drop(&mut self) {
+        // Cancelled while waiting
+        if let Some(id) = self.id {
+            let mut state = self.semaphore.state.lock().unwrap();
+            state.waiters.retain(|waiter| waiter.id != id);
+            self.semaphore.notify(&mut state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn test_acquire() {
+        let semaphore = Semaphore::new(2);
+        let first = semaphore.acquire(None).unwrap();
+        let _second = semaphore.acquire(None).unwrap();
+        assert_eq!(semaphore.available(), 0);
+        assert!(semaphore.acquire(Some(Duration::from_millis(10))).is_none());
+
+        drop(first);
+        assert_eq!(semaphore.available(), 1);
+        assert!(semaphore.acquire(Some(Duration::from_millis(10))).is_some());
+    }
+
+    #[test]
+    fn test_fairness() {
+        let semaphore = Semaphore::new(1);
+        let permit = semaphore.acquire(None).unwrap();
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        let threads = (0..3)
+            .map(|i| {
+                let semaphore_ = Arc::clone(&semaphore);
+                let order = Arc::clone(&order);
+                let thread = thread::spawn(move || {
+                    let _permit = semaphore_.acquire(None).unwrap();
+                    order.lock().unwrap().push(i);
+                });
+                // Queue the threads one after the other
+                while semaphore.state.lock().unwrap().waiters.len() <= i {
+                    thread::yield_now();
+                }
+                thread
+            })
+            .collect::<Vec<_>>();
+
+        drop(permit);
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(*order.lock().unwrap(), [0, 1, 2]);
+    }
+}
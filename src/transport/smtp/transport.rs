@@ -1,15 +1,16 @@
-#[cfg(feature = "pool")]
-use std::sync::Arc;
-use std::{fmt::Debug, time::Duration};
+use std::{fmt::Debug, net::ToSocketAddrs, sync::Arc, thread, time::Duration};
 
 #[cfg(feature = "pool")]
 use super::pool::sync_impl::Pool;
+use super::{
+    authentication::CredentialsProvider, error, rate_limit::RateLimiter, ClientId, Credentials,
+    Error, Mechanism, RateLimit, Resolver, Response, SmtpConnection, SmtpInfo, SocketOptions,
+};
 #[cfg(feature = "pool")]
-use super::PoolConfig;
-use super::{ClientId, Credentials, Error, Mechanism, Response, SmtpConnection, SmtpInfo};
+use super::{PoolConfig, PoolStats};
 #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
 use super::{Tls, TlsParameters, SUBMISSIONS_PORT, SUBMISSION_PORT};
-use crate::{address::Envelope, Transport};
+use crate::{address::Envelope, transport::failover::HealthCheck, Transport};
 
 /// Synchronously send emails using the SMTP protocol
 ///
@@ -29,7 +30,8 @@ use crate::{address::Envelope, Transport};
 ///
 /// The connection pool can grow to hold multiple SMTP connections if multiple
 /// emails are sent concurrently, as SMTP does not support multiplexing within a
-/// single connection.
+/// single connection. It opens at most [`PoolConfig::max_size`] connections,
+/// additional senders wait for one of them to be released.
 ///
 /// However, **connection reuse is not possible** if the `SmtpTransport` instance
 /// is dropped after every email send operation. You must reuse the instance
@@ -43,6 +45,8 @@ pub struct SmtpTransport {
     inner: Arc<Pool>,
     #[cfg(not(feature = "pool"))]
     inner: SmtpClient,
+    partial_delivery: bool,
+    rate_limiter: Option<Arc<RateLimiter>>,
 }
 
 impl Transport for SmtpTransport {
@@ -51,9 +55,27 @@ impl Transport for SmtpTransport {
 
     /// Sends an email
     fn send_raw(&self, envelope: &Envelope, email: &[u8]) -> Result<Self::Ok, Self::Error> {
+        // Declared first, the permit is released after the connection
+        let _permit = match &self.rate_limiter {
+            Some(rate_limiter) => {
+                thread::sleep(rate_limiter.delay(envelope.to().len()));
+                rate_limiter.connection()
+            }
+            None => None,
+        };
         let mut conn = self.inner.connection()?;
 
-        let result = conn.send(envelope, email)?;
+        let result = match self.send_with(&mut conn, envelope, email) {
+            #[cfg(feature = "pool")]
+            Err(err) if conn.is_stale(&err) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("pooled connection was closed, retrying with a new one");
+
+                conn.reconnect()?;
+                self.send_with(&mut conn, envelope, email)?
+            }
+            result => result?,
+        };
 
         #[cfg(not(feature = "pool"))]
         conn.abort();
@@ -62,10 +84,24 @@ impl Transport for SmtpTransport {
     }
 }
 
+impl HealthCheck for SmtpTransport {
+    /// Connection and network errors make the relay unhealthy, unless the
+    /// message content was sent and the relay may have accepted it
+    fn is_unavailable(error: &Error) -> bool {
+        error.is_network() && !error.has_sent_content()
+    }
+
+    fn test_connection(&self) -> bool {
+        self.test_connection().unwrap_or(false)
+    }
+}
+
 impl Debug for SmtpTransport {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut builder = f.debug_struct("SmtpTransport");
         builder.field("inner", &self.inner);
+        builder.field("partial_delivery", &self.partial_delivery);
+        builder.field("rate_limiter", &self.rate_limiter);
         builder.finish()
     }
 }
@@ -228,6 +264,10 @@ impl SmtpTransport {
     /// `test_connection()` tests the connection by using the SMTP NOOP command.
     /// The connection is closed afterward if a connection pool is not used.
     pub fn test_connection(&self) -> Result<bool, Error> {
+        let _permit = self
+            .rate_limiter
+            .as_ref()
+            .and_then(|rate_limiter| rate_limiter.connection());
         let mut conn = self.inner.connection()?;
 
         let is_connected = conn.test_connected();
@@ -237,6 +277,51 @@ impl SmtpTransport {
 
         Ok(is_connected)
     }
+
+    fn send_with(
+        &self,
+        conn: &mut SmtpConnection,
+        envelope: &Envelope,
+        email: &[u8],
+    ) -> Result<Response, Error> {
+        if self.partial_delivery {
+            conn.send_partial(envelope, email)
+        } else {
+            conn.send(envelope, email)
+        }
+    }
+
+    /// Returns statistics about the connection pool
+    ///
+    /// To receive them periodically, see [`PoolConfig::on_stats`].
+    #[cfg(feature = "pool")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "pool")))]
+    pub fn pool_stats(&self) -> PoolStats {
+        self.inner.stats()
+    }
+
+    /// Opens the [`min_idle`](PoolConfig::min_idle) connections of the
+    /// pool, before sending the first emails
+    ///
+    /// Returns the first error, if a connection can't be opened.
+    #[cfg(feature = "pool")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "pool")))]
+    pub fn warm_up(&self) -> Result<(), Error> {
+        self.inner.warm_up()
+    }
+
+    /// Closes the connections of the pool, sending `QUIT` to the server
+    ///
+    /// Idle connections are closed right away, the ones currently used to
+    /// send an email when they are released. Sending emails with this
+    /// transport, or its clones, fails afterward.
+    ///
+    /// Returns the first error, after trying to close all the idle connections.
+    #[cfg(feature = "pool")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "pool")))]
+    pub fn shutdown(&self) -> Result<(), Error> {
+        self.inner.shutdown()
+    }
 }
 
 /// Contains client configuration.
@@ -246,6 +331,7 @@ pub struct SmtpTransportBuilder {
     info: SmtpInfo,
     #[cfg(feature = "pool")]
     pool_config: PoolConfig,
+    rate_limit: Option<RateLimit>,
 }
 
 /// Builder for the SMTP `SmtpTransport`
@@ -261,6 +347,7 @@ impl SmtpTransportBuilder {
             info: new,
             #[cfg(feature = "pool")]
             pool_config: PoolConfig::default(),
+            rate_limit: None,
         }
     }
 
@@ -276,6 +363,32 @@ impl SmtpTransportBuilder {
         self
     }
 
+    /// Set a provider for the authentication credentials
+    ///
+    /// The provider is consulted each time a new connection is opened,
+    /// instead of the [`credentials`](Self::credentials). If the server
+    /// rejects them with a 535 reply, [`CredentialsProvider::refresh`] is
+    /// called and the connection is retried once.
+    pub fn credentials_provider<P>(mut self, provider: P) -> Self
+    where
+        P: CredentialsProvider + 'static,
+    {
+        self.info.credentials_provider = Some(Arc::new(provider));
+        self
+    }
+
+    /// Set the resolver of the server host name
+    ///
+    /// The resolver is consulted each time a new connection is opened.
+    /// By default, the host name is resolved by the system.
+    pub fn resolver<R>(mut self, resolver: R) -> Self
+    where
+        R: Resolver + 'static,
+    {
+        self.info.resolver = Some(Arc::new(resolver));
+        self
+    }
+
     /// Set the authentication mechanism to use
     pub fn authentication(mut self, mechanisms: Vec<Mechanism>) -> Self {
         self.info.authentication = mechanisms;
@@ -288,6 +401,14 @@ impl SmtpTransportBuilder {
         self
     }
 
+    /// Set the options of the TCP sockets, like the source address
+    ///
+    /// See [`SocketOptions`] for the defaults.
+    pub fn socket_options(mut self, socket_options: SocketOptions) -> Self {
+        self.info.socket_options = socket_options;
+        self
+    }
+
     /// Set the port to use
     ///
     /// # ⚠️⚠️⚠️ You probably don't need to call this method ⚠️⚠️⚠️
@@ -327,6 +448,19 @@ impl SmtpTransportBuilder {
         self
     }
 
+    /// Deliver the message to the accepted recipients when some are rejected
+    ///
+    /// By default the whole transaction is aborted as soon as the server
+    /// rejects one `RCPT TO`. When enabled, the rejected recipients are skipped
+    /// and the returned [`Response`] lists the accepted and rejected recipients
+    /// with their responses, see [`Response::accepted_recipients`] and
+    /// [`Response::rejected_recipients`]. Sending still fails if every
+    /// recipient is rejected.
+    pub fn partial_delivery(mut self, enabled: bool) -> Self {
+        self.info.partial_delivery = enabled;
+        self
+    }
+
     /// Use a custom configuration for the connection pool
     ///
     /// Defaults can be found at [`PoolConfig`]
@@ -337,17 +471,36 @@ impl SmtpTransportBuilder {
         self
     }
 
+    /// Limit how fast emails are sent
+    ///
+    /// By default, there is no limit. Senders exceeding the limit block
+    /// until they can proceed.
+    pub fn rate_limit(mut self, rate_limit: RateLimit) -> Self {
+        self.rate_limit = Some(rate_limit);
+        self
+    }
+
     /// Build the transport
     ///
     /// If the `pool` feature is enabled, an `Arc` wrapped pool is created.
     /// Defaults can be found at [`PoolConfig`]
     pub fn build(self) -> SmtpTransport {
+        let partial_delivery = self.info.partial_delivery;
         let client = SmtpClient { info: self.info };
 
         #[cfg(feature = "pool")]
-        let client = Pool::new(self.pool_config, client);
+        let pool_config = match &self.rate_limit {
+            Some(rate_limit) => rate_limit.limit_pool(self.pool_config),
+            None => self.pool_config,
+        };
+        #[cfg(feature = "pool")]
+        let client = Pool::new(pool_config, client);
 
-        SmtpTransport { inner: client }
+        SmtpTransport {
+            inner: client,
+            partial_delivery,
+            rate_limiter: self.rate_limit.map(RateLimiter::new),
+        }
     }
 }
 
@@ -362,6 +515,21 @@ impl SmtpClient {
     ///
     /// Handles encryption and authentication
     pub fn connection(&self) -> Result<SmtpConnection, Error> {
+        let Some(provider) = &self.info.credentials_provider else {
+            return self.connect(self.info.credentials.as_ref());
+        };
+
+        let credentials = provider.credentials().map_err(error::client)?;
+        match self.connect(Some(&credentials)) {
+            Err(err) if err.status().map(u16::from) == Some(535) => {
+                let credentials = provider.refresh(&credentials).map_err(error::client)?;
+                self.connect(Some(&credentials))
+            }
+            result => result,
+        }
+    }
+
+    fn connect(&self, credentials: Option<&Credentials>) -> Result<SmtpConnection, Error> {
         #[allow(clippy::match_single_binding)]
         let tls_parameters = match &self.info.tls {
             #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
@@ -369,14 +537,25 @@ impl SmtpClient {
             _ => None,
         };
 
+        let addrs = match &self.info.resolver {
+            Some(resolver) => resolver
+                .resolve(&self.info.server, self.info.port)
+                .map_err(error::connection)?,
+            None => (self.info.server.as_ref(), self.info.port)
+                .to_socket_addrs()
+                .map_err(error::connection)?
+                .collect(),
+        };
+
         #[allow(unused_mut)]
-        let mut conn = SmtpConnection::connect::<(&str, u16)>(
-            (self.info.server.as_ref(), self.info.port),
+        let mut conn = SmtpConnection::connect_with_options(
+            addrs.as_slice(),
             self.info.timeout,
             &self.info.hello_name,
             tls_parameters,
-            None,
+            &self.info.socket_options,
         )?;
+        conn.set_server_address(&self.info.server, self.info.port);
 
         #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
         match &self.info.tls {
@@ -391,7 +570,7 @@ impl SmtpClient {
             _ => (),
         }
 
-        if let Some(credentials) = &self.info.credentials {
+        if let Some(credentials) = credentials {
             conn.auth(&self.info.authentication, credentials)?;
         }
         Ok(conn)
@@ -52,9 +52,9 @@ use async_trait::async_trait;
 #[cfg(any(feature = "tokio1", feature = "async-std1"))]
 use futures_util::lock::Mutex as FuturesMutex;
 
+use crate::{address::Envelope, transport::failover::HealthCheck, Transport};
 #[cfg(any(feature = "tokio1", feature = "async-std1"))]
-use crate::AsyncTransport;
-use crate::{address::Envelope, Transport};
+use crate::{transport::failover::AsyncHealthCheck, AsyncTransport};
 
 /// An error returned by the stub transport
 #[non_exhaustive]
@@ -179,3 +179,27 @@ impl AsyncTransport for AsyncStubTransport {
         self.response
     }
 }
+
+impl HealthCheck for StubTransport {
+    /// Stub errors make the transport unhealthy
+    fn is_unavailable(_error: &Error) -> bool {
+        true
+    }
+
+    fn test_connection(&self) -> bool {
+        self.response.is_ok()
+    }
+}
+
+#[cfg(any(feature = "tokio1", feature = "async-std1"))]
+#[async_trait]
+impl AsyncHealthCheck for AsyncStubTransport {
+    /// Stub errors make the transport unhealthy
+    fn is_unavailable(_error: &Error) -> bool {
+        true
+    }
+
+    async fn test_connection(&self) -> bool {
+        self.response.is_ok()
+    }
+}
@@ -0,0 +1,163 @@
+#[cfg(test)]
+#[cfg(feature = "builder")]
+mod sync {
+    use lettre::{
+        transport::{
+            failover::{FailoverTransport, Strategy},
+            stub::StubTransport,
+        },
+        Message, Transport,
+    };
+
+    fn email() -> Message {
+        Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .subject("Happy new year")
+            .body(String::from("Be happy!"))
+            .unwrap()
+    }
+
+    #[test]
+    fn failover_transport() {
+        let sender = FailoverTransport::builder()
+            .strategy(Strategy::Priority)
+            .backend(StubTransport::new_error())
+            .backend(StubTransport::new_ok())
+            .build();
+
+        sender.send(&email()).unwrap();
+        assert_eq!(sender.is_healthy(0), Some(false));
+        assert_eq!(sender.is_healthy(1), Some(true));
+        assert_eq!(sender.is_healthy(2), None);
+
+        // The unhealthy backend is skipped
+        sender.send(&email()).unwrap();
+        assert_eq!(sender.backends()[0].messages().len(), 1);
+        assert_eq!(sender.backends()[1].messages().len(), 2);
+    }
+
+    #[test]
+    fn failover_transport_all_unhealthy() {
+        let sender = FailoverTransport::builder()
+            .backend(StubTransport::new_error())
+            .backend(StubTransport::new_error())
+            .build();
+
+        sender.send(&email()).unwrap_err();
+        assert_eq!(sender.is_healthy(0), Some(false));
+        assert_eq!(sender.is_healthy(1), Some(false));
+
+        // Unhealthy backends are tried anyway
+        sender.send(&email()).unwrap_err();
+        assert_eq!(sender.backends()[0].messages().len(), 2);
+        assert_eq!(sender.backends()[1].messages().len(), 2);
+    }
+
+    #[test]
+    fn failover_transport_round_robin() {
+        let sender = FailoverTransport::builder()
+            .strategy(Strategy::RoundRobin)
+            .weighted_backend(StubTransport::new_ok(), 2)
+            .backend(StubTransport::new_ok())
+            .build();
+
+        for _ in 0..6 {
+            sender.send(&email()).unwrap();
+        }
+        assert_eq!(sender.backends()[0].messages().len(), 4);
+        assert_eq!(sender.backends()[1].messages().len(), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "smtp-transport")]
+    fn failover_transport_smtp() {
+        use lettre::SmtpTransport;
+
+        // Nothing listens on the first port
+        let sender = FailoverTransport::builder()
+            .strategy(Strategy::Priority)
+            .backend(
+                SmtpTransport::builder_dangerous("127.0.0.1")
+                    .port(9)
+                    .build(),
+            )
+            .backend(
+                SmtpTransport::builder_dangerous("127.0.0.1")
+                    .port(2525)
+                    .build(),
+            )
+            .build();
+
+        sender.send(&email()).unwrap();
+        assert_eq!(sender.is_healthy(0), Some(false));
+        assert_eq!(sender.is_healthy(1), Some(true));
+    }
+}
+
+#[cfg(test)]
+#[cfg(all(feature = "builder", feature = "tokio1"))]
+mod tokio_1 {
+    use lettre::{
+        transport::{
+            failover::{FailoverTransport, Strategy},
+            stub::AsyncStubTransport,
+        },
+        AsyncTransport, Message,
+    };
+    use tokio1_crate as tokio;
+
+    #[tokio::test]
+    async fn failover_transport_tokio1() {
+        let sender = FailoverTransport::builder()
+            .strategy(Strategy::Priority)
+            .backend(AsyncStubTransport::new_error())
+            .backend(AsyncStubTransport::new_ok())
+            .build();
+        let email = Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .subject("Happy new year")
+            .body(String::from("Be happy!"))
+            .unwrap();
+
+        sender.send(email.clone()).await.unwrap();
+        sender.send(email).await.unwrap();
+        assert_eq!(sender.is_healthy(0), Some(false));
+        assert_eq!(sender.backends()[0].messages().await.len(), 1);
+        assert_eq!(sender.backends()[1].messages().await.len(), 2);
+    }
+}
+
+#[cfg(test)]
+#[cfg(all(feature = "builder", feature = "async-std1"))]
+mod asyncstd_1 {
+    use lettre::{
+        transport::{
+            failover::{FailoverTransport, Strategy},
+            stub::AsyncStubTransport,
+        },
+        AsyncTransport, Message,
+    };
+
+    #[async_std::test]
+    async fn failover_transport_asyncstd1() {
+        let sender = FailoverTransport::builder()
+            .strategy(Strategy::Priority)
+            .backend(AsyncStubTransport::new_error())
+            .backend(AsyncStubTransport::new_ok())
+            .build();
+        let email = Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .subject("Happy new year")
+            .body(String::from("Be happy!"))
+            .unwrap();
+
+        sender.send(email.clone()).await.unwrap();
+        sender.send(email).await.unwrap();
+        assert_eq!(sender.is_healthy(0), Some(false));
+        assert_eq!(sender.backends()[0].messages().await.len(), 1);
+        assert_eq!(sender.backends()[1].messages().await.len(), 2);
+    }
+}
@@ -0,0 +1,115 @@
+#[cfg(test)]
+#[cfg(all(feature = "mx-transport", feature = "builder"))]
+mod sync {
+    use std::{error::Error, net::IpAddr};
+
+    use lettre::{
+        transport::smtp::mx::{MxRecord, MxResolver, MxTransport},
+        Message, Transport,
+    };
+
+    #[derive(Debug)]
+    struct LocalResolver;
+
+    impl MxResolver for LocalResolver {
+        fn mx(&self, domain: &str) -> Result<Vec<MxRecord>, Box<dyn Error + Send + Sync>> {
+            match domain {
+                "domain.tld" => Ok(vec![
+                    MxRecord::new(20, "mx2.domain.tld".to_owned()),
+                    MxRecord::new(10, "mx1.domain.tld".to_owned()),
+                ]),
+                "other.tld" => Ok(vec![]),
+                "null.tld" => Ok(vec![MxRecord::new(0, ".".to_owned())]),
+                _ => Err("no such domain".into()),
+            }
+        }
+
+        fn ip(&self, host: &str) -> Result<Vec<IpAddr>, Box<dyn Error + Send + Sync>> {
+            match host {
+                // Nothing listens there, the next MX is used
+                "mx1.domain.tld" => Ok(vec![[127, 0, 0, 2].into()]),
+                "mx2.domain.tld" | "other.tld" => Ok(vec![[127, 0, 0, 1].into()]),
+                _ => Ok(vec![]),
+            }
+        }
+
+        fn txt(&self, name: &str) -> Result<Vec<String>, Box<dyn Error + Send + Sync>> {
+            match name {
+                "_mta-sts.domain.tld" => Ok(vec!["v=STSv1; id=20260101".to_owned()]),
+                _ => Ok(vec![]),
+            }
+        }
+    }
+
+    #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+    #[derive(Debug)]
+    struct LocalFetcher;
+
+    #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+    impl lettre::transport::smtp::mx::MtaStsFetcher for LocalFetcher {
+        fn fetch(&self, domain: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
+            match domain {
+                "domain.tld" => Ok(
+                    "version: STSv1\nmode: enforce\nmx: *.domain.tld\nmax_age: 86400\n".to_owned(),
+                ),
+                _ => Err("no policy".into()),
+            }
+        }
+    }
+
+    #[test]
+    fn mx_transport() {
+        let email = Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .to("Yuin <yuin@other.tld>".parse().unwrap())
+            .to("Nobody <nobody@null.tld>".parse().unwrap())
+            .subject("Happy new year")
+            .body(String::from("Be happy!"))
+            .unwrap();
+
+        let sender = MxTransport::builder(LocalResolver).port(2525).build();
+        let response = sender.send(&email).unwrap();
+
+        let delivered = response
+            .delivered()
+            .map(|(domain, _)| domain)
+            .collect::<Vec<_>>();
+        assert_eq!(delivered, ["domain.tld", "other.tld"]);
+        let failed = response
+            .failed()
+            .map(|(domain, _)| domain)
+            .collect::<Vec<_>>();
+        assert_eq!(failed, ["null.tld"]);
+    }
+
+    #[test]
+    #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+    fn mx_transport_mta_sts() {
+        let email = Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .to("Yuin <yuin@other.tld>".parse().unwrap())
+            .subject("Happy new year")
+            .body(String::from("Be happy!"))
+            .unwrap();
+
+        let sender = MxTransport::builder(LocalResolver)
+            .port(2525)
+            .mta_sts(LocalFetcher)
+            .build();
+        let response = sender.send(&email).unwrap();
+
+        // The policy of domain.tld requires STARTTLS, the test server doesn't support it
+        let delivered = response
+            .delivered()
+            .map(|(domain, _)| domain)
+            .collect::<Vec<_>>();
+        assert_eq!(delivered, ["other.tld"]);
+        let failed = response
+            .failed()
+            .map(|(domain, error)| (domain, error.is_tls()))
+            .collect::<Vec<_>>();
+        assert_eq!(failed, [("domain.tld", true)]);
+    }
+}
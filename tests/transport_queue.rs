@@ -0,0 +1,280 @@
+#[cfg(test)]
+#[cfg(all(feature = "queue-transport", feature = "builder"))]
+mod sync {
+    use std::{
+        env::temp_dir,
+        fmt,
+        fs::{create_dir, create_dir_all, remove_dir_all, write},
+        panic::{catch_unwind, AssertUnwindSafe},
+        path::PathBuf,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex, OnceLock,
+        },
+        time::Duration,
+    };
+
+    use lettre::{
+        address::Envelope,
+        transport::{
+            queue::{Delivery, QueuedTransport},
+            RetryableError,
+        },
+        Message, Transport,
+    };
+
+    #[derive(Debug)]
+    struct FlakyError {
+        retryable: bool,
+    }
+
+    impl fmt::Display for FlakyError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("flaky error")
+        }
+    }
+
+    impl std::error::Error for FlakyError {}
+
+    impl RetryableError for FlakyError {
+        fn is_retryable(&self) -> bool {
+            self.retryable
+        }
+    }
+
+    /// Fails with the given errors, then succeeds
+    #[derive(Debug)]
+    struct FlakyTransport {
+        errors: Mutex<Vec<bool>>,
+    }
+
+    impl FlakyTransport {
+        fn new(errors: &[bool]) -> Self {
+            Self {
+                errors: Mutex::new(errors.iter().rev().copied().collect()),
+            }
+        }
+    }
+
+    impl Transport for FlakyTransport {
+        type Ok = ();
+        type Error = FlakyError;
+
+        fn send_raw(&self, _envelope: &Envelope, _email: &[u8]) -> Result<(), FlakyError> {
+            match self.errors.lock().unwrap().pop() {
+                Some(retryable) => Err(FlakyError { retryable }),
+                None => Ok(()),
+            }
+        }
+    }
+
+    /// Processes the queue while sending, failing the first attempt
+    #[derive(Debug, Clone, Default)]
+    struct ProcessingTransport(Arc<Processing>);
+
+    #[derive(Debug, Default)]
+    struct Processing {
+        queue: OnceLock<QueuedTransport<ProcessingTransport>>,
+        sent: AtomicUsize,
+        remaining: Mutex<Option<usize>>,
+    }
+
+    impl Transport for ProcessingTransport {
+        type Ok = ();
+        type Error = FlakyError;
+
+        fn send_raw(&self, _envelope: &Envelope, _email: &[u8]) -> Result<(), FlakyError> {
+            if self.0.sent.fetch_add(1, Ordering::SeqCst) > 0 {
+                return Ok(());
+            }
+            let remaining = self.0.queue.get().unwrap().process_queue().unwrap();
+            *self.0.remaining.lock().unwrap() = Some(remaining);
+            Err(FlakyError { retryable: true })
+        }
+    }
+
+    fn queue_dir(name: &str) -> PathBuf {
+        let path = temp_dir().join(format!("lettre-queue-{name}"));
+        let _ = remove_dir_all(&path);
+        create_dir_all(&path).unwrap();
+        path
+    }
+
+    fn email() -> Message {
+        Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .subject("Happy new year")
+            .body(String::from("Be happy!"))
+            .unwrap()
+    }
+
+    #[test]
+    fn queued_transport() {
+        let path = queue_dir("retry");
+        let sender = QueuedTransport::builder(FlakyTransport::new(&[]), &path).build();
+        assert_eq!(sender.send(&email()).unwrap(), Delivery::Sent(()));
+        assert!(sender.queued().unwrap().is_empty());
+
+        let sender = QueuedTransport::builder(FlakyTransport::new(&[true, true]), &path)
+            .initial_backoff(Duration::ZERO)
+            .build();
+        let Delivery::Queued(id) = sender.send(&email()).unwrap() else {
+            panic!("email not queued");
+        };
+        assert!(path.join(format!("{id}.eml")).exists());
+        assert!(path.join(format!("{id}.json")).exists());
+        assert_eq!(sender.queued().unwrap(), [id]);
+
+        assert_eq!(sender.process_queue().unwrap(), 1);
+        assert_eq!(sender.process_queue().unwrap(), 0);
+        assert!(sender.queued().unwrap().is_empty());
+        assert_eq!(path.read_dir().unwrap().count(), 0);
+
+        remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn queued_transport_permanent_error() {
+        let path = queue_dir("permanent");
+        let bounces = Arc::new(AtomicUsize::new(0));
+        let sender = QueuedTransport::builder(FlakyTransport::new(&[false]), &path)
+            .on_bounce({
+                let bounces = Arc::clone(&bounces);
+                move |bounce| {
+                    assert!(!bounce.is_expired());
+                    assert!(!bounce.error().is_retryable());
+                    bounces.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+            .build();
+        let error = sender.send(&email()).unwrap_err();
+        assert!(error.is_transport());
+        assert_eq!(bounces.load(Ordering::SeqCst), 1);
+        assert_eq!(path.read_dir().unwrap().count(), 0);
+
+        remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn queued_transport_bounce() {
+        let path = queue_dir("bounce");
+        let bounces = Arc::new(AtomicUsize::new(0));
+        let expired = Arc::new(AtomicUsize::new(0));
+
+        let sender = QueuedTransport::builder(FlakyTransport::new(&[true, false]), &path)
+            .initial_backoff(Duration::ZERO)
+            .on_bounce({
+                let bounces = Arc::clone(&bounces);
+                move |bounce| {
+                    assert!(!bounce.is_expired());
+                    assert!(!bounce.error().is_retryable());
+                    bounces.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+            .build();
+        assert!(matches!(sender.send(&email()), Ok(Delivery::Queued(_))));
+        assert_eq!(sender.process_queue().unwrap(), 0);
+        assert_eq!(bounces.load(Ordering::SeqCst), 1);
+
+        let sender = QueuedTransport::builder(FlakyTransport::new(&[true, true]), &path)
+            .initial_backoff(Duration::ZERO)
+            .lifetime(Duration::ZERO)
+            .on_bounce({
+                let expired = Arc::clone(&expired);
+                move |bounce| {
+                    assert!(bounce.is_expired());
+                    assert_eq!(bounce.envelope().to().len(), 1);
+                    expired.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+            .build();
+        assert!(matches!(sender.send(&email()), Ok(Delivery::Queued(_))));
+        assert_eq!(sender.process_queue().unwrap(), 0);
+        assert_eq!(expired.load(Ordering::SeqCst), 1);
+        assert_eq!(path.read_dir().unwrap().count(), 0);
+
+        remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn queued_transport_panicking_bounce() {
+        let path = queue_dir("panic");
+        let sender = QueuedTransport::builder(FlakyTransport::new(&[true, false, true]), &path)
+            .initial_backoff(Duration::ZERO)
+            .on_bounce(|_| panic!("bounce"))
+            .build();
+        assert!(matches!(sender.send(&email()), Ok(Delivery::Queued(_))));
+        assert!(catch_unwind(AssertUnwindSafe(|| sender.process_queue())).is_err());
+
+        // The queue is still usable
+        assert!(matches!(sender.send(&email()), Ok(Delivery::Queued(_))));
+        assert_eq!(sender.process_queue().unwrap(), 0);
+
+        remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn queued_transport_reschedule_error() {
+        let path = queue_dir("reschedule");
+        let sender = QueuedTransport::builder(FlakyTransport::new(&[true; 4]), &path)
+            .initial_backoff(Duration::ZERO)
+            .build();
+        let Delivery::Queued(unwritable) = sender.send(&email()).unwrap() else {
+            panic!("email not queued");
+        };
+        assert!(matches!(sender.send(&email()), Ok(Delivery::Queued(_))));
+        // The schedule can't be replaced through a directory
+        create_dir(path.join(format!("{unwritable}.queue.tmp"))).unwrap();
+
+        // Both emails are still attempted and counted
+        assert_eq!(sender.process_queue().unwrap(), 2);
+        assert_eq!(sender.process_queue().unwrap(), 0);
+
+        remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn queued_transport_concurrent_processing() {
+        let path = queue_dir("concurrent");
+        let inner = ProcessingTransport::default();
+        let sender = QueuedTransport::builder(inner.clone(), &path)
+            .initial_backoff(Duration::ZERO)
+            .build();
+        // A clone sharing the queue, processing it during the first attempt
+        let processing = Arc::clone(&inner.0);
+        processing.queue.set(sender.clone()).unwrap();
+
+        // The email being sent was skipped by the queue processing
+        assert!(matches!(sender.send(&email()), Ok(Delivery::Queued(_))));
+        assert_eq!(*processing.remaining.lock().unwrap(), Some(1));
+        assert_eq!(processing.sent.load(Ordering::SeqCst), 1);
+
+        assert_eq!(sender.process_queue().unwrap(), 0);
+        assert_eq!(processing.sent.load(Ordering::SeqCst), 2);
+
+        remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn queued_transport_invalid_schedule() {
+        let path = queue_dir("invalid");
+        let sender = QueuedTransport::builder(FlakyTransport::new(&[true, true]), &path)
+            .initial_backoff(Duration::ZERO)
+            .build();
+        let Delivery::Queued(invalid) = sender.send(&email()).unwrap() else {
+            panic!("email not queued");
+        };
+        let Delivery::Queued(_) = sender.send(&email()).unwrap() else {
+            panic!("email not queued");
+        };
+        write(path.join(format!("{invalid}.queue")), "{\"queued_at\":").unwrap();
+
+        assert_eq!(sender.process_queue().unwrap(), 0);
+        assert!(sender.queued().unwrap().is_empty());
+        assert!(path.join(format!("{invalid}.invalid")).exists());
+        assert!(path.join(format!("{invalid}.eml")).exists());
+
+        remove_dir_all(path).unwrap();
+    }
+}
@@ -0,0 +1,225 @@
+#[cfg(test)]
+#[cfg(feature = "builder")]
+mod sync {
+    use std::time::Duration;
+
+    use lettre::{
+        transport::{
+            retry::Retry,
+            stub::{self, StubTransport},
+        },
+        Message, Transport,
+    };
+
+    #[test]
+    fn retry_transport() {
+        let email = Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .subject("Happy new year")
+            .body(String::from("Be happy!"))
+            .unwrap();
+
+        let sender_ok = Retry::with_policy(StubTransport::new_ok(), |_, _: &stub::Error| {
+            Some(Duration::ZERO)
+        });
+        sender_ok.send(&email).unwrap();
+        assert_eq!(sender_ok.inner().messages().len(), 1);
+
+        let sender_ko =
+            Retry::with_policy(StubTransport::new_error(), |attempt, _: &stub::Error| {
+                (attempt < 3).then_some(Duration::ZERO)
+            });
+        sender_ko.send(&email).unwrap_err();
+        assert_eq!(sender_ko.inner().messages().len(), 3);
+    }
+}
+
+#[cfg(test)]
+#[cfg(all(feature = "builder", feature = "tokio1"))]
+mod tokio_1 {
+    use std::time::Duration;
+
+    use lettre::{
+        transport::{
+            retry::AsyncRetry,
+            stub::{self, AsyncStubTransport},
+        },
+        AsyncTransport, Message, Tokio1Executor,
+    };
+    use tokio1_crate as tokio;
+
+    #[tokio::test]
+    async fn retry_transport_tokio1() {
+        let email = Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .subject("Happy new year")
+            .body(String::from("Be happy!"))
+            .unwrap();
+
+        let sender_ko = AsyncRetry::<_, Tokio1Executor, _>::with_policy(
+            AsyncStubTransport::new_error(),
+            |attempt, _: &stub::Error| (attempt < 3).then_some(Duration::from_millis(10)),
+        );
+        sender_ko.send(email).await.unwrap_err();
+        assert_eq!(sender_ko.inner().messages().await.len(), 3);
+    }
+}
+
+#[cfg(test)]
+#[cfg(all(feature = "builder", feature = "async-std1"))]
+mod asyncstd_1 {
+    use std::time::Duration;
+
+    use lettre::{
+        transport::{
+            retry::AsyncRetry,
+            stub::{self, AsyncStubTransport},
+        },
+        AsyncStd1Executor, AsyncTransport, Message,
+    };
+
+    #[async_std::test]
+    async fn retry_transport_asyncstd1() {
+        let email = Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .subject("Happy new year")
+            .body(String::from("Be happy!"))
+            .unwrap();
+
+        let sender_ko = AsyncRetry::<_, AsyncStd1Executor, _>::with_policy(
+            AsyncStubTransport::new_error(),
+            |attempt, _: &stub::Error| (attempt < 3).then_some(Duration::from_millis(10)),
+        );
+        sender_ko.send(email).await.unwrap_err();
+        assert_eq!(sender_ko.inner().messages().await.len(), 3);
+    }
+}
+
+#[cfg(test)]
+#[cfg(all(feature = "builder", feature = "smtp-transport"))]
+mod smtp {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+        time::Duration,
+    };
+
+    use lettre::{
+        transport::{retry::ExponentialBackoff, retry::Retry, RetryableError},
+        Message, SmtpTransport, Transport,
+    };
+
+    #[test]
+    fn retry_smtp_transport_after_content() {
+        let email = Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .subject("Happy new year")
+            .body(String::from("Be happy!"))
+            .unwrap();
+
+        // A server never replying once it received the message
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let messages = Arc::new(AtomicUsize::new(0));
+        let messages_ = Arc::clone(&messages);
+        thread::spawn(move || {
+            let mut held = Vec::new();
+            for stream in listener.incoming() {
+                let mut writer = stream.unwrap();
+                let mut reader = BufReader::new(writer.try_clone().unwrap());
+                writer.write_all(b"220 localhost ESMTP\r\n").unwrap();
+
+                let mut data = false;
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap_or(0) > 0 {
+                    if data {
+                        if line == ".\r\n" {
+                            messages_.fetch_add(1, Ordering::SeqCst);
+                            held.push(writer);
+                            break;
+                        }
+                    } else if line.starts_with("DATA") {
+                        data = true;
+                        writer.write_all(b"354 Go ahead\r\n").unwrap();
+                    } else {
+                        writer.write_all(b"250 Ok\r\n").unwrap();
+                    }
+                    line.clear();
+                }
+            }
+        });
+
+        let sender = Retry::with_policy(
+            SmtpTransport::builder_dangerous("127.0.0.1")
+                .port(port)
+                .timeout(Some(Duration::from_millis(200)))
+                .build(),
+            ExponentialBackoff::new(2, Duration::ZERO),
+        );
+        let error = sender.send(&email).unwrap_err();
+        // The server may have accepted the message, so it isn't sent again
+        assert!(error.has_sent_content());
+        assert!(!error.is_retryable());
+        assert_eq!(messages.load(Ordering::SeqCst), 1);
+    }
+}
+
+#[cfg(test)]
+#[cfg(all(feature = "builder", feature = "sendmail-transport", unix))]
+mod sendmail {
+    use std::{fs, os::unix::fs::PermissionsExt, time::Duration};
+
+    use lettre::{
+        transport::{retry::ExponentialBackoff, retry::Retry, RetryableError},
+        Message, SendmailTransport, Transport,
+    };
+
+    #[test]
+    fn retry_sendmail_transport() {
+        let email = Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .subject("Happy new year")
+            .body(String::from("Be happy!"))
+            .unwrap();
+
+        // A sendmail command always failing temporarily, counting its runs
+        let dir = std::env::temp_dir().join(format!("lettre-retry-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let attempts = dir.join("attempts");
+        let command = dir.join("sendmail");
+        fs::write(
+            &command,
+            format!(
+                "#!/bin/sh\ncat > /dev/null\necho >> '{}'\nexit 75\n",
+                attempts.display()
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&command, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let sender = Retry::with_policy(
+            SendmailTransport::new_with_command(&command),
+            ExponentialBackoff::new(2, Duration::ZERO),
+        );
+        let error = sender.send(&email).unwrap_err();
+        assert!(error.is_temporary());
+        assert!(error.is_retryable());
+        assert_eq!(fs::read_to_string(&attempts).unwrap().lines().count(), 3);
+
+        // Other failures aren't retried
+        let sender = Retry::new(SendmailTransport::new_with_command("false"));
+        assert!(!sender.send(&email).unwrap_err().is_retryable());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
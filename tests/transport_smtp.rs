@@ -1,7 +1,26 @@
 #[cfg(test)]
 #[cfg(all(feature = "smtp-transport", feature = "builder"))]
 mod sync {
-    use lettre::{Message, SmtpTransport, Transport};
+    use std::{
+        error::Error,
+        net::{Ipv4Addr, Ipv6Addr},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::{Duration, Instant},
+    };
+
+    use lettre::{
+        address::Envelope,
+        transport::smtp::{
+            authentication::{Credentials, CredentialsProvider},
+            client::SocketOptions,
+            extension::{MailParameter, MailRetParameter, RcptNotifyParameter, RcptParameter},
+            RateLimit, StaticResolver,
+        },
+        Message, SmtpTransport, Transport,
+    };
 
     #[test]
     fn smtp_transport_simple() {
@@ -18,12 +37,225 @@ mod sync {
             .build();
         sender.send(&email).unwrap();
     }
+
+    #[test]
+    fn smtp_transport_credentials_provider() {
+        #[derive(Debug, Default)]
+        struct Provider {
+            calls: AtomicUsize,
+        }
+
+        impl CredentialsProvider for Provider {
+            fn credentials(&self) -> Result<Credentials, Box<dyn Error + Send + Sync>> {
+                self.calls.fetch_add(1, Ordering::SeqCst);
+                Ok(Credentials::new("user".to_owned(), "password".to_owned()))
+            }
+        }
+
+        let email = Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .subject("Happy new year")
+            .body(String::from("Be happy!"))
+            .unwrap();
+
+        let provider = Arc::new(Provider::default());
+        let sender = SmtpTransport::builder_dangerous("127.0.0.1")
+            .port(2525)
+            .credentials_provider(Arc::clone(&provider))
+            .build();
+        sender.send(&email).unwrap();
+
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn smtp_transport_partial_delivery() {
+        let email = Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .to("Yuin <yuin@domain.tld>".parse().unwrap())
+            .subject("Happy new year")
+            .body(String::from("Be happy!"))
+            .unwrap();
+
+        let sender = SmtpTransport::builder_dangerous("127.0.0.1")
+            .port(2525)
+            .partial_delivery(true)
+            .build();
+        let response = sender.send(&email).unwrap();
+
+        let accepted = response
+            .accepted_recipients()
+            .iter()
+            .map(|recipient| recipient.address().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(accepted, ["hei@domain.tld", "yuin@domain.tld"]);
+        assert!(response.rejected_recipients().is_empty());
+    }
+
+    #[test]
+    fn smtp_transport_dsn() {
+        let to = "hei@domain.tld".parse().unwrap();
+        let mut envelope =
+            Envelope::new(Some("nobody@domain.tld".parse().unwrap()), vec![to]).unwrap();
+        envelope.add_mail_parameter(MailParameter::Ret(MailRetParameter::Headers));
+        envelope.add_mail_parameter(MailParameter::EnvId("QQ314159".to_owned()));
+        let to = envelope.to()[0].clone();
+        envelope.add_rcpt_parameter(
+            &to,
+            RcptParameter::Notify(vec![
+                RcptNotifyParameter::Success,
+                RcptNotifyParameter::Failure,
+            ]),
+        );
+        envelope.add_rcpt_parameter(&to, RcptParameter::Orcpt(to.clone()));
+
+        let sender = SmtpTransport::builder_dangerous("127.0.0.1")
+            .port(2525)
+            .build();
+        sender
+            .send_raw(&envelope, b"Subject: Happy new year\r\n\r\nBe happy!\r\n")
+            .unwrap();
+    }
+
+    #[test]
+    fn smtp_transport_require_tls_cleartext() {
+        let mut envelope = Envelope::new(
+            Some("nobody@domain.tld".parse().unwrap()),
+            vec!["hei@domain.tld".parse().unwrap()],
+        )
+        .unwrap();
+        envelope.add_mail_parameter(MailParameter::RequireTls);
+
+        let sender = SmtpTransport::builder_dangerous("127.0.0.1")
+            .port(2525)
+            .build();
+        let err = sender
+            .send_raw(&envelope, b"Subject: Happy new year\r\n\r\nBe happy!\r\n")
+            .unwrap_err();
+        assert!(err.is_client());
+    }
+
+    #[test]
+    fn smtp_transport_binary() {
+        let envelope = Envelope::new(
+            Some("nobody@domain.tld".parse().unwrap()),
+            vec!["hei@domain.tld".parse().unwrap()],
+        )
+        .unwrap();
+
+        let sender = SmtpTransport::builder_dangerous("127.0.0.1")
+            .port(2525)
+            .build();
+        sender
+            .send_raw(
+                &envelope,
+                b"Content-Transfer-Encoding: binary\r\n\r\n\0\xff\n.\r\n.\n",
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn smtp_transport_rate_limit() {
+        let email = Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .subject("Happy new year")
+            .body(String::from("Be happy!"))
+            .unwrap();
+
+        let sender = SmtpTransport::builder_dangerous("127.0.0.1")
+            .port(2525)
+            .rate_limit(RateLimit::new().messages_per_second(2).max_connections(1))
+            .build();
+
+        let start = Instant::now();
+        for _ in 0..3 {
+            sender.send(&email).unwrap();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(450));
+    }
+
+    #[test]
+    fn smtp_transport_socket_options() {
+        let email = Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .subject("Happy new year")
+            .body(String::from("Be happy!"))
+            .unwrap();
+
+        let configured = Arc::new(AtomicUsize::new(0));
+        let options = SocketOptions::new()
+            .source_address(Ipv4Addr::LOCALHOST.into())
+            .tcp_keepalive(Duration::from_secs(60))
+            .tcp_nodelay(true)
+            .configure({
+                let configured = Arc::clone(&configured);
+                move |socket| {
+                    assert!(socket.nodelay()?);
+                    assert!(socket.keepalive()?);
+                    configured.fetch_add(1, Ordering::SeqCst);
+                    Ok(())
+                }
+            });
+        let sender = SmtpTransport::builder_dangerous("127.0.0.1")
+            .port(2525)
+            .socket_options(options)
+            .build();
+        sender.send(&email).unwrap();
+        assert_eq!(configured.load(Ordering::SeqCst), 1);
+
+        // No server address of the same IP version
+        let sender = SmtpTransport::builder_dangerous("127.0.0.1")
+            .port(2525)
+            .socket_options(SocketOptions::new().source_address(Ipv6Addr::LOCALHOST.into()))
+            .build();
+        assert!(sender.send(&email).is_err());
+    }
+
+    #[test]
+    fn smtp_transport_resolver() {
+        let email = Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .subject("Happy new year")
+            .body(String::from("Be happy!"))
+            .unwrap();
+
+        let resolver =
+            StaticResolver::new().host("relay.example.test", [Ipv4Addr::LOCALHOST.into()]);
+        let sender = SmtpTransport::builder_dangerous("relay.example.test")
+            .port(2525)
+            .resolver(resolver.clone())
+            .build();
+        sender.send(&email).unwrap();
+
+        let sender = SmtpTransport::builder_dangerous("unknown.example.test")
+            .port(2525)
+            .resolver(resolver)
+            .build();
+        assert!(sender.send(&email).is_err());
+    }
 }
 
 #[cfg(test)]
 #[cfg(all(feature = "smtp-transport", feature = "builder", feature = "tokio1"))]
 mod tokio_1 {
-    use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+    use std::{
+        net::Ipv4Addr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::{Duration, Instant},
+    };
+
+    use lettre::{
+        transport::smtp::{client::SocketOptions, RateLimit, StaticResolver},
+        AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+    };
     use tokio1_crate as tokio;
 
     #[tokio::test]
@@ -42,6 +274,77 @@ mod tokio_1 {
                 .build();
         sender.send(email).await.unwrap();
     }
+
+    #[tokio::test]
+    async fn smtp_transport_rate_limit_tokio1() {
+        let email = Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .subject("Happy new year")
+            .body(String::from("Be happy!"))
+            .unwrap();
+
+        let sender: AsyncSmtpTransport<Tokio1Executor> =
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous("127.0.0.1")
+                .port(2525)
+                .rate_limit(RateLimit::new().messages_per_second(2))
+                .build();
+
+        let start = Instant::now();
+        for _ in 0..3 {
+            sender.send(email.clone()).await.unwrap();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(450));
+    }
+
+    #[tokio::test]
+    async fn smtp_transport_socket_options_tokio1() {
+        let email = Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .subject("Happy new year")
+            .body(String::from("Be happy!"))
+            .unwrap();
+
+        let configured = Arc::new(AtomicUsize::new(0));
+        let options = SocketOptions::new()
+            .source_address(Ipv4Addr::LOCALHOST.into())
+            .tcp_nodelay(true)
+            .configure({
+                let configured = Arc::clone(&configured);
+                move |socket| {
+                    assert!(socket.nodelay()?);
+                    configured.fetch_add(1, Ordering::SeqCst);
+                    Ok(())
+                }
+            });
+        let sender: AsyncSmtpTransport<Tokio1Executor> =
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous("127.0.0.1")
+                .port(2525)
+                .socket_options(options)
+                .build();
+        sender.send(email).await.unwrap();
+        assert_eq!(configured.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn smtp_transport_resolver_tokio1() {
+        let email = Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .subject("Happy new year")
+            .body(String::from("Be happy!"))
+            .unwrap();
+
+        let sender: AsyncSmtpTransport<Tokio1Executor> =
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous("relay.example.test")
+                .port(2525)
+                .resolver(
+                    StaticResolver::new().host("relay.example.test", [Ipv4Addr::LOCALHOST.into()]),
+                )
+                .build();
+        sender.send(email).await.unwrap();
+    }
 }
 
 #[cfg(test)]
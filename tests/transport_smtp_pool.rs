@@ -1,8 +1,21 @@
 #[cfg(all(test, feature = "smtp-transport", feature = "pool"))]
 mod sync {
-    use std::{sync::mpsc, thread};
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            mpsc, Arc,
+        },
+        thread,
+        time::Duration,
+    };
 
-    use lettre::{address::Envelope, SmtpTransport, Transport};
+    use lettre::{
+        address::Envelope,
+        transport::smtp::{CheckoutValidation, PoolConfig, PoolStats, RateLimit},
+        SmtpTransport, Transport,
+    };
 
     fn envelope() -> Envelope {
         Envelope::new(
@@ -56,4 +69,353 @@ mod sync {
             .send_raw(&envelope(), b"test")
             .expect("Send failed from main thread");
     }
+
+    #[test]
+    fn pool_stats() {
+        let mailer = SmtpTransport::builder_dangerous("127.0.0.1")
+            .port(2525)
+            .build();
+        assert_eq!(mailer.pool_stats(), PoolStats::default());
+
+        mailer.send_raw(&envelope(), b"test1").unwrap();
+        mailer.send_raw(&envelope(), b"test2").unwrap();
+
+        let stats = mailer.pool_stats();
+        assert_eq!(stats.idle(), 1);
+        assert_eq!(stats.in_use(), 0);
+        assert_eq!(stats.created(), 1);
+        assert_eq!(stats.recycled(), 2);
+        assert_eq!(stats.checkouts(), 2);
+        assert_eq!(stats.broken(), 0);
+        assert!(stats.max_wait() <= stats.total_wait());
+    }
+
+    #[test]
+    fn pool_stats_callback() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mailer = SmtpTransport::builder_dangerous("127.0.0.1")
+            .port(2525)
+            .pool_config(
+                PoolConfig::new()
+                    .idle_timeout(Duration::from_millis(50))
+                    .on_stats({
+                        let calls = Arc::clone(&calls);
+                        move |stats| {
+                            assert_eq!(stats.in_use(), 0);
+                            calls.fetch_add(1, Ordering::SeqCst);
+                        }
+                    }),
+            )
+            .build();
+
+        thread::sleep(Duration::from_millis(200));
+        assert!(calls.load(Ordering::SeqCst) >= 2);
+        drop(mailer);
+    }
+
+    #[test]
+    fn max_messages_per_connection() {
+        let mailer = SmtpTransport::builder_dangerous("127.0.0.1")
+            .port(2525)
+            .pool_config(PoolConfig::new().max_messages_per_connection(2))
+            .build();
+
+        for _ in 0..3 {
+            mailer.send_raw(&envelope(), b"test").unwrap();
+        }
+
+        let stats = mailer.pool_stats();
+        assert_eq!(stats.created(), 2);
+        assert_eq!(stats.dropped(), 1);
+        assert_eq!(stats.idle(), 1);
+    }
+
+    #[test]
+    fn max_lifetime() {
+        let mailer = SmtpTransport::builder_dangerous("127.0.0.1")
+            .port(2525)
+            .pool_config(PoolConfig::new().max_lifetime(Duration::ZERO))
+            .build();
+
+        mailer.send_raw(&envelope(), b"test").unwrap();
+        mailer.send_raw(&envelope(), b"test").unwrap();
+
+        let stats = mailer.pool_stats();
+        assert_eq!(stats.created(), 2);
+        assert_eq!(stats.idle(), 0);
+    }
+
+    /// Serves sessions accepting `max_messages` emails, and replying 421 to
+    /// the next one, after waiting `delay` before accepting each email
+    ///
+    /// Returns the port, and the number of `NOOP` received.
+    fn test_server(max_messages: Option<usize>, delay: Duration) -> (u16, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let noops = Arc::new(AtomicUsize::new(0));
+
+        let noops_ = Arc::clone(&noops);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let noops = Arc::clone(&noops_);
+                thread::spawn(move || {
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    stream.write_all(b"220 localhost ESMTP\r\n").unwrap();
+
+                    let mut sent = 0;
+                    let mut line = String::new();
+                    while reader.read_line(&mut line).unwrap_or(0) > 0 {
+                        let reply: &[u8] = match &line[..4] {
+                            "EHLO" => b"250 localhost\r\n",
+                            "MAIL" if Some(sent) == max_messages => b"421 too many messages\r\n",
+                            "DATA" => {
+                                stream.write_all(b"354 go ahead\r\n").unwrap();
+                                let mut content = String::new();
+                                while content != ".\r\n" {
+                                    content.clear();
+                                    reader.read_line(&mut content).unwrap();
+                                }
+                                thread::sleep(delay);
+                                sent += 1;
+                                b"250 queued\r\n"
+                            }
+                            "QUIT" => b"221 bye\r\n",
+                            "NOOP" => {
+                                noops.fetch_add(1, Ordering::SeqCst);
+                                b"250 ok\r\n"
+                            }
+                            _ => b"250 ok\r\n",
+                        };
+                        if stream.write_all(reply).is_err()
+                            || reply.starts_with(b"421")
+                            || reply.starts_with(b"221")
+                        {
+                            break;
+                        }
+                        line.clear();
+                    }
+                });
+            }
+        });
+
+        (port, noops)
+    }
+
+    #[test]
+    fn reconnect_stale_connection() {
+        let mailer = SmtpTransport::builder_dangerous("127.0.0.1")
+            .port(test_server(Some(1), Duration::ZERO).0)
+            .build();
+
+        mailer.send_raw(&envelope(), b"test1").unwrap();
+        mailer.send_raw(&envelope(), b"test2").unwrap();
+
+        let stats = mailer.pool_stats();
+        assert_eq!(stats.created(), 2);
+        assert_eq!(stats.broken(), 1);
+    }
+
+    #[test]
+    fn validation_never() {
+        let (port, noops) = test_server(Some(1), Duration::ZERO);
+        let mailer = SmtpTransport::builder_dangerous("127.0.0.1")
+            .port(port)
+            .pool_config(PoolConfig::new().validation(CheckoutValidation::Never))
+            .build();
+
+        mailer.send_raw(&envelope(), b"test1").unwrap();
+        mailer.send_raw(&envelope(), b"test2").unwrap();
+        assert_eq!(noops.load(Ordering::SeqCst), 0);
+        assert_eq!(mailer.pool_stats().broken(), 1);
+    }
+
+    #[test]
+    fn validation_idle_longer_than() {
+        let (port, noops) = test_server(None, Duration::ZERO);
+        let mailer = SmtpTransport::builder_dangerous("127.0.0.1")
+            .port(port)
+            .pool_config(
+                PoolConfig::new().validation(CheckoutValidation::IdleLongerThan(
+                    Duration::from_millis(100),
+                )),
+            )
+            .build();
+
+        mailer.send_raw(&envelope(), b"test1").unwrap();
+        mailer.send_raw(&envelope(), b"test2").unwrap();
+        assert_eq!(noops.load(Ordering::SeqCst), 0);
+
+        thread::sleep(Duration::from_millis(150));
+        mailer.send_raw(&envelope(), b"test3").unwrap();
+        assert_eq!(noops.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn keepalive() {
+        let (port, noops) = test_server(None, Duration::ZERO);
+        let mailer = SmtpTransport::builder_dangerous("127.0.0.1")
+            .port(port)
+            .pool_config(
+                PoolConfig::new()
+                    .validation(CheckoutValidation::Never)
+                    .keepalive_interval(Duration::from_millis(50)),
+            )
+            .build();
+
+        mailer.send_raw(&envelope(), b"test").unwrap();
+        thread::sleep(Duration::from_millis(300));
+        assert!(noops.load(Ordering::SeqCst) >= 2);
+        assert_eq!(mailer.pool_stats().idle(), 1);
+    }
+
+    #[test]
+    fn max_size() {
+        let mailer = SmtpTransport::builder_dangerous("127.0.0.1")
+            .port(test_server(None, Duration::from_millis(100)).0)
+            .pool_config(PoolConfig::new().max_size(2))
+            .build();
+
+        let threads = (0..6)
+            .map(|_| {
+                let mailer = mailer.clone();
+                thread::spawn(move || mailer.send_raw(&envelope(), b"test").unwrap())
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let stats = mailer.pool_stats();
+        assert_eq!(stats.created(), 2);
+        assert_eq!(stats.checkouts(), 6);
+        assert!(stats.max_wait() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn acquire_timeout() {
+        let mailer = SmtpTransport::builder_dangerous("127.0.0.1")
+            .port(test_server(None, Duration::from_millis(300)).0)
+            .pool_config(
+                PoolConfig::new()
+                    .max_size(1)
+                    .acquire_timeout(Some(Duration::from_millis(50))),
+            )
+            .build();
+
+        let mailer_ = mailer.clone();
+        let thread = thread::spawn(move || mailer_.send_raw(&envelope(), b"test1"));
+        while mailer.pool_stats().in_use() == 0 {
+            thread::yield_now();
+        }
+
+        let error = mailer.send_raw(&envelope(), b"test2").unwrap_err();
+        assert!(error.is_pool_timeout());
+        thread.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn warm_up_and_shutdown() {
+        let mailer = SmtpTransport::builder_dangerous("127.0.0.1")
+            .port(2525)
+            .pool_config(PoolConfig::new().min_idle(3))
+            .build();
+
+        mailer.warm_up().unwrap();
+        assert_eq!(mailer.pool_stats().idle(), 3);
+        assert_eq!(mailer.pool_stats().created(), 3);
+        mailer.send_raw(&envelope(), b"test").unwrap();
+
+        mailer.shutdown().unwrap();
+        assert_eq!(mailer.pool_stats().idle(), 0);
+        assert!(mailer.send_raw(&envelope(), b"test").is_err());
+        assert!(mailer.warm_up().is_err());
+    }
+
+    #[test]
+    fn warm_up_error() {
+        let mailer = SmtpTransport::builder_dangerous("127.0.0.1")
+            .port(9)
+            .pool_config(PoolConfig::new().min_idle(1))
+            .build();
+
+        assert!(mailer.warm_up().is_err());
+    }
+
+    #[test]
+    fn rate_limit_max_connections() {
+        let mailer = SmtpTransport::builder_dangerous("127.0.0.1")
+            .port(2525)
+            .pool_config(PoolConfig::new().min_idle(3))
+            .rate_limit(RateLimit::new().max_connections(2))
+            .build();
+
+        // Idle connections count towards the limit
+        mailer.warm_up().unwrap();
+        assert_eq!(mailer.pool_stats().idle(), 2);
+        mailer.send_raw(&envelope(), b"test").unwrap();
+        assert_eq!(mailer.pool_stats().created(), 2);
+    }
+}
+
+#[cfg(all(test, feature = "smtp-transport", feature = "pool", feature = "tokio1"))]
+mod tokio_1 {
+    use std::time::Duration;
+
+    use lettre::{
+        address::Envelope, transport::smtp::PoolConfig, AsyncSmtpTransport, AsyncTransport,
+        Tokio1Executor,
+    };
+    use tokio1_crate as tokio;
+
+    fn envelope() -> Envelope {
+        Envelope::new(
+            Some("user@localhost".parse().unwrap()),
+            vec!["root@localhost".parse().unwrap()],
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn warm_up_and_close() {
+        let mailer = AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous("127.0.0.1")
+            .port(2525)
+            .pool_config(PoolConfig::new().min_idle(2))
+            .build::<Tokio1Executor>();
+
+        mailer.warm_up().await.unwrap();
+        assert_eq!(mailer.pool_stats().await.idle(), 2);
+
+        mailer.close().await.unwrap();
+        assert_eq!(mailer.pool_stats().await.idle(), 0);
+        assert!(mailer.send_raw(&envelope(), b"test").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn max_size() {
+        let mailer = AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous("127.0.0.1")
+            .port(2525)
+            .pool_config(
+                PoolConfig::new()
+                    .max_size(1)
+                    .acquire_timeout(Some(Duration::from_secs(5))),
+            )
+            .build::<Tokio1Executor>();
+
+        let envelope = envelope();
+        let results = tokio::join!(
+            mailer.send_raw(&envelope, b"test1"),
+            mailer.send_raw(&envelope, b"test2"),
+            mailer.send_raw(&envelope, b"test3"),
+            mailer.send_raw(&envelope, b"test4"),
+        );
+        results.0.unwrap();
+        results.1.unwrap();
+        results.2.unwrap();
+        results.3.unwrap();
+
+        let stats = mailer.pool_stats().await;
+        assert_eq!(stats.created(), 1);
+        assert_eq!(stats.checkouts(), 4);
+    }
 }